@@ -0,0 +1,268 @@
+use futures_util::stream;
+use futures_util::StreamExt;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use crate::config::{RemoteConfig, RemotePath};
+use crate::listing::spawn_listing;
+use crate::store::{
+    ObjectInfo, RestoreStatus, RestoreTier, S3Store, StoreError, StorePath, MAX_COPY_SIZE,
+};
+use crate::transfer::{Progress, TransferEvent, TransferOptions, TransferSummary};
+
+/// Storage classes whose objects must be restored before being read
+const ARCHIVED_CLASSES: [&str; 2] = ["GLACIER", "DEEP_ARCHIVE"];
+
+/// Settings of `restore_objects`, on top of the `TransferOptions`.
+#[derive(Debug, Clone)]
+pub struct RestoreOptions {
+    /// Days the restored copies stay readable
+    pub days: u32,
+    pub tier: RestoreTier,
+    /// Return once every restore is done, rather than once asked for
+    pub wait: bool,
+    /// Time between two checks of the restores waited for
+    pub interval: Duration,
+}
+
+impl Default for RestoreOptions {
+    fn default() -> Self {
+        RestoreOptions {
+            days: 7,
+            tier: RestoreTier::default(),
+            wait: false,
+            interval: Duration::from_secs(60),
+        }
+    }
+}
+
+pub(crate) fn is_archived(storage_class: Option<&str>) -> bool {
+    storage_class.is_some_and(|storage_class| ARCHIVED_CLASSES.contains(&storage_class))
+}
+
+/// `error` of reading an object, which is expected if it is archived.
+pub(crate) fn read_error(storage_class: Option<&str>, error: impl fmt::Display) -> String {
+    match storage_class {
+        Some(storage_class) if is_archived(Some(storage_class)) => format!(
+            "{} (archived in {}, restore it first)",
+            error, storage_class
+        ),
+        _ => error.to_string(),
+    }
+}
+
+/// Rewrites the objects under `location` with `storage_class`, skipping
+/// those already in it.
+pub async fn transition_objects(
+    location: &RemotePath,
+    remote: &RemoteConfig,
+    storage_class: &str,
+    options: &TransferOptions,
+) -> Result<TransferSummary, Box<dyn std::error::Error>> {
+    let concurrency = options.concurrency(remote.concurrency, 10);
+    let throttle = options.throttle(concurrency, remote.max_requests_per_second);
+    let store = S3Store::connect(remote, &location.bucket, throttle)?.with_sse(options.sse.clone());
+
+    transition_from(
+        &StorePath::new(store, location.key_prefix()),
+        storage_class,
+        &TransferOptions {
+            concurrency: Some(concurrency),
+            ..options.clone()
+        },
+    )
+    .await
+}
+
+/// Rewrites the objects under `location` with `storage_class` by copying
+/// them onto themselves, with their own encryption unless the store sets
+/// one. Versioned buckets keep the previous version in its former storage
+/// class.
+pub async fn transition_from(
+    location: &StorePath,
+    storage_class: &str,
+    options: &TransferOptions,
+) -> Result<TransferSummary, Box<dyn std::error::Error>> {
+    if options.manifest.is_some() {
+        return Err("Storage classes are changed for a prefix, not a manifest".into());
+    }
+    let concurrency = options.concurrency.unwrap_or(10);
+    let progress = Progress::new(options.events.clone());
+
+    list(location, concurrency, &progress)
+        .for_each_concurrent(concurrency, |info| {
+            let progress = progress.clone();
+            async move {
+                let ObjectInfo {
+                    key,
+                    size,
+                    storage_class: current,
+                    ..
+                } = info;
+                if current.as_deref().unwrap_or("STANDARD") == storage_class {
+                    progress.emit(TransferEvent::UpToDate { key });
+                } else if size > MAX_COPY_SIZE {
+                    progress.emit(TransferEvent::Skipped {
+                        key,
+                        reason: "larger than 5 GiB, which cannot be copied in place".to_owned(),
+                    });
+                } else {
+                    let result = match location.store.head(&key).await {
+                        Ok(Some(head)) => {
+                            location
+                                .store
+                                .set_storage_class(&key, storage_class, &head)
+                                .await
+                        }
+                        Ok(None) => Err(StoreError::NotFound(key.clone())),
+                        Err(e) => Err(e),
+                    };
+                    match result {
+                        Ok(_) => progress.emit(TransferEvent::StorageClassChanged {
+                            key,
+                            storage_class: storage_class.to_owned(),
+                        }),
+                        Err(e) => progress.fail(&key, read_error(current.as_deref(), e)),
+                    }
+                }
+            }
+        })
+        .await;
+
+    Ok(progress.summary())
+}
+
+/// Asks for the archived objects under `location` to be restored.
+pub async fn restore_objects(
+    location: &RemotePath,
+    remote: &RemoteConfig,
+    restore: &RestoreOptions,
+    options: &TransferOptions,
+) -> Result<TransferSummary, Box<dyn std::error::Error>> {
+    let concurrency = options.concurrency(remote.concurrency, 10);
+    let throttle = options.throttle(concurrency, remote.max_requests_per_second);
+    let store =
+        S3Store::connect(remote, &location.bucket, throttle)?.with_sse(options.source_sse.clone());
+
+    restore_from(
+        &StorePath::new(store, location.key_prefix()),
+        restore,
+        &TransferOptions {
+            concurrency: Some(concurrency),
+            ..options.clone()
+        },
+    )
+    .await
+}
+
+/// Asks for the archived objects under `location` to be restored, unless
+/// they already are or are being restored, then with `wait` checks on them
+/// until every restore is done or Ctrl-C is pressed.
+///
+/// Objects that are not archived are left alone.
+pub async fn restore_from(
+    location: &StorePath,
+    restore: &RestoreOptions,
+    options: &TransferOptions,
+) -> Result<TransferSummary, Box<dyn std::error::Error>> {
+    if options.manifest.is_some() {
+        return Err("Objects are restored for a prefix, not a manifest".into());
+    }
+    let concurrency = options.concurrency.unwrap_or(10);
+    let progress = Progress::new(options.events.clone());
+    let pending = Mutex::new(Vec::new());
+
+    list(location, concurrency, &progress)
+        .for_each_concurrent(concurrency, |info| {
+            let progress = progress.clone();
+            let pending = &pending;
+            async move {
+                let key = info.key;
+                if !is_archived(info.storage_class.as_deref()) {
+                    progress.emit(TransferEvent::UpToDate { key });
+                    return;
+                }
+                match location.store.head(&key).await {
+                    Ok(head) => match head.and_then(|head| head.restore) {
+                        Some(RestoreStatus::Restored { expiry }) => {
+                            progress.emit(TransferEvent::Restored { key, expiry })
+                        }
+                        Some(RestoreStatus::InProgress) => {
+                            progress.emit(TransferEvent::RestoreInProgress { key: key.clone() });
+                            pending.lock().unwrap().push(key);
+                        }
+                        None => match location
+                            .store
+                            .restore(&key, restore.days, restore.tier)
+                            .await
+                        {
+                            Ok(_) => {
+                                progress.emit(TransferEvent::RestoreRequested { key: key.clone() });
+                                pending.lock().unwrap().push(key);
+                            }
+                            Err(e) => progress.fail(&key, e),
+                        },
+                    },
+                    Err(e) => progress.fail(&key, e),
+                }
+            }
+        })
+        .await;
+
+    let mut pending = pending.into_inner().unwrap();
+    if !restore.wait {
+        return Ok(progress.summary());
+    }
+
+    let mut interrupted = tokio::spawn(tokio::signal::ctrl_c());
+    while !pending.is_empty() {
+        tokio::select! {
+            _ = &mut interrupted => {
+                for key in pending {
+                    progress.fail(&key, "interrupted before being restored");
+                }
+                break;
+            }
+            _ = tokio::time::sleep(restore.interval) => {}
+        }
+
+        let statuses = stream::iter(std::mem::take(&mut pending))
+            .map(|key| async move {
+                let status = location.store.head(&key).await;
+                (key, status)
+            })
+            .buffer_unordered(concurrency)
+            .collect::<Vec<_>>()
+            .await;
+        for (key, status) in statuses {
+            match status.map(|head| head.and_then(|head| head.restore)) {
+                // Counted already, when it was asked for
+                Ok(Some(RestoreStatus::Restored { expiry })) => {
+                    options.events.emit(TransferEvent::Restored { key, expiry })
+                }
+                Ok(Some(RestoreStatus::InProgress)) => pending.push(key),
+                Ok(None) => progress.fail(&key, "no longer being restored"),
+                Err(e) => progress.fail(&key, e),
+            }
+        }
+    }
+
+    Ok(progress.summary())
+}
+
+fn list(
+    location: &StorePath,
+    concurrency: usize,
+    progress: &Progress,
+) -> tokio_stream::wrappers::ReceiverStream<ObjectInfo> {
+    let (tx, rx) = mpsc::channel(concurrency);
+    spawn_listing(
+        location.store.clone(),
+        location.prefix.clone(),
+        progress.clone(),
+        tx,
+    );
+    tokio_stream::wrappers::ReceiverStream::new(rx)
+}
@@ -1,40 +1,57 @@
 use clap::Parser;
-use futures_util::StreamExt;
-use rusoto_core::Region;
-use rusoto_credential::StaticProvider;
-use rusoto_s3::{GetObjectRequest, PutObjectRequest, S3Client, S3};
-use tokio::fs::File;
-use tokio::io::AsyncReadExt;
-use tokio::sync::mpsc;
+use std::path::PathBuf;
+
+use s3_utils::client::Provider;
+use s3_utils::config::{resolve_location, static_credentials};
+use s3_utils::filter::{parse_age, TimeFilter};
+use s3_utils::keys::{KeyCase, KeyRules};
+use s3_utils::sse::{ServerSideEncryption, SseOptions};
+use s3_utils::tags::tags_from_args;
+use s3_utils::{
+    copy_bucket_config, copy_object_versions, copy_objects, create_bucket, parse_time,
+    BucketSetting, Config, Manifest, RemoteConfig, SettingOutcome, TransferOptions,
+    TransferSummary,
+};
+
+use crate::console::{print_bucket_config, print_events, print_summary};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
     pub b2b: Option<String>,
 
+    /// Origin location, `[remote:]bucket[/prefix]`
+    pub origin: Option<String>,
+
+    /// Target location, `[remote:]bucket[/prefix]`
+    pub target: Option<String>,
+
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
     #[arg(long)]
-    pub origin_bucket: String,
+    pub origin_bucket: Option<String>,
 
     #[arg(long)]
-    pub target_bucket: String,
+    pub target_bucket: Option<String>,
 
     #[arg(long)]
-    pub origin_region: String,
+    pub origin_region: Option<String>,
 
     #[arg(long)]
-    pub target_region: String,
+    pub target_region: Option<String>,
 
     #[arg(long)]
-    pub origin_aws_access_key_id: String,
+    pub origin_aws_access_key_id: Option<String>,
 
     #[arg(long)]
-    pub target_aws_access_key_id: String,
+    pub target_aws_access_key_id: Option<String>,
 
     #[arg(long)]
-    pub origin_aws_secret_access_key: String,
+    pub origin_aws_secret_access_key: Option<String>,
 
     #[arg(long)]
-    pub target_aws_secret_access_key: String,
+    pub target_aws_secret_access_key: Option<String>,
 
     #[arg(long)]
     pub origin_endpoint: Option<String>,
@@ -42,157 +59,240 @@ pub struct Args {
     #[arg(long)]
     pub target_endpoint: Option<String>,
 
+    #[arg(long, value_enum)]
+    pub origin_provider: Option<Provider>,
+
+    #[arg(long)]
+    pub origin_account_id: Option<String>,
+
+    #[arg(long)]
+    pub origin_force_path_style: bool,
+
+    #[arg(long)]
+    pub origin_use_http: bool,
+
+    #[arg(long)]
+    pub origin_insecure: bool,
+
+    #[arg(long)]
+    pub origin_ca_bundle: Option<PathBuf>,
+
+    #[arg(long, value_enum)]
+    pub target_provider: Option<Provider>,
+
+    #[arg(long)]
+    pub target_account_id: Option<String>,
+
+    #[arg(long)]
+    pub target_force_path_style: bool,
+
+    #[arg(long)]
+    pub target_use_http: bool,
+
+    #[arg(long)]
+    pub target_insecure: bool,
+
+    #[arg(long)]
+    pub target_ca_bundle: Option<PathBuf>,
+
     #[arg(long)]
     pub concurrency: Option<usize>,
+
+    #[arg(long)]
+    pub max_requests_per_second: Option<f64>,
+
+    /// Remove this directory prefix from the keys starting with it
+    #[arg(long)]
+    pub strip_prefix: Option<String>,
+
+    /// Rename keys with `s/pattern/replacement/[gi]`, can be repeated
+    #[arg(long)]
+    pub rename: Vec<String>,
+
+    #[arg(long, value_enum)]
+    pub key_case: Option<KeyCase>,
+
+    /// Add this directory prefix to every key
+    #[arg(long)]
+    pub target_prefix: Option<String>,
+
+    /// Base64 encoded 256-bit SSE-C key of the origin objects
+    #[arg(long)]
+    pub origin_sse_customer_key: Option<String>,
+
+    #[arg(long, value_enum)]
+    pub target_sse: Option<ServerSideEncryption>,
+
+    #[arg(long)]
+    pub target_sse_kms_key_id: Option<String>,
+
+    /// Base64 encoded 256-bit SSE-C key
+    #[arg(long)]
+    pub target_sse_customer_key: Option<String>,
+
+    /// Only copy the objects of this file: one key per line, or `.csv` and
+    /// `.jsonl` files with `key`, `version_id` and `target_key` fields
+    #[arg(long)]
+    pub from_manifest: Option<PathBuf>,
+
+    /// Only copy the objects tagged `key=value`, can be repeated
+    #[arg(long)]
+    pub where_tag: Vec<String>,
+
+    /// Only copy the objects last modified at or after this time, e.g.
+    /// `2024-05-01T12:00:00Z`, or a date for its midnight UTC
+    #[arg(long, value_parser = parse_time)]
+    pub modified_after: Option<chrono::DateTime<chrono::Utc>>,
+
+    /// Only copy the objects last modified before this time
+    #[arg(long, value_parser = parse_time)]
+    pub modified_before: Option<chrono::DateTime<chrono::Utc>>,
+
+    /// Only copy the objects last modified within this age, e.g. `7d`,
+    /// in `s`, `m`, `h`, `d` or `w`
+    #[arg(long, value_parser = parse_age)]
+    pub newer_than: Option<chrono::Duration>,
+
+    /// Only copy the objects last modified longer ago than this age,
+    /// e.g. `30d`
+    #[arg(long, value_parser = parse_age)]
+    pub older_than: Option<chrono::Duration>,
+
+    /// Create the target bucket if missing, and copy the versioning, default
+    /// encryption, CORS, lifecycle rules, policy, tags and website of the
+    /// origin bucket to it
+    #[arg(long)]
+    pub copy_bucket_config: bool,
+
+    /// Copy every version and delete marker, oldest first, to a target with
+    /// versioning enabled
+    #[arg(long, requires = "version_mapping")]
+    pub all_versions: bool,
+
+    /// JSON lines file recording the target version id of each version
+    /// copied, appended to and used to resume with `--all-versions`
+    #[arg(long, requires = "all_versions")]
+    pub version_mapping: Option<PathBuf>,
 }
 
-pub async fn bucket_to_bucket() -> Result<(), Box<dyn std::error::Error>> {
+pub async fn bucket_to_bucket() -> Result<TransferSummary, Box<dyn std::error::Error>> {
     // Read command-line arguments
     let args = Args::parse();
-    let concurrency = args.concurrency.unwrap_or(50);
-    let origin_bucket = args.origin_bucket;
-    let target_bucket = args.target_bucket;
-    let origin_endpoint = args
-        .origin_endpoint
-        .unwrap_or(format!("s3.{}.amazonaws.com", args.origin_region.clone()));
-    let target_endpoint = args
-        .target_endpoint
-        .unwrap_or(format!("s3.{}.amazonaws.com", args.target_region.clone()));
-
-    // Create AWS credentials provider
-    let origin_credentials_provider = StaticProvider::new_minimal(
-        args.origin_aws_access_key_id,
-        args.origin_aws_secret_access_key,
-    );
-
-    let target_credentials_provider = StaticProvider::new_minimal(
-        args.target_aws_access_key_id,
-        args.target_aws_secret_access_key,
-    );
-
-    // Create S3 clients for origin and target regions
-    let origin_client = S3Client::new_with(
-        rusoto_core::HttpClient::new().expect("Failed to create HTTP client"),
-        origin_credentials_provider,
-        Region::Custom {
-            name: args.origin_region.clone(),
-            endpoint: origin_endpoint,
-        },
-    );
-
-    let target_client = S3Client::new_with(
-        rusoto_core::HttpClient::new().expect("Failed to create HTTP client"),
-        target_credentials_provider,
-        Region::Custom {
-            name: args.target_region.clone(),
-            endpoint: target_endpoint,
-        },
-    );
-
-    let (tx, rx) = mpsc::channel(concurrency);
-
-    let result = origin_client
-        .list_objects_v2(rusoto_s3::ListObjectsV2Request {
-            bucket: origin_bucket.to_string(),
-            ..Default::default()
-        })
-        .await;
-
-    let next_continuation_token = match result {
-        Ok(output) => output.next_continuation_token,
-        Err(_) => None,
+    let config = Config::load(args.config.as_deref())?;
+    let origin = resolve_location(
+        args.origin.as_deref(),
+        args.origin_bucket,
+        "--origin-bucket",
+    )?;
+    let target = resolve_location(
+        args.target.as_deref(),
+        args.target_bucket,
+        "--target-bucket",
+    )?;
+    let origin_remote = RemoteConfig {
+        provider: args.origin_provider,
+        endpoint: args.origin_endpoint,
+        region: args.origin_region,
+        account_id: args.origin_account_id,
+        credentials: static_credentials(
+            args.origin_aws_access_key_id,
+            args.origin_aws_secret_access_key,
+        ),
+        force_path_style: args.origin_force_path_style.then_some(true),
+        use_http: args.origin_use_http.then_some(true),
+        insecure: args.origin_insecure.then_some(true),
+        ca_bundle: args.origin_ca_bundle,
+        ..Default::default()
+    }
+    .or(config.remote_for(&origin)?);
+    let target_remote = RemoteConfig {
+        provider: args.target_provider,
+        endpoint: args.target_endpoint,
+        region: args.target_region,
+        account_id: args.target_account_id,
+        credentials: static_credentials(
+            args.target_aws_access_key_id,
+            args.target_aws_secret_access_key,
+        ),
+        force_path_style: args.target_force_path_style.then_some(true),
+        use_http: args.target_use_http.then_some(true),
+        insecure: args.target_insecure.then_some(true),
+        ca_bundle: args.target_ca_bundle,
+        ..Default::default()
+    }
+    .or(config.remote_for(&target)?);
+
+    let options = TransferOptions {
+        concurrency: args.concurrency,
+        max_requests_per_second: args.max_requests_per_second,
+        source_sse: SseOptions::customer_key(args.origin_sse_customer_key.as_deref())?,
+        sse: SseOptions::new(
+            args.target_sse,
+            args.target_sse_kms_key_id,
+            args.target_sse_customer_key.as_deref(),
+        )?,
+        keys: KeyRules::from_args(
+            args.strip_prefix,
+            &args.rename,
+            args.key_case,
+            args.target_prefix,
+        )?,
+        manifest: args
+            .from_manifest
+            .as_deref()
+            .map(Manifest::read)
+            .transpose()?,
+        where_tags: tags_from_args(&args.where_tag, "--where-tag")?,
+        modified: TimeFilter::from_args(
+            args.modified_after,
+            args.modified_before,
+            args.newer_than,
+            args.older_than,
+        )?,
+        events: print_events(),
+        ..Default::default()
     };
 
-    tokio::spawn({
-        let origin_client = origin_client.clone();
-        let origin_bucket = origin_bucket.clone();
-        let mut next_continuation_token = next_continuation_token.clone();
-
-        async move {
-            while next_continuation_token.is_some() {
-                let result = origin_client
-                    .list_objects_v2(rusoto_s3::ListObjectsV2Request {
-                        bucket: origin_bucket.to_string(),
-                        continuation_token: next_continuation_token.clone(),
-                        ..Default::default()
-                    })
-                    .await;
-
-                next_continuation_token = match result {
-                    Ok(output) => {
-                        if let Some(objects) = output.contents {
-                            for object in objects {
-                                tx.send(object.key.unwrap()).await.unwrap();
-                            }
-                        }
-
-                        output.next_continuation_token
-                    }
-                    Err(_) => None,
-                }
-            }
+    // Settings that shape the objects go before them, the others after
+    let (before, after): (Vec<_>, Vec<_>) = BucketSetting::ALL
+        .into_iter()
+        .partition(BucketSetting::applies_to_objects);
+    let mut outcomes = Vec::new();
+    if args.copy_bucket_config {
+        if create_bucket(&target, &target_remote).await? {
+            println!("Bucket created: {}", target.bucket);
+        }
+        let copied =
+            copy_bucket_config(&origin, &origin_remote, &target, &target_remote, &before).await?;
+        print_bucket_config(&copied);
+        outcomes.extend(copied);
+    }
+
+    let mut summary = match &args.version_mapping {
+        Some(mapping) => {
+            copy_object_versions(
+                &origin,
+                &origin_remote,
+                &target,
+                &target_remote,
+                mapping,
+                &options,
+            )
+            .await?
         }
-    });
-
-    tokio_stream::wrappers::ReceiverStream::new(rx)
-        .for_each_concurrent(concurrency, |object_key| {
-            let object_key = object_key.clone();
-            let origin_client = origin_client.clone();
-            let origin_bucket = origin_bucket.clone();
-            let target_client = target_client.clone();
-            let target_bucket = target_bucket.clone();
-
-            async move {
-                let download_request = GetObjectRequest {
-                    bucket: origin_bucket.to_owned(),
-                    key: object_key.clone(),
-                    ..Default::default()
-                };
-
-                // Send the request and download the file
-                let object = origin_client.get_object(download_request).await.ok();
-
-                if object.is_some() {
-                    let file_path = format!("{}", object_key);
-                    let mut file = File::create(file_path.clone()).await.unwrap();
-
-                    // Read the object data and write it to the file
-                    let stream = object.unwrap().body.unwrap();
-                    tokio::io::copy(&mut stream.into_async_read(), &mut file)
-                        .await
-                        .expect("Failed to download file");
-
-                    // Upload the file to the target bucket
-                    let mut file = File::open(file_path.clone()).await.unwrap();
-                    let mut vec = Vec::new();
-                    let _ = file.read_to_end(&mut vec).await;
-
-                    let put_request = PutObjectRequest {
-                        bucket: target_bucket.to_owned(),
-                        key: object_key.to_owned(),
-                        body: Some(vec.into()),
-                        ..Default::default()
-                    };
-
-                    match target_client.put_object(put_request).await {
-                        Ok(_) => {}
-                        Err(e) => {
-                            println!("Error uploading file {}: {:?}", file_path, e);
-                        }
-                    }
-
-                    match tokio::fs::remove_file(file_path.clone()).await {
-                        Ok(_) => {
-                            println!("File sync: {}", file_path);
-                        }
-                        Err(e) => {
-                            println!("Error deleting file {}: {:?}", file_path, e);
-                        }
-                    }
-                }
-            }
-        })
-        .await;
-
-    return Ok(());
+        None => copy_objects(&origin, &origin_remote, &target, &target_remote, &options).await?,
+    };
+    if args.copy_bucket_config {
+        let copied =
+            copy_bucket_config(&origin, &origin_remote, &target, &target_remote, &after).await?;
+        print_bucket_config(&copied);
+        outcomes.extend(copied);
+    }
+    summary.failed += outcomes
+        .iter()
+        .filter(|(_, outcome)| matches!(outcome, SettingOutcome::Failed(_)))
+        .count() as u64;
+    print_summary(&summary);
+
+    Ok(summary)
 }
@@ -0,0 +1,449 @@
+use clap::ValueEnum;
+use rusoto_core::RusotoError;
+use rusoto_s3::{
+    BucketLifecycleConfiguration, CORSConfiguration, CreateBucketConfiguration,
+    CreateBucketRequest, GetBucketCorsRequest, GetBucketEncryptionRequest,
+    GetBucketLifecycleConfigurationRequest, GetBucketPolicyRequest, GetBucketTaggingRequest,
+    GetBucketVersioningRequest, GetBucketWebsiteRequest, HeadBucketError, HeadBucketRequest,
+    PutBucketCorsRequest, PutBucketEncryptionRequest, PutBucketLifecycleConfigurationRequest,
+    PutBucketPolicyRequest, PutBucketTaggingRequest, PutBucketVersioningRequest,
+    PutBucketWebsiteRequest, Tagging, VersioningConfiguration, WebsiteConfiguration, S3,
+};
+use std::fmt;
+
+use crate::config::{RemoteConfig, RemotePath};
+use crate::store::{S3Store, StoreError};
+use crate::throttle::Throttle;
+
+/// Error codes of a bucket without the setting asked for
+const NOT_SET_CODES: [&str; 6] = [
+    "NoSuchCORSConfiguration",
+    "NoSuchLifecycleConfiguration",
+    "NoSuchBucketPolicy",
+    "ServerSideEncryptionConfigurationNotFoundError",
+    "NoSuchTagSet",
+    "NoSuchWebsiteConfiguration",
+];
+
+/// Bucket-level settings `copy_bucket_config` can copy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BucketSetting {
+    Versioning,
+    Encryption,
+    Cors,
+    Lifecycle,
+    Policy,
+    Tagging,
+    Website,
+}
+
+impl BucketSetting {
+    pub const ALL: [BucketSetting; 7] = [
+        BucketSetting::Versioning,
+        BucketSetting::Encryption,
+        BucketSetting::Cors,
+        BucketSetting::Lifecycle,
+        BucketSetting::Policy,
+        BucketSetting::Tagging,
+        BucketSetting::Website,
+    ];
+
+    /// Whether the setting shapes the objects written to the bucket, and so
+    /// is to be copied before them. A policy or lifecycle rules could refuse
+    /// or expire the objects being copied, so they go last.
+    pub fn applies_to_objects(&self) -> bool {
+        matches!(self, BucketSetting::Versioning | BucketSetting::Encryption)
+    }
+}
+
+impl fmt::Display for BucketSetting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            BucketSetting::Versioning => "versioning",
+            BucketSetting::Encryption => "default encryption",
+            BucketSetting::Cors => "CORS",
+            BucketSetting::Lifecycle => "lifecycle rules",
+            BucketSetting::Policy => "bucket policy",
+            BucketSetting::Tagging => "bucket tags",
+            BucketSetting::Website => "website",
+        };
+        f.write_str(name)
+    }
+}
+
+/// What became of a bucket setting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SettingOutcome {
+    Copied,
+    /// The origin bucket does not have it
+    NotSet,
+    /// The provider of the origin or target bucket does not handle it
+    Unsupported(String),
+    Failed(String),
+}
+
+/// Why reading or writing a setting did not succeed.
+enum Failure {
+    Missing,
+    Unsupported,
+    Other(String),
+}
+
+/// Creates the bucket of `location` unless it exists, and returns whether
+/// it did.
+pub async fn create_bucket(
+    location: &RemotePath,
+    remote: &RemoteConfig,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let store = S3Store::connect(remote, &location.bucket, throttle(remote))?;
+    let head_bucket_request = HeadBucketRequest {
+        bucket: location.bucket.clone(),
+        ..Default::default()
+    };
+    match store
+        .throttle()
+        .run(|| store.client().head_bucket(head_bucket_request.clone()))
+        .await
+    {
+        Ok(_) => return Ok(false),
+        Err(RusotoError::Service(HeadBucketError::NoSuchBucket(_))) => {}
+        Err(RusotoError::Unknown(response)) if response.status.as_u16() == 404 => {}
+        Err(e) => {
+            return Err(format!(
+                "Could not check bucket {}: {}",
+                location.bucket,
+                StoreError::from(e)
+            )
+            .into())
+        }
+    }
+
+    // us-east-1 is where buckets go without a constraint, and refuses one
+    let region = remote.client_options()?.region;
+    let create_bucket_request = CreateBucketRequest {
+        bucket: location.bucket.clone(),
+        create_bucket_configuration: (region != "us-east-1").then_some(CreateBucketConfiguration {
+            location_constraint: Some(region),
+        }),
+        ..Default::default()
+    };
+    store
+        .throttle()
+        .run(|| store.client().create_bucket(create_bucket_request.clone()))
+        .await
+        .map_err(|e| {
+            format!(
+                "Could not create bucket {}: {}",
+                location.bucket,
+                StoreError::from(e)
+            )
+        })?;
+    Ok(true)
+}
+
+/// Copies the `settings` of the origin bucket to the target bucket, one at
+/// a time, and reports what became of each. The origin bucket name in the
+/// resources of the policy is replaced with the target one.
+pub async fn copy_bucket_config(
+    origin: &RemotePath,
+    origin_remote: &RemoteConfig,
+    target: &RemotePath,
+    target_remote: &RemoteConfig,
+    settings: &[BucketSetting],
+) -> Result<Vec<(BucketSetting, SettingOutcome)>, Box<dyn std::error::Error>> {
+    let origin = S3Store::connect(origin_remote, &origin.bucket, throttle(origin_remote))?;
+    let target = S3Store::connect(target_remote, &target.bucket, throttle(target_remote))?;
+
+    let mut outcomes = Vec::new();
+    for setting in settings {
+        outcomes.push((*setting, copy_setting(*setting, &origin, &target).await));
+    }
+    Ok(outcomes)
+}
+
+async fn copy_setting(
+    setting: BucketSetting,
+    origin: &S3Store,
+    target: &S3Store,
+) -> SettingOutcome {
+    let origin_bucket = origin.bucket().to_owned();
+    let target_bucket = target.bucket().to_owned();
+    match setting {
+        BucketSetting::Versioning => {
+            let request = GetBucketVersioningRequest {
+                bucket: origin_bucket,
+                ..Default::default()
+            };
+            let status = match origin
+                .throttle()
+                .run(|| origin.client().get_bucket_versioning(request.clone()))
+                .await
+            {
+                Ok(output) => output.status,
+                Err(e) => return read_failure(e),
+            };
+            // Never enabled, which is how a new bucket starts
+            let Some(status) = status else {
+                return SettingOutcome::NotSet;
+            };
+            let request = PutBucketVersioningRequest {
+                bucket: target_bucket,
+                versioning_configuration: VersioningConfiguration {
+                    status: Some(status),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            written(
+                target
+                    .throttle()
+                    .run(|| target.client().put_bucket_versioning(request.clone()))
+                    .await,
+            )
+        }
+        BucketSetting::Encryption => {
+            let request = GetBucketEncryptionRequest {
+                bucket: origin_bucket,
+                ..Default::default()
+            };
+            let configuration = match origin
+                .throttle()
+                .run(|| origin.client().get_bucket_encryption(request.clone()))
+                .await
+            {
+                Ok(output) => output.server_side_encryption_configuration,
+                Err(e) => return read_failure(e),
+            };
+            let Some(configuration) = configuration else {
+                return SettingOutcome::NotSet;
+            };
+            let request = PutBucketEncryptionRequest {
+                bucket: target_bucket,
+                server_side_encryption_configuration: configuration,
+                ..Default::default()
+            };
+            written(
+                target
+                    .throttle()
+                    .run(|| target.client().put_bucket_encryption(request.clone()))
+                    .await,
+            )
+        }
+        BucketSetting::Cors => {
+            let request = GetBucketCorsRequest {
+                bucket: origin_bucket,
+                ..Default::default()
+            };
+            let cors_rules = match origin
+                .throttle()
+                .run(|| origin.client().get_bucket_cors(request.clone()))
+                .await
+            {
+                Ok(output) => output.cors_rules.unwrap_or_default(),
+                Err(e) => return read_failure(e),
+            };
+            if cors_rules.is_empty() {
+                return SettingOutcome::NotSet;
+            }
+            let request = PutBucketCorsRequest {
+                bucket: target_bucket,
+                cors_configuration: CORSConfiguration { cors_rules },
+                ..Default::default()
+            };
+            written(
+                target
+                    .throttle()
+                    .run(|| target.client().put_bucket_cors(request.clone()))
+                    .await,
+            )
+        }
+        BucketSetting::Lifecycle => {
+            let request = GetBucketLifecycleConfigurationRequest {
+                bucket: origin_bucket,
+                ..Default::default()
+            };
+            let rules = match origin
+                .throttle()
+                .run(|| {
+                    origin
+                        .client()
+                        .get_bucket_lifecycle_configuration(request.clone())
+                })
+                .await
+            {
+                Ok(output) => output.rules.unwrap_or_default(),
+                Err(e) => return read_failure(e),
+            };
+            if rules.is_empty() {
+                return SettingOutcome::NotSet;
+            }
+            let request = PutBucketLifecycleConfigurationRequest {
+                bucket: target_bucket,
+                lifecycle_configuration: Some(BucketLifecycleConfiguration { rules }),
+                ..Default::default()
+            };
+            written(
+                target
+                    .throttle()
+                    .run(|| {
+                        target
+                            .client()
+                            .put_bucket_lifecycle_configuration(request.clone())
+                    })
+                    .await,
+            )
+        }
+        BucketSetting::Policy => {
+            let request = GetBucketPolicyRequest {
+                bucket: origin_bucket.clone(),
+                ..Default::default()
+            };
+            let policy = match origin
+                .throttle()
+                .run(|| origin.client().get_bucket_policy(request.clone()))
+                .await
+            {
+                Ok(output) => output.policy,
+                Err(e) => return read_failure(e),
+            };
+            let Some(policy) = policy.filter(|policy| !policy.trim().is_empty()) else {
+                return SettingOutcome::NotSet;
+            };
+            let request = PutBucketPolicyRequest {
+                policy: rewrite_arns(&policy, &origin_bucket, &target_bucket),
+                bucket: target_bucket,
+                ..Default::default()
+            };
+            written(
+                target
+                    .throttle()
+                    .run(|| target.client().put_bucket_policy(request.clone()))
+                    .await,
+            )
+        }
+        BucketSetting::Tagging => {
+            let request = GetBucketTaggingRequest {
+                bucket: origin_bucket,
+                ..Default::default()
+            };
+            let tag_set = match origin
+                .throttle()
+                .run(|| origin.client().get_bucket_tagging(request.clone()))
+                .await
+            {
+                Ok(output) => output.tag_set,
+                Err(e) => return read_failure(e),
+            };
+            if tag_set.is_empty() {
+                return SettingOutcome::NotSet;
+            }
+            let request = PutBucketTaggingRequest {
+                bucket: target_bucket,
+                tagging: Tagging { tag_set },
+                ..Default::default()
+            };
+            written(
+                target
+                    .throttle()
+                    .run(|| target.client().put_bucket_tagging(request.clone()))
+                    .await,
+            )
+        }
+        BucketSetting::Website => {
+            let request = GetBucketWebsiteRequest {
+                bucket: origin_bucket,
+                ..Default::default()
+            };
+            let website = match origin
+                .throttle()
+                .run(|| origin.client().get_bucket_website(request.clone()))
+                .await
+            {
+                Ok(output) => WebsiteConfiguration {
+                    error_document: output.error_document,
+                    index_document: output.index_document,
+                    redirect_all_requests_to: output.redirect_all_requests_to,
+                    routing_rules: output.routing_rules,
+                },
+                Err(e) => return read_failure(e),
+            };
+            if website.index_document.is_none() && website.redirect_all_requests_to.is_none() {
+                return SettingOutcome::NotSet;
+            }
+            let request = PutBucketWebsiteRequest {
+                bucket: target_bucket,
+                website_configuration: website,
+                ..Default::default()
+            };
+            written(
+                target
+                    .throttle()
+                    .run(|| target.client().put_bucket_website(request.clone()))
+                    .await,
+            )
+        }
+    }
+}
+
+/// Bucket settings are a handful of requests, sent one at a time.
+fn throttle(remote: &RemoteConfig) -> Throttle {
+    Throttle::new(1, remote.max_requests_per_second)
+}
+
+fn read_failure<E: std::error::Error + 'static>(error: RusotoError<E>) -> SettingOutcome {
+    match failure(error) {
+        Failure::Missing => SettingOutcome::NotSet,
+        Failure::Unsupported => {
+            SettingOutcome::Unsupported("not supported by the origin provider".to_owned())
+        }
+        Failure::Other(message) => SettingOutcome::Failed(message),
+    }
+}
+
+fn written<T, E: std::error::Error + 'static>(result: Result<T, RusotoError<E>>) -> SettingOutcome {
+    match result.map_err(failure) {
+        Ok(_) => SettingOutcome::Copied,
+        Err(Failure::Unsupported) => {
+            SettingOutcome::Unsupported("not supported by the target provider".to_owned())
+        }
+        Err(Failure::Missing) => SettingOutcome::Failed("target bucket not found".to_owned()),
+        Err(Failure::Other(message)) => SettingOutcome::Failed(message),
+    }
+}
+
+fn failure<E: std::error::Error + 'static>(error: RusotoError<E>) -> Failure {
+    if let RusotoError::Unknown(response) = &error {
+        let body = String::from_utf8_lossy(&response.body);
+        let code = body
+            .split_once("<Code>")
+            .and_then(|(_, rest)| rest.split_once("</Code>"))
+            .map_or("", |(code, _)| code);
+        if response.status.as_u16() == 501 || code == "NotImplemented" {
+            return Failure::Unsupported;
+        }
+        if NOT_SET_CODES.contains(&code) {
+            return Failure::Missing;
+        }
+    }
+    Failure::Other(StoreError::from(error).to_string())
+}
+
+/// Points the ARNs of the origin bucket and its objects, e.g.
+/// `arn:aws:s3:::origin/*`, to the target bucket.
+fn rewrite_arns(policy: &str, origin: &str, target: &str) -> String {
+    let origin_arn = format!(":::{}", origin);
+    let mut rewritten = String::with_capacity(policy.len());
+    let mut rest = policy;
+    while let Some(start) = rest.find(&origin_arn) {
+        let end = start + origin_arn.len();
+        rewritten.push_str(&rest[..start]);
+        // Only the whole bucket name, not another bucket it is a prefix of
+        match rest[end..].starts_with(['/', '"']) {
+            true => rewritten.push_str(&format!(":::{}", target)),
+            false => rewritten.push_str(&origin_arn),
+        }
+        rest = &rest[end..];
+    }
+    rewritten.push_str(rest);
+    rewritten
+}
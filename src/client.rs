@@ -0,0 +1,283 @@
+use clap::ValueEnum;
+use hyper::client::HttpConnector;
+use hyper_tls::HttpsConnector;
+use rusoto_core::credential::{AwsCredentials, ProvideAwsCredentials};
+use rusoto_core::request::{DispatchSignedRequestFuture, HttpDispatchError};
+use rusoto_core::signature::SignedRequest;
+use rusoto_core::{DispatchSignedRequest, HttpClient, Region};
+use rusoto_credential::{ChainProvider, EnvironmentProvider, ProfileProvider, StaticProvider};
+use rusoto_s3::S3Client;
+use serde::Deserialize;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// S3-compatible services with a known endpoint layout.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    #[default]
+    Aws,
+    Minio,
+    R2,
+    B2,
+    Wasabi,
+    Digitalocean,
+    Gcs,
+}
+
+/// Differences between providers the commands have to account for.
+#[derive(Debug, Clone, Copy)]
+pub struct ProviderQuirks {
+    /// Buckets are addressed as `bucket.endpoint` rather than `endpoint/bucket`
+    pub virtual_hosted: bool,
+    /// Object lock headers such as `x-amz-bypass-governance-retention` are understood
+    pub supports_object_lock: bool,
+}
+
+impl Provider {
+    fn endpoint_template(&self) -> &'static str {
+        match self {
+            Provider::Aws => "s3.{region}.amazonaws.com",
+            Provider::Minio => "http://localhost:9000",
+            Provider::R2 => "{account_id}.r2.cloudflarestorage.com",
+            Provider::B2 => "s3.{region}.backblazeb2.com",
+            Provider::Wasabi => "s3.{region}.wasabisys.com",
+            Provider::Digitalocean => "{region}.digitaloceanspaces.com",
+            Provider::Gcs => "storage.googleapis.com",
+        }
+    }
+
+    pub fn quirks(&self) -> ProviderQuirks {
+        match self {
+            Provider::Aws => ProviderQuirks {
+                virtual_hosted: true,
+                supports_object_lock: true,
+            },
+            Provider::Minio | Provider::Wasabi => ProviderQuirks {
+                virtual_hosted: false,
+                supports_object_lock: true,
+            },
+            Provider::R2 | Provider::B2 | Provider::Digitalocean | Provider::Gcs => {
+                ProviderQuirks {
+                    virtual_hosted: false,
+                    supports_object_lock: false,
+                }
+            }
+        }
+    }
+}
+
+/// Where a client gets its AWS credentials from.
+#[derive(Clone, Default, Deserialize)]
+#[serde(tag = "source", rename_all = "lowercase")]
+pub enum CredentialsSource {
+    /// Environment, shared credentials file, then instance metadata
+    #[default]
+    Default,
+    Static {
+        access_key_id: String,
+        secret_access_key: String,
+    },
+    Env,
+    Profile {
+        name: Option<String>,
+    },
+}
+
+/// Static keys are left out, so that the settings can be logged.
+impl fmt::Debug for CredentialsSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CredentialsSource::Default => f.write_str("Default"),
+            CredentialsSource::Static { .. } => f
+                .debug_struct("Static")
+                .field("access_key_id", &"<redacted>")
+                .field("secret_access_key", &"<redacted>")
+                .finish(),
+            CredentialsSource::Env => f.write_str("Env"),
+            CredentialsSource::Profile { name } => {
+                f.debug_struct("Profile").field("name", name).finish()
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ClientOptions {
+    pub region: String,
+    pub credentials: CredentialsSource,
+    pub endpoint: Option<String>,
+    pub provider: Provider,
+    pub account_id: Option<String>,
+    /// Address buckets as `endpoint/bucket` even where the provider is
+    /// addressed virtual-hosted style by default
+    pub force_path_style: bool,
+    pub use_http: bool,
+    pub insecure: bool,
+    pub ca_bundle: Option<PathBuf>,
+}
+
+impl ClientOptions {
+    pub fn endpoint(&self) -> Result<String, Box<dyn std::error::Error>> {
+        let endpoint = match &self.endpoint {
+            Some(endpoint) => endpoint.clone(),
+            None => {
+                let template = self.provider.endpoint_template();
+                if template.contains("{account_id}") && self.account_id.is_none() {
+                    return Err(format!("--account-id is required for {:?}", self.provider).into());
+                }
+                template.replace("{region}", &self.region).replace(
+                    "{account_id}",
+                    self.account_id.as_deref().unwrap_or_default(),
+                )
+            }
+        };
+
+        if endpoint.starts_with("http://") || endpoint.starts_with("https://") {
+            Ok(endpoint)
+        } else if self.use_http {
+            Ok(format!("http://{}", endpoint))
+        } else {
+            Ok(format!("https://{}", endpoint))
+        }
+    }
+
+    pub fn quirks(&self) -> ProviderQuirks {
+        self.provider.quirks()
+    }
+
+    /// Whether buckets are addressed as `bucket.endpoint`: the default for
+    /// AWS, whose path-style addressing is deprecated. Explicit endpoints
+    /// keep the path-style addressing rusoto uses natively.
+    pub fn virtual_hosted(&self) -> bool {
+        !self.force_path_style && self.endpoint.is_none() && self.quirks().virtual_hosted
+    }
+}
+
+fn create_http_client(
+    options: &ClientOptions,
+) -> Result<HttpClient<HttpsConnector<HttpConnector>>, Box<dyn std::error::Error>> {
+    let mut tls = native_tls::TlsConnector::builder();
+    if options.insecure {
+        tls.danger_accept_invalid_certs(true);
+        tls.danger_accept_invalid_hostnames(true);
+    }
+    if let Some(ca_bundle) = &options.ca_bundle {
+        let pem = std::fs::read(ca_bundle)?;
+        tls.add_root_certificate(native_tls::Certificate::from_pem(&pem)?);
+    }
+
+    let mut http = HttpConnector::new();
+    http.enforce_http(false);
+    let connector = HttpsConnector::from((http, tls.build()?.into()));
+
+    Ok(HttpClient::from_connector(connector))
+}
+
+pub fn create_s3_client(options: &ClientOptions) -> Result<S3Client, Box<dyn std::error::Error>> {
+    match &options.credentials {
+        CredentialsSource::Default => build_s3_client(options, ChainProvider::new()),
+        CredentialsSource::Static {
+            access_key_id,
+            secret_access_key,
+        } => build_s3_client(
+            options,
+            StaticProvider::new_minimal(access_key_id.clone(), secret_access_key.clone()),
+        ),
+        CredentialsSource::Env => build_s3_client(options, EnvironmentProvider::default()),
+        CredentialsSource::Profile { name } => {
+            let mut provider = ProfileProvider::new()?;
+            if let Some(name) = name {
+                provider.set_profile(name.clone());
+            }
+            build_s3_client(options, provider)
+        }
+    }
+}
+
+fn build_s3_client<P>(
+    options: &ClientOptions,
+    credentials_provider: P,
+) -> Result<S3Client, Box<dyn std::error::Error>>
+where
+    P: ProvideAwsCredentials + Send + Sync + 'static,
+{
+    let http_client = create_http_client(options)?;
+    let region = Region::Custom {
+        name: options.region.clone(),
+        endpoint: options.endpoint()?,
+    };
+
+    if options.virtual_hosted() {
+        // Requests are signed by the dispatcher once they have been rewritten
+        let dispatcher = VirtualHostDispatcher {
+            inner: Arc::new(http_client),
+            credentials_provider: Arc::new(credentials_provider),
+        };
+        Ok(S3Client::new_with(
+            dispatcher,
+            StaticProvider::from(AwsCredentials::default()),
+            region,
+        ))
+    } else {
+        Ok(S3Client::new_with(
+            http_client,
+            credentials_provider,
+            region,
+        ))
+    }
+}
+
+/// Rewrites the path-style requests rusoto builds (`endpoint/bucket/key`)
+/// into virtual-hosted ones (`bucket.endpoint/key`) before signing them.
+struct VirtualHostDispatcher<P> {
+    inner: Arc<HttpClient<HttpsConnector<HttpConnector>>>,
+    credentials_provider: Arc<P>,
+}
+
+impl<P> DispatchSignedRequest for VirtualHostDispatcher<P>
+where
+    P: ProvideAwsCredentials + Send + Sync + 'static,
+{
+    fn dispatch(
+        &self,
+        mut request: SignedRequest,
+        timeout: Option<Duration>,
+    ) -> DispatchSignedRequestFuture {
+        let inner = self.inner.clone();
+        let credentials_provider = self.credentials_provider.clone();
+
+        Box::pin(async move {
+            let credentials = credentials_provider
+                .credentials()
+                .await
+                .map_err(|e| HttpDispatchError::new(e.to_string()))?;
+
+            to_virtual_host(&mut request);
+            request.sign(&credentials);
+
+            inner.dispatch(request, timeout).await
+        })
+    }
+}
+
+/// Moves the bucket of a path-style request into its hostname, unless its
+/// name has dots.
+pub fn to_virtual_host(request: &mut SignedRequest) {
+    let path = request.path.trim_start_matches('/').to_owned();
+    let (bucket, rest) = match path.split_once('/') {
+        Some((bucket, rest)) => (bucket.to_owned(), rest.to_owned()),
+        None => (path.clone(), String::new()),
+    };
+
+    // Dotted bucket names do not match the provider's wildcard certificate
+    if bucket.is_empty() || bucket.contains('.') {
+        return;
+    }
+
+    let hostname = format!("{}.{}", bucket, request.hostname());
+    request.set_hostname(Some(hostname));
+    request.path = format!("/{}", rest);
+}
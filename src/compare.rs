@@ -0,0 +1,220 @@
+use futures_util::StreamExt;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+use tokio::io::AsyncReadExt;
+
+use crate::listing::list_objects_by_key;
+use crate::store::{ObjectInfo, StoreError, StorePath};
+use crate::transfer::{EventHandler, Progress};
+
+/// Settings of a `diff`.
+#[derive(Debug, Clone, Default)]
+pub struct DiffOptions {
+    /// Compare the MD5 of objects of the same size whose ETags say nothing,
+    /// reading their bodies when needed. Otherwise, they are unverified.
+    pub checksum: bool,
+    /// Bodies read at once for checksums
+    pub concurrency: Option<usize>,
+    pub events: EventHandler,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffStatus {
+    OnlyInOrigin,
+    OnlyInTarget,
+    /// Sizes differ
+    SizeDiffers,
+    /// Same size, but their ETags or checksums differ
+    ContentDiffers,
+    /// Same size, but their ETags cannot be compared and their bodies were
+    /// not read
+    Unverified,
+}
+
+impl DiffStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DiffStatus::OnlyInOrigin => "only_in_origin",
+            DiffStatus::OnlyInTarget => "only_in_target",
+            DiffStatus::SizeDiffers => "size_differs",
+            DiffStatus::ContentDiffers => "content_differs",
+            DiffStatus::Unverified => "unverified",
+        }
+    }
+}
+
+/// A key that does not match between the two sides.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiffEntry {
+    /// Key relative to the prefix of each side
+    pub key: String,
+    pub status: DiffStatus,
+    pub origin_size: Option<u64>,
+    pub target_size: Option<u64>,
+    pub origin_e_tag: Option<String>,
+    pub target_e_tag: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DiffReport {
+    pub matching: u64,
+    pub only_in_origin: u64,
+    pub only_in_target: u64,
+    pub different: u64,
+    pub unverified: u64,
+    /// Listing errors, after which the report is incomplete
+    pub errors: u64,
+    /// Keys that do not match, in order
+    pub differences: Vec<DiffEntry>,
+}
+
+impl DiffReport {
+    pub fn is_match(&self) -> bool {
+        self.differences.is_empty() && self.errors == 0
+    }
+
+    fn add(&mut self, entry: DiffEntry) {
+        match entry.status {
+            DiffStatus::OnlyInOrigin => self.only_in_origin += 1,
+            DiffStatus::OnlyInTarget => self.only_in_target += 1,
+            DiffStatus::SizeDiffers | DiffStatus::ContentDiffers => self.different += 1,
+            DiffStatus::Unverified => self.unverified += 1,
+        }
+        self.differences.push(entry);
+    }
+}
+
+/// Lists `origin` and `target` at the same time and compares their objects
+/// by key relative to their prefix, then by size and ETag.
+pub async fn diff(
+    origin: &StorePath,
+    target: &StorePath,
+    options: &DiffOptions,
+) -> Result<DiffReport, Box<dyn std::error::Error>> {
+    let progress = Progress::new(options.events.clone());
+    let (origin_objects, target_objects) = tokio::join!(
+        list_objects_by_key(&origin.store, &origin.prefix, &progress),
+        list_objects_by_key(&target.store, &target.prefix, &progress),
+    );
+
+    let keys = origin_objects
+        .keys()
+        .chain(target_objects.keys())
+        .collect::<BTreeSet<_>>();
+    let report = Arc::new(Mutex::new(DiffReport {
+        errors: progress.summary().failed,
+        ..Default::default()
+    }));
+
+    futures_util::stream::iter(keys)
+        .for_each_concurrent(options.concurrency.unwrap_or(10), |key| {
+            let report = report.clone();
+            let origin_object = origin_objects.get(key);
+            let target_object = target_objects.get(key);
+            async move {
+                let status = match (origin_object, target_object) {
+                    (Some(_), None) => Some(DiffStatus::OnlyInOrigin),
+                    (None, Some(_)) => Some(DiffStatus::OnlyInTarget),
+                    (Some(origin_object), Some(target_object)) => {
+                        compare(origin, origin_object, target, target_object, options).await
+                    }
+                    (None, None) => None,
+                };
+
+                let mut report = report.lock().unwrap();
+                match status {
+                    Some(status) => report.add(DiffEntry {
+                        key: key.clone(),
+                        status,
+                        origin_size: origin_object.map(|object| object.size),
+                        target_size: target_object.map(|object| object.size),
+                        origin_e_tag: origin_object.and_then(|object| object.e_tag.clone()),
+                        target_e_tag: target_object.and_then(|object| object.e_tag.clone()),
+                    }),
+                    None => report.matching += 1,
+                }
+            }
+        })
+        .await;
+
+    let mut report = Arc::try_unwrap(report).unwrap().into_inner().unwrap();
+    report.differences.sort_by(|a, b| a.key.cmp(&b.key));
+    Ok(report)
+}
+
+/// How two objects under the same key differ, if they do.
+async fn compare(
+    origin: &StorePath,
+    origin_object: &ObjectInfo,
+    target: &StorePath,
+    target_object: &ObjectInfo,
+    options: &DiffOptions,
+) -> Option<DiffStatus> {
+    if origin_object.size != target_object.size {
+        return Some(DiffStatus::SizeDiffers);
+    }
+    if origin_object.e_tag.is_some() && origin_object.e_tag == target_object.e_tag {
+        return None;
+    }
+
+    let (origin_md5, target_md5) = tokio::join!(
+        content_md5(origin, origin_object),
+        content_md5(target, target_object),
+    );
+    if let (Some(origin_md5), Some(target_md5)) = (&origin_md5, &target_md5) {
+        return (origin_md5 != target_md5).then_some(DiffStatus::ContentDiffers);
+    }
+    if !options.checksum {
+        return Some(DiffStatus::Unverified);
+    }
+
+    let origin_md5 = match origin_md5 {
+        Some(md5) => Ok(md5),
+        None => read_md5(origin, &origin_object.key).await,
+    };
+    let target_md5 = match target_md5 {
+        Some(md5) => Ok(md5),
+        None => read_md5(target, &target_object.key).await,
+    };
+    match (origin_md5, target_md5) {
+        (Ok(origin_md5), Ok(target_md5)) if origin_md5 == target_md5 => None,
+        // A body that cannot be read cannot be said to match
+        _ => Some(DiffStatus::ContentDiffers),
+    }
+}
+
+/// MD5 of an object's body, when its ETag is one: single part uploads
+/// stored in the clear or with SSE-S3. The ETags of SSE-KMS and SSE-C
+/// objects look the same, so the encryption of the object is asked for.
+async fn content_md5(location: &StorePath, object: &ObjectInfo) -> Option<String> {
+    let e_tag = object.e_tag.as_deref()?.trim_matches('"');
+    let looks_like_md5 = e_tag.len() == 32 && e_tag.bytes().all(|byte| byte.is_ascii_hexdigit());
+    if !looks_like_md5 {
+        return None;
+    }
+
+    // An object whose encryption is unknown is not trusted
+    let head = location.store.head(&object.key).await.ok()??;
+    let is_md5 = !head.customer_encrypted
+        && matches!(
+            head.server_side_encryption.as_deref(),
+            None | Some("AES256")
+        );
+    is_md5.then(|| e_tag.to_ascii_lowercase())
+}
+
+async fn read_md5(location: &StorePath, key: &str) -> Result<String, StoreError> {
+    let mut body = location.store.get(key, None).await?.body;
+    let mut context = md5::Context::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = body.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        context.consume(&buffer[..read]);
+    }
+    Ok(format!("{:x}", context.compute()))
+}
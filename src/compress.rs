@@ -0,0 +1,126 @@
+use async_compression::tokio::bufread::{GzipEncoder, ZstdEncoder};
+use clap::ValueEnum;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use tokio::io::{AsyncRead, BufReader};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    fn content_encoding(&self) -> &'static str {
+        match self {
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            Compression::Gzip => ".gz",
+            Compression::Zstd => ".zst",
+        }
+    }
+}
+
+/// How compressed objects are told apart from the others.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum CompressionMode {
+    /// Keep the key and set the `Content-Encoding` header
+    #[default]
+    ContentEncoding,
+    /// Append `.gz` or `.zst` to the key
+    Suffix,
+}
+
+/// Compression of the uploaded files matching a set of globs.
+#[derive(Debug, Clone)]
+pub struct CompressOptions {
+    compression: Compression,
+    mode: CompressionMode,
+    globs: GlobSet,
+}
+
+impl CompressOptions {
+    /// Compresses every file when no glob is given.
+    pub fn new(
+        compression: Compression,
+        mode: CompressionMode,
+        patterns: &[String],
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in patterns {
+            builder.add(
+                Glob::new(pattern)
+                    .map_err(|e| format!("Invalid --compress-glob {}: {}", pattern, e))?,
+            );
+        }
+        if patterns.is_empty() {
+            builder.add(Glob::new("*")?);
+        }
+
+        Ok(CompressOptions {
+            compression,
+            mode,
+            globs: builder.build()?,
+        })
+    }
+
+    pub fn from_args(
+        compression: Option<Compression>,
+        mode: Option<CompressionMode>,
+        patterns: &[String],
+    ) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        match compression {
+            Some(compression) => Ok(Some(CompressOptions::new(
+                compression,
+                mode.unwrap_or_default(),
+                patterns,
+            )?)),
+            None if mode.is_some() || !patterns.is_empty() => {
+                Err("--compress-mode and --compress-glob require --compress".into())
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn mode(&self) -> CompressionMode {
+        self.mode
+    }
+
+    /// Whether the file at `relative_key` should be compressed.
+    pub fn matches(&self, relative_key: &str) -> bool {
+        self.globs.is_match(relative_key)
+    }
+
+    /// Key of the compressed object for `relative_key`.
+    pub fn key(&self, relative_key: &str) -> String {
+        match self.mode {
+            CompressionMode::ContentEncoding => relative_key.to_owned(),
+            CompressionMode::Suffix => format!("{}{}", relative_key, self.compression.extension()),
+        }
+    }
+
+    pub fn content_encoding(&self) -> Option<String> {
+        match self.mode {
+            CompressionMode::ContentEncoding => {
+                Some(self.compression.content_encoding().to_owned())
+            }
+            CompressionMode::Suffix => None,
+        }
+    }
+
+    /// Compresses `reader` as it is read.
+    pub fn compress<R>(&self, reader: R) -> Box<dyn AsyncRead + Unpin + Send>
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+    {
+        let reader = BufReader::new(reader);
+        match self.compression {
+            Compression::Gzip => Box::new(GzipEncoder::new(reader)),
+            Compression::Zstd => Box::new(ZstdEncoder::new(reader)),
+        }
+    }
+}
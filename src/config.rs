@@ -0,0 +1,242 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::client::{ClientOptions, CredentialsSource, Provider};
+
+/// Contents of `~/.config/s3-utils/config.toml`.
+///
+/// ```toml
+/// [remotes.prod]
+/// region = "eu-west-1"
+/// credentials = { source = "profile", name = "prod" }
+/// concurrency = 100
+///
+/// [remotes.backup]
+/// provider = "b2"
+/// region = "us-west-004"
+/// credentials = { source = "static", access_key_id = "...", secret_access_key = "..." }
+/// ```
+#[derive(Debug, Default, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub remotes: HashMap<String, RemoteConfig>,
+}
+
+/// Connection settings of a named remote. Every field is optional so the
+/// same structure also carries the settings given on the command line.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RemoteConfig {
+    pub provider: Option<Provider>,
+    pub endpoint: Option<String>,
+    pub region: Option<String>,
+    pub account_id: Option<String>,
+    pub credentials: Option<CredentialsSource>,
+    pub force_path_style: Option<bool>,
+    pub use_http: Option<bool>,
+    pub insecure: Option<bool>,
+    pub ca_bundle: Option<PathBuf>,
+    pub concurrency: Option<usize>,
+    pub max_requests_per_second: Option<f64>,
+}
+
+impl Config {
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::home_dir().map(|home| home.join(".config").join("s3-utils").join("config.toml"))
+    }
+
+    /// Loads the given config file, or the default one when it exists.
+    pub fn load(path: Option<&Path>) -> Result<Config, Box<dyn std::error::Error>> {
+        let path = match path {
+            Some(path) => path.to_owned(),
+            None => match Config::default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Config::default()),
+            },
+        };
+
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read config {}: {}", path.display(), e))?;
+        let config = toml::from_str(&contents)
+            .map_err(|e| format!("Failed to parse config {}: {}", path.display(), e))?;
+
+        Ok(config)
+    }
+
+    pub fn remote(&self, name: &str) -> Result<RemoteConfig, Box<dyn std::error::Error>> {
+        self.remotes
+            .get(name)
+            .cloned()
+            .ok_or_else(|| format!("Unknown remote: {}", name).into())
+    }
+
+    /// Settings for a location: those of its remote, if it names one.
+    pub fn remote_for(
+        &self,
+        location: &RemotePath,
+    ) -> Result<RemoteConfig, Box<dyn std::error::Error>> {
+        match location.remote.as_deref() {
+            Some(name) => self.remote(name),
+            None => Ok(RemoteConfig::default()),
+        }
+    }
+}
+
+impl RemoteConfig {
+    /// Fills the settings missing from `self` with those of `fallback`.
+    pub fn or(self, fallback: RemoteConfig) -> RemoteConfig {
+        RemoteConfig {
+            provider: self.provider.or(fallback.provider),
+            endpoint: self.endpoint.or(fallback.endpoint),
+            region: self.region.or(fallback.region),
+            account_id: self.account_id.or(fallback.account_id),
+            credentials: self.credentials.or(fallback.credentials),
+            force_path_style: self.force_path_style.or(fallback.force_path_style),
+            use_http: self.use_http.or(fallback.use_http),
+            insecure: self.insecure.or(fallback.insecure),
+            ca_bundle: self.ca_bundle.or(fallback.ca_bundle),
+            concurrency: self.concurrency.or(fallback.concurrency),
+            max_requests_per_second: self
+                .max_requests_per_second
+                .or(fallback.max_requests_per_second),
+        }
+    }
+
+    pub fn client_options(&self) -> Result<ClientOptions, Box<dyn std::error::Error>> {
+        let region = self.region.clone().ok_or("--region is required")?;
+
+        Ok(ClientOptions {
+            region,
+            credentials: self.credentials.clone().unwrap_or_default(),
+            endpoint: self.endpoint.clone(),
+            provider: self.provider.unwrap_or_default(),
+            account_id: self.account_id.clone(),
+            force_path_style: self.force_path_style.unwrap_or_default(),
+            use_http: self.use_http.unwrap_or_default(),
+            insecure: self.insecure.unwrap_or_default(),
+            ca_bundle: self.ca_bundle.clone(),
+        })
+    }
+}
+
+/// Static credentials given on the command line, when both halves are set.
+pub fn static_credentials(
+    access_key_id: Option<String>,
+    secret_access_key: Option<String>,
+) -> Option<CredentialsSource> {
+    match (access_key_id, secret_access_key) {
+        (Some(access_key_id), Some(secret_access_key)) => Some(CredentialsSource::Static {
+            access_key_id,
+            secret_access_key,
+        }),
+        _ => None,
+    }
+}
+
+/// A bucket location written as `[remote:]bucket[/prefix]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemotePath {
+    pub remote: Option<String>,
+    pub bucket: String,
+    pub prefix: String,
+}
+
+impl RemotePath {
+    pub fn new(bucket: String) -> Self {
+        RemotePath {
+            remote: None,
+            bucket,
+            prefix: String::new(),
+        }
+    }
+
+    /// Prefix every key of the location starts with, `""` or `"dir/"`.
+    pub fn key_prefix(&self) -> String {
+        let prefix = self.prefix.trim_matches('/');
+        if prefix.is_empty() {
+            String::new()
+        } else {
+            format!("{}/", prefix)
+        }
+    }
+
+    pub fn key(&self, relative_key: &str) -> String {
+        format!("{}{}", self.key_prefix(), relative_key)
+    }
+}
+
+impl FromStr for RemotePath {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (remote, path) = match value.split_once(':') {
+            Some((remote, path)) if !remote.contains('/') => (Some(remote.to_owned()), path),
+            _ => (None, value),
+        };
+
+        let path = path.trim_start_matches('/');
+        let (bucket, prefix) = match path.split_once('/') {
+            Some((bucket, prefix)) => (bucket, prefix),
+            None => (path, ""),
+        };
+
+        if bucket.is_empty() {
+            return Err(format!("Missing bucket name in {}", value));
+        }
+
+        Ok(RemotePath {
+            remote,
+            bucket: bucket.to_owned(),
+            prefix: prefix.to_owned(),
+        })
+    }
+}
+
+/// Either side of a `cp`/`sync`: a local directory, `s3://bucket/prefix`
+/// or `remote:bucket/prefix`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Location {
+    Local(PathBuf),
+    Bucket(RemotePath),
+}
+
+impl Location {
+    pub fn parse(value: &str, config: &Config) -> Result<Location, Box<dyn std::error::Error>> {
+        if let Some(path) = value.strip_prefix("s3://") {
+            // Parsed as `/path` so a colon in the prefix is not read as a remote
+            return Ok(Location::Bucket(format!("/{}", path).parse()?));
+        }
+
+        if let Some((remote, _)) = value.split_once(':') {
+            if config.remotes.contains_key(remote) {
+                return Ok(Location::Bucket(value.parse()?));
+            }
+
+            // Single letters are Windows drives, anything with a separator a path
+            let looks_like_remote = remote.len() > 1 && !remote.contains(['/', '\\', '.']);
+            if looks_like_remote {
+                return Err(format!("Unknown remote: {}", remote).into());
+            }
+        }
+
+        Ok(Location::Local(PathBuf::from(value)))
+    }
+}
+
+/// Resolves the bucket location of a command from its positional argument
+/// or, failing that, from its `--bucket`-style flag.
+pub fn resolve_location(
+    positional: Option<&str>,
+    bucket_flag: Option<String>,
+    flag_name: &str,
+) -> Result<RemotePath, Box<dyn std::error::Error>> {
+    match (positional, bucket_flag) {
+        (Some(positional), _) => Ok(positional.parse()?),
+        (None, Some(bucket)) => Ok(RemotePath::new(bucket)),
+        (None, None) => {
+            Err(format!("{} or a remote:bucket location is required", flag_name).into())
+        }
+    }
+}
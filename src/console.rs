@@ -0,0 +1,117 @@
+use s3_utils::{BucketSetting, EventHandler, SettingOutcome, TransferEvent, TransferSummary};
+
+/// Prints the progress of a transfer as it happens.
+pub fn print_events() -> EventHandler {
+    EventHandler::new(|event| match event {
+        TransferEvent::Uploaded {
+            key,
+            bytes,
+            original_bytes: Some(original_bytes),
+        } => println!(
+            "File uploaded: {} ({} -> {} bytes, {})",
+            key,
+            original_bytes,
+            bytes,
+            compression_ratio(*original_bytes, *bytes)
+        ),
+        TransferEvent::Uploaded { key, .. } => println!("File uploaded: {}", key),
+        TransferEvent::Downloaded { path, .. } => println!("File downloaded: {}", path.display()),
+        TransferEvent::Copied { key, .. } => println!("File sync: {}", key),
+        TransferEvent::Deleted { key } => println!("Deleted {}", key),
+        TransferEvent::PartUploaded { part_number, .. } => {
+            println!("Uploading part: {}", part_number)
+        }
+        TransferEvent::Tagged { key } => println!("Tagged: {}", key),
+        TransferEvent::StorageClassChanged { key, storage_class } => {
+            println!("Storage class changed: {} ({})", key, storage_class)
+        }
+        TransferEvent::RestoreRequested { key } => println!("Restore requested: {}", key),
+        TransferEvent::RestoreInProgress { key } => println!("Restore in progress: {}", key),
+        TransferEvent::Restored {
+            key,
+            expiry: Some(expiry),
+        } => println!("Restored: {} (until {})", key, expiry.to_rfc3339()),
+        TransferEvent::Restored { key, .. } => println!("Restored: {}", key),
+        TransferEvent::UpToDate { .. } => {}
+        TransferEvent::Skipped { key, reason } => println!("Skipping object {}: {}", key, reason),
+        TransferEvent::Failed { key, error } => println!("Error with {}: {}", key, error),
+        TransferEvent::ListingFailed { prefix, error } if prefix.is_empty() => {
+            println!("Error listing: {}", error)
+        }
+        TransferEvent::ListingFailed { prefix, error } => {
+            println!("Error listing {}: {}", prefix, error)
+        }
+        TransferEvent::Throttled { concurrency } => println!(
+            "Throttled by provider, concurrency reduced to {}",
+            concurrency
+        ),
+    })
+}
+
+pub fn print_summary(summary: &TransferSummary) {
+    if summary.original_bytes > 0 {
+        println!(
+            "Compressed {} bytes to {} bytes ({})",
+            summary.original_bytes,
+            summary.compressed_bytes,
+            compression_ratio(summary.original_bytes, summary.compressed_bytes)
+        );
+    }
+    println!(
+        "Done: {} transferred ({} bytes), {} skipped, {} failed",
+        summary.transferred, summary.bytes, summary.skipped, summary.failed
+    );
+}
+
+/// Prints what became of the bucket settings copied, but those not set.
+pub fn print_bucket_config(outcomes: &[(BucketSetting, SettingOutcome)]) {
+    for (setting, outcome) in outcomes {
+        match outcome {
+            SettingOutcome::Copied => println!("Bucket setting copied: {}", setting),
+            SettingOutcome::NotSet => {}
+            SettingOutcome::Unsupported(reason) => {
+                println!("Skipping bucket setting {}: {}", setting, reason)
+            }
+            SettingOutcome::Failed(error) => {
+                println!("Error with bucket setting {}: {}", setting, error)
+            }
+        }
+    }
+}
+
+fn compression_ratio(original: u64, compressed: u64) -> String {
+    match compressed {
+        0 => "-".to_owned(),
+        _ => format!("{:.1}x", original as f64 / compressed as f64),
+    }
+}
+
+/// A line of CSV, quoting the fields that need it.
+pub fn csv_row<S: AsRef<str>>(fields: &[S]) -> String {
+    fields
+        .iter()
+        .map(|field| {
+            let field = field.as_ref();
+            match field.contains([',', '"', '\n', '\r']) {
+                true => format!("\"{}\"", field.replace('"', "\"\"")),
+                false => field.to_owned(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Size in binary units, e.g. `1.5 GiB`.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
@@ -0,0 +1,399 @@
+use futures_util::stream::{self, BoxStream};
+use futures_util::StreamExt;
+use std::collections::{HashMap, HashSet};
+use tokio::sync::mpsc;
+
+use crate::archive::read_error;
+use crate::compress::{CompressOptions, CompressionMode};
+use crate::config::{RemoteConfig, RemotePath};
+use crate::cse::ClientEncryption;
+use crate::listing::{list_objects_by_key, spawn_listing};
+use crate::store::{ByteReader, ObjectInfo, PutOptions, S3Store, StorePath, WrittenObject};
+use crate::tags::is_tagged;
+use crate::transfer::{Progress, TransferEvent, TransferOptions, TransferSummary};
+use crate::utils::ObjectState;
+
+/// Copies the objects under `origin` to `target`, possibly in another
+/// account or provider: bodies go through this process.
+pub async fn copy_objects(
+    origin: &RemotePath,
+    origin_remote: &RemoteConfig,
+    target: &RemotePath,
+    target_remote: &RemoteConfig,
+    options: &TransferOptions,
+) -> Result<TransferSummary, Box<dyn std::error::Error>> {
+    let concurrency =
+        options.concurrency(origin_remote.concurrency.or(target_remote.concurrency), 50);
+    let throttle = options.throttle(
+        concurrency,
+        origin_remote
+            .max_requests_per_second
+            .or(target_remote.max_requests_per_second),
+    );
+
+    let origin_store = S3Store::connect(origin_remote, &origin.bucket, throttle.clone())?
+        .with_sse(options.source_sse.clone());
+    let target_store = S3Store::connect(target_remote, &target.bucket, throttle)?
+        .with_sse(options.sse.clone())
+        .with_events(options.events.clone());
+
+    transfer_between(
+        &StorePath::new(origin_store, origin.key_prefix()),
+        &StorePath::new(target_store, target.key_prefix()),
+        &TransferOptions {
+            concurrency: Some(concurrency),
+            ..options.clone()
+        },
+    )
+    .await
+}
+
+/// Copies the objects under `source` to `target`, whatever their stores:
+/// uploads, downloads and bucket to bucket copies all go through here.
+///
+/// Files are compressed and encrypted on their way to a store that keeps
+/// metadata, and client-side encrypted objects decrypted on their way to a
+/// local directory.
+pub async fn transfer_between(
+    source: &StorePath,
+    target: &StorePath,
+    options: &TransferOptions,
+) -> Result<TransferSummary, Box<dyn std::error::Error>> {
+    let TransferOptions {
+        sync,
+        delete,
+        flatten,
+        keys,
+        encryption,
+        compress,
+        ..
+    } = options;
+
+    // Encrypted bodies are not valid gzip or zstd streams
+    if encryption.is_some()
+        && compress
+            .as_ref()
+            .is_some_and(|compress| compress.mode() == CompressionMode::ContentEncoding)
+    {
+        return Err("Client-side encryption requires --compress-mode suffix".into());
+    }
+    // Without a listing there are no sizes and dates to compare
+    if *sync && options.manifest.is_some() {
+        return Err("A manifest cannot be synced, its objects are always transferred".into());
+    }
+    if *delete && !*sync {
+        return Err("Deleting objects whose source is gone requires a sync".into());
+    }
+    // Objects without the tags would look gone
+    if *delete && !options.where_tags.is_empty() {
+        return Err("Deleting objects whose source is gone cannot be filtered by tags".into());
+    }
+    // Likewise for objects modified outside of the window
+    if *delete && !options.modified.is_empty() {
+        return Err("Deleting objects whose source is gone cannot be filtered by date".into());
+    }
+    if options.manifest.is_some() && !options.modified.is_empty() {
+        return Err("A manifest has no modification dates to filter by".into());
+    }
+    if let Some(root) = source.store.local_root() {
+        if !root.is_dir() {
+            return Err(format!("Not a directory: {}", root.display()).into());
+        }
+    }
+
+    let concurrency = options.concurrency.unwrap_or(50);
+    let progress = Progress::new(options.events.clone());
+    let stores_metadata = target.store.local_root().is_none();
+
+    let target_objects = if *sync {
+        list_objects_by_key(&target.store, &target.prefix, &progress).await
+    } else {
+        Default::default()
+    };
+
+    let objects: BoxStream<SourceObject> = match &options.manifest {
+        Some(manifest) => stream::iter(manifest.entries())
+            .map(|entry| SourceObject {
+                info: ObjectInfo {
+                    key: entry.key.clone(),
+                    ..Default::default()
+                },
+                version_id: entry.version_id.clone(),
+                target_key: entry.target_key.clone(),
+            })
+            .boxed(),
+        None => {
+            let (tx, rx) = mpsc::channel(concurrency);
+            spawn_listing(
+                source.store.clone(),
+                source.prefix.clone(),
+                progress.clone(),
+                tx,
+            );
+            tokio_stream::wrappers::ReceiverStream::new(rx)
+                .filter(|info| std::future::ready(options.modified.matches(info.modified)))
+                .map(|info| SourceObject {
+                    info,
+                    version_id: None,
+                    target_key: None,
+                })
+                .boxed()
+        }
+    };
+
+    // Tags are only known by asking for them, one object at a time
+    let objects = match options.where_tags.is_empty() {
+        true => objects,
+        false => objects
+            .map(|object| {
+                let progress = progress.clone();
+                async move {
+                    match is_tagged(
+                        source.store.as_ref(),
+                        &object.info.key,
+                        object.version_id.as_deref(),
+                        &options.where_tags,
+                    )
+                    .await
+                    {
+                        Ok(tagged) => tagged.then_some(object),
+                        Err(e) => {
+                            progress.fail(&object.info.key, e);
+                            None
+                        }
+                    }
+                }
+            })
+            .buffered(concurrency)
+            .filter_map(std::future::ready)
+            .boxed(),
+    };
+
+    // Keys that several objects would be written to, once changed
+    let detect_collisions = *flatten || keys.may_collide();
+    let mut written_keys = HashMap::new();
+    // Target keys relative to its prefix that still have a source
+    let mut source_keys = HashSet::new();
+
+    objects
+        .filter_map(|object| {
+            let relative_key = source.relative_key(&object.info.key);
+            let relative_key = match flatten {
+                true => relative_key.rsplit('/').next().unwrap_or(relative_key),
+                false => relative_key,
+            };
+            let relative_key = keys.apply(relative_key);
+            let relative_key = relative_key.as_str();
+            let compress = compress
+                .as_ref()
+                .filter(|compress| compress.matches(relative_key))
+                .cloned();
+            let relative_key = match &compress {
+                Some(compress) => compress.key(relative_key),
+                None => relative_key.to_owned(),
+            };
+            let target_key = match &object.target_key {
+                Some(target_key) => target_key.clone(),
+                None => target.key(&relative_key),
+            };
+            if *delete {
+                source_keys.insert(relative_key.clone());
+            }
+
+            let collision = match detect_collisions {
+                true => Some(
+                    written_keys
+                        .entry(target_key.clone())
+                        .or_insert_with(|| object.info.key.clone()),
+                )
+                .filter(|first| **first != object.info.key)
+                .cloned(),
+                false => None,
+            };
+
+            let transfer = if let Some(first) = collision {
+                progress.fail(
+                    &object.info.key,
+                    format!("{} is also the target of {}", target_key, first),
+                );
+                false
+            } else if !target.store.accepts_key(&target_key) {
+                progress.emit(TransferEvent::Skipped {
+                    key: object.info.key.clone(),
+                    reason: "not a valid key or file path for the target".to_owned(),
+                });
+                false
+            } else if *sync {
+                let mut source_state = ObjectState::from_info(&object.info);
+                let target_state = target_objects
+                    .get(&relative_key)
+                    .map(ObjectState::from_info);
+                let transfer = match (&compress, encryption) {
+                    // The compressed size is only known once uploaded
+                    (Some(_), _) => source_state.is_newer_than(target_state.as_ref()),
+                    (None, Some(_)) => {
+                        source_state.size = match stores_metadata {
+                            true => ClientEncryption::encrypted_size(source_state.size),
+                            false => ClientEncryption::plaintext_size(source_state.size),
+                        };
+                        source_state.needs_transfer(target_state.as_ref())
+                    }
+                    (None, None) => source_state.needs_transfer(target_state.as_ref()),
+                };
+                if !transfer {
+                    progress.emit(TransferEvent::UpToDate {
+                        key: target_key.clone(),
+                    });
+                }
+                transfer
+            } else {
+                true
+            };
+
+            async move { transfer.then_some((object, target_key, compress)) }
+        })
+        .for_each_concurrent(concurrency, |(object, target_key, compress)| {
+            let progress = progress.clone();
+            async move {
+                transfer_object(
+                    source, target, object, target_key, compress, options, &progress,
+                )
+                .await;
+            }
+        })
+        .await;
+
+    // Unless part of either side could not be listed, or anything else
+    // failed, which would make deleting hazardous
+    if *delete && progress.summary().failed == 0 {
+        stream::iter(target_objects.keys())
+            .filter(|relative_key| std::future::ready(!source_keys.contains(*relative_key)))
+            .for_each_concurrent(concurrency, |relative_key| {
+                let progress = progress.clone();
+                async move {
+                    let key = target.key(relative_key);
+                    match target.store.delete(&key).await {
+                        Ok(_) => progress.emit(TransferEvent::Deleted { key }),
+                        Err(e) => progress.fail(&key, e),
+                    }
+                }
+            })
+            .await;
+    }
+
+    Ok(progress.summary())
+}
+
+/// An object to transfer, listed or named by a manifest.
+pub(crate) struct SourceObject {
+    /// Only the key is known for manifest entries
+    pub(crate) info: ObjectInfo,
+    pub(crate) version_id: Option<String>,
+    /// Key in the target given by a manifest
+    pub(crate) target_key: Option<String>,
+}
+
+/// Copies an object to `target_key`, reporting it to `progress`, and
+/// returns what was written, if anything.
+pub(crate) async fn transfer_object(
+    source: &StorePath,
+    target: &StorePath,
+    object: SourceObject,
+    target_key: String,
+    compress: Option<CompressOptions>,
+    options: &TransferOptions,
+    progress: &Progress,
+) -> Option<WrittenObject> {
+    let stores_metadata = target.store.local_root().is_none();
+    let SourceObject {
+        info: ObjectInfo {
+            key, storage_class, ..
+        },
+        version_id,
+        ..
+    } = object;
+    let source_object = match source.store.get_version(&key, version_id.as_deref()).await {
+        Ok(source_object) => source_object,
+        Err(e) => {
+            progress.fail(&key, read_error(storage_class.as_deref(), e));
+            return None;
+        }
+    };
+    let head = source_object.head;
+    let mut body = source_object.body;
+    let mut put_options = PutOptions::from_head(&head);
+    if stores_metadata && head.tag_count > 0 {
+        put_options.tags = match source.store.get_tags(&key, version_id.as_deref()).await {
+            Ok(tags) => tags,
+            Err(e) => {
+                progress.fail(&key, e);
+                return None;
+            }
+        };
+    }
+    put_options.tags.extend(options.tags.clone());
+
+    // Decrypt client-side encrypted objects on their way out of the stores
+    let encrypted = ClientEncryption::is_encrypted(Some(&head.metadata));
+    if encrypted && !stores_metadata {
+        let Some(encryption) = &options.encryption else {
+            progress.emit(TransferEvent::Skipped {
+                key,
+                reason: "client-side encrypted and no key was given".to_owned(),
+            });
+            return None;
+        };
+        body = match encryption.decrypt(body, &head.metadata) {
+            Ok(decrypted) => Box::new(Box::pin(decrypted)),
+            Err(e) => {
+                progress.fail(&key, e);
+                return None;
+            }
+        };
+    }
+
+    // Compress, then encrypt the body as it is written
+    if let Some(compress) = &compress {
+        body = compress.compress(body);
+        put_options.content_encoding = compress.content_encoding();
+    }
+    if let (false, true, Some(encryption)) = (encrypted, stores_metadata, &options.encryption) {
+        let (encrypted_body, metadata) = encryption.encrypt(body);
+        body = Box::new(Box::pin(encrypted_body)) as ByteReader;
+        put_options
+            .metadata
+            .get_or_insert_with(Default::default)
+            .extend(metadata);
+    }
+
+    let written = match target
+        .store
+        .put_reader(&target_key, body, &put_options)
+        .await
+    {
+        Ok(written) => written,
+        Err(e) => {
+            progress.fail(&target_key, e);
+            return None;
+        }
+    };
+
+    let event = match (source.store.local_root(), target.store.local_root()) {
+        (Some(_), None) => TransferEvent::Uploaded {
+            key: target_key,
+            bytes: written.size,
+            original_bytes: compress.map(|_| head.info.size),
+        },
+        (None, Some(root)) => TransferEvent::Downloaded {
+            path: root.join(&target_key),
+            key,
+            bytes: written.size,
+        },
+        _ => TransferEvent::Copied {
+            key: target_key,
+            bytes: written.size,
+        },
+    };
+    progress.emit(event);
+    Some(written)
+}
@@ -0,0 +1,309 @@
+use clap::Parser;
+use std::path::PathBuf;
+
+use s3_utils::client::Provider;
+use s3_utils::compress::{CompressOptions, Compression, CompressionMode};
+use s3_utils::config::static_credentials;
+use s3_utils::cse::ClientEncryption;
+use s3_utils::filter::{parse_age, TimeFilter};
+use s3_utils::keys::{KeyCase, KeyRules};
+use s3_utils::sse::{ServerSideEncryption, SseOptions};
+use s3_utils::store::{KeyNormalization, LocalKeys, NonUtf8Names, WalkOptions};
+use s3_utils::tags::{tags_from_args, Tags};
+use s3_utils::{
+    copy_objects, download_directory, parse_time, transfer_between, upload_directory, Config,
+    LocalStore, Location, Manifest, RemoteConfig, StorePath, TransferOptions, TransferSummary,
+};
+
+use crate::console::{print_events, print_summary};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+pub struct Args {
+    pub cp: String,
+
+    /// Local directory, `s3://bucket/prefix` or `remote:bucket/prefix`
+    pub source: String,
+
+    /// Local directory, `s3://bucket/prefix` or `remote:bucket/prefix`
+    pub destination: String,
+
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    #[arg(long)]
+    pub region: Option<String>,
+
+    #[arg(long)]
+    pub aws_access_key_id: Option<String>,
+
+    #[arg(long)]
+    pub aws_secret_access_key: Option<String>,
+
+    #[arg(long)]
+    pub endpoint: Option<String>,
+
+    #[arg(long, value_enum)]
+    pub provider: Option<Provider>,
+
+    #[arg(long)]
+    pub account_id: Option<String>,
+
+    #[arg(long)]
+    pub force_path_style: bool,
+
+    #[arg(long)]
+    pub use_http: bool,
+
+    #[arg(long)]
+    pub insecure: bool,
+
+    #[arg(long)]
+    pub ca_bundle: Option<PathBuf>,
+
+    #[arg(long)]
+    pub concurrency: Option<usize>,
+
+    #[arg(long)]
+    pub max_requests_per_second: Option<f64>,
+
+    #[arg(long)]
+    pub flatten: Option<bool>,
+
+    /// With `sync`, delete the destination objects whose source is gone
+    #[arg(long)]
+    pub delete: bool,
+
+    /// Remove this directory prefix from the keys starting with it
+    #[arg(long)]
+    pub strip_prefix: Option<String>,
+
+    /// Rename keys with `s/pattern/replacement/[gi]`, can be repeated
+    #[arg(long)]
+    pub rename: Vec<String>,
+
+    #[arg(long, value_enum)]
+    pub key_case: Option<KeyCase>,
+
+    /// Add this directory prefix to every key
+    #[arg(long)]
+    pub target_prefix: Option<String>,
+
+    /// What to do with file names that are not valid UTF-8
+    #[arg(long, value_enum, default_value_t)]
+    pub non_utf8_names: NonUtf8Names,
+
+    /// Unicode normalization form of the keys of local files
+    #[arg(long, value_enum)]
+    pub normalize_keys: Option<KeyNormalization>,
+
+    /// Upload the targets of symbolic links instead of skipping them
+    #[arg(long)]
+    pub follow_symlinks: bool,
+
+    /// Leave out files and directories whose name starts with `.`
+    #[arg(long)]
+    pub skip_hidden: bool,
+
+    /// Leave out files matched by `.gitignore` and `.s3ignore` files
+    #[arg(long)]
+    pub ignore_files: bool,
+
+    /// Levels of directories to list, 1 for the files of the directory only
+    #[arg(long)]
+    pub max_depth: Option<usize>,
+
+    /// Do not descend into other file systems
+    #[arg(long)]
+    pub one_file_system: bool,
+
+    /// Base64 encoded 256-bit SSE-C key of the source objects
+    #[arg(long)]
+    pub source_sse_customer_key: Option<String>,
+
+    #[arg(long, value_enum)]
+    pub sse: Option<ServerSideEncryption>,
+
+    #[arg(long)]
+    pub sse_kms_key_id: Option<String>,
+
+    /// Base64 encoded 256-bit SSE-C key
+    #[arg(long)]
+    pub sse_customer_key: Option<String>,
+
+    /// Encrypt uploads and decrypt downloads with this 256-bit key (raw or base64)
+    #[arg(long)]
+    pub cse_key_file: Option<PathBuf>,
+
+    /// Encrypt uploads and decrypt downloads with a key derived from this passphrase
+    #[arg(long, env = "S3_UTILS_CSE_PASSPHRASE", hide_env_values = true)]
+    pub cse_passphrase: Option<String>,
+
+    /// Compress files while uploading them
+    #[arg(long, value_enum)]
+    pub compress: Option<Compression>,
+
+    #[arg(long, value_enum)]
+    pub compress_mode: Option<CompressionMode>,
+
+    /// Only compress files matching this glob, can be repeated
+    #[arg(long)]
+    pub compress_glob: Vec<String>,
+
+    /// Tag the written objects with `key=value`, can be repeated
+    #[arg(long)]
+    pub tag: Vec<String>,
+
+    /// Only copy the objects of this file: one key per line, or `.csv` and
+    /// `.jsonl` files with `key`, `version_id` and `target_key` fields
+    #[arg(long)]
+    pub from_manifest: Option<PathBuf>,
+
+    /// Only copy the objects and files last modified at or after this time, e.g.
+    /// `2024-05-01T12:00:00Z`, or a date for its midnight UTC
+    #[arg(long, value_parser = parse_time)]
+    pub modified_after: Option<chrono::DateTime<chrono::Utc>>,
+
+    /// Only copy the objects and files last modified before this time
+    #[arg(long, value_parser = parse_time)]
+    pub modified_before: Option<chrono::DateTime<chrono::Utc>>,
+
+    /// Only copy the objects and files last modified within this age, e.g. `7d`,
+    /// in `s`, `m`, `h`, `d` or `w`
+    #[arg(long, value_parser = parse_age)]
+    pub newer_than: Option<chrono::Duration>,
+
+    /// Only copy the objects and files last modified longer ago than this age,
+    /// e.g. `30d`
+    #[arg(long, value_parser = parse_age)]
+    pub older_than: Option<chrono::Duration>,
+}
+
+/// `cp` and `sync`: copies between any two local directories or buckets.
+/// `sync` only transfers what is missing or outdated.
+pub async fn copy(sync: bool) -> Result<TransferSummary, Box<dyn std::error::Error>> {
+    // Read command-line arguments
+    let args = Args::parse();
+    let config = Config::load(args.config.as_deref())?;
+    let source = Location::parse(&args.source, &config)?;
+    let destination = Location::parse(&args.destination, &config)?;
+
+    // Flags apply to both sides, on top of the settings of their remote
+    let flags = RemoteConfig {
+        provider: args.provider,
+        endpoint: args.endpoint,
+        region: args.region,
+        account_id: args.account_id,
+        credentials: static_credentials(args.aws_access_key_id, args.aws_secret_access_key),
+        force_path_style: args.force_path_style.then_some(true),
+        use_http: args.use_http.then_some(true),
+        insecure: args.insecure.then_some(true),
+        ca_bundle: args.ca_bundle,
+        ..Default::default()
+    };
+
+    let remote_for = |location| -> Result<RemoteConfig, Box<dyn std::error::Error>> {
+        Ok(flags.clone().or(config.remote_for(location)?))
+    };
+
+    let compress =
+        CompressOptions::from_args(args.compress, args.compress_mode, &args.compress_glob)?;
+    if compress.is_some() && !matches!(source, Location::Local(_)) {
+        return Err("--compress only applies to uploads from a local directory".into());
+    }
+
+    let options = TransferOptions {
+        concurrency: args.concurrency,
+        max_requests_per_second: args.max_requests_per_second,
+        sync,
+        delete: args.delete,
+        flatten: args.flatten.unwrap_or_default(),
+        keys: KeyRules::from_args(
+            args.strip_prefix,
+            &args.rename,
+            args.key_case,
+            args.target_prefix,
+        )?,
+        local_keys: LocalKeys {
+            non_utf8: args.non_utf8_names,
+            normalization: args.normalize_keys,
+        },
+        walk: WalkOptions {
+            follow_symlinks: args.follow_symlinks,
+            skip_hidden: args.skip_hidden,
+            ignore_files: args.ignore_files,
+            max_depth: args.max_depth,
+            one_file_system: args.one_file_system,
+        },
+        source_sse: SseOptions::customer_key(args.source_sse_customer_key.as_deref())?,
+        sse: SseOptions::new(
+            args.sse,
+            args.sse_kms_key_id,
+            args.sse_customer_key.as_deref(),
+        )?,
+        encryption: ClientEncryption::from_args(args.cse_key_file.as_deref(), args.cse_passphrase)?,
+        compress,
+        manifest: args
+            .from_manifest
+            .as_deref()
+            .map(Manifest::read)
+            .transpose()?,
+        tags: tags_from_args(&args.tag, "--tag")?,
+        where_tags: Tags::new(),
+        modified: TimeFilter::from_args(
+            args.modified_after,
+            args.modified_before,
+            args.newer_than,
+            args.older_than,
+        )?,
+        events: print_events(),
+    };
+
+    let summary = match (source, destination) {
+        (Location::Local(directory), Location::Bucket(target)) => {
+            upload_directory(directory, &target, &remote_for(&target)?, &options).await?
+        }
+        (Location::Bucket(origin), Location::Local(directory)) => {
+            download_directory(&origin, &remote_for(&origin)?, directory, &options).await?
+        }
+        (Location::Bucket(_), Location::Bucket(_)) | (Location::Local(_), Location::Local(_))
+            if options.encryption.is_some() =>
+        {
+            return Err(
+                "Client-side encryption applies to uploads and downloads, not copies between two buckets or directories"
+                    .into(),
+            );
+        }
+        (Location::Bucket(origin), Location::Bucket(target)) => {
+            copy_objects(
+                &origin,
+                &remote_for(&origin)?,
+                &target,
+                &remote_for(&target)?,
+                &options,
+            )
+            .await?
+        }
+        (Location::Local(origin), Location::Local(target)) => {
+            transfer_between(
+                &StorePath::new(
+                    LocalStore::new(origin)
+                        .with_keys(options.local_keys)
+                        .with_walk(options.walk),
+                    "",
+                ),
+                &StorePath::new(
+                    LocalStore::new(target)
+                        .with_keys(options.local_keys)
+                        .with_walk(options.walk),
+                    "",
+                ),
+                &options,
+            )
+            .await?
+        }
+    };
+    print_summary(&summary);
+
+    Ok(summary)
+}
@@ -0,0 +1,320 @@
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use bytes::Bytes;
+use rand::RngCore;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_util::io::StreamReader;
+
+/// Plaintext bytes per encrypted chunk
+const CHUNK_SIZE: usize = 64 * 1024;
+const TAG_SIZE: usize = 16;
+const NONCE_PREFIX_SIZE: usize = 7;
+const SALT_SIZE: usize = 16;
+const PBKDF2_ROUNDS: u32 = 600_000;
+const WRAP_AAD: &[u8] = b"s3utils-cse-v1";
+
+// Object metadata (`x-amz-meta-*`) holding the envelope
+const META_VERSION: &str = "s3utils-cse";
+const META_WRAPPED_KEY: &str = "s3utils-cse-key";
+const META_NONCE_PREFIX: &str = "s3utils-cse-nonce";
+const META_SALT: &str = "s3utils-cse-salt";
+const VERSION: &str = "v1";
+
+#[derive(Clone)]
+enum Secret {
+    Key(Key<Aes256Gcm>),
+    Passphrase(Arc<String>),
+}
+
+/// Client-side encryption of object bodies.
+///
+/// Every object gets a random data key, wrapped with the master key and
+/// stored in the object metadata along with the nonce prefix. The body is
+/// encrypted with AES-256-GCM in 64 KiB chunks following the STREAM
+/// construction: each chunk nonce is the prefix, the chunk counter and a
+/// flag set on the last chunk, so reordered or truncated bodies fail to
+/// decrypt. With a passphrase the master key is derived with
+/// PBKDF2-HMAC-SHA256 and the salt is stored in the metadata too.
+#[derive(Clone)]
+pub struct ClientEncryption {
+    secret: Secret,
+    upload_key: Key<Aes256Gcm>,
+    upload_salt: Option<[u8; SALT_SIZE]>,
+    derived_keys: Arc<Mutex<HashMap<Vec<u8>, Key<Aes256Gcm>>>>,
+}
+
+impl ClientEncryption {
+    pub fn from_args(
+        key_file: Option<&Path>,
+        passphrase: Option<String>,
+    ) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        match (key_file, passphrase) {
+            (Some(_), Some(_)) => {
+                Err("--cse-key-file and --cse-passphrase are mutually exclusive".into())
+            }
+            (Some(key_file), None) => Ok(Some(ClientEncryption::from_key_file(key_file)?)),
+            (None, Some(passphrase)) => Ok(Some(ClientEncryption::from_passphrase(passphrase))),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Reads a 256-bit key, either as 32 raw bytes or base64 encoded.
+    pub fn from_key_file(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let contents = std::fs::read(path)
+            .map_err(|e| format!("Failed to read key file {}: {}", path.display(), e))?;
+        let key = match contents.len() {
+            32 => contents,
+            _ => BASE64
+                .decode(String::from_utf8_lossy(&contents).trim())
+                .map_err(|_| "Key file must hold 32 raw bytes or their base64 encoding")?,
+        };
+        if key.len() != 32 {
+            return Err("Key file must hold a 256-bit key".into());
+        }
+
+        let key = *Key::<Aes256Gcm>::from_slice(&key);
+        Ok(ClientEncryption {
+            secret: Secret::Key(key),
+            upload_key: key,
+            upload_salt: None,
+            derived_keys: Default::default(),
+        })
+    }
+
+    pub fn from_passphrase(passphrase: String) -> Self {
+        let mut salt = [0u8; SALT_SIZE];
+        rand::thread_rng().fill_bytes(&mut salt);
+        let upload_key = derive_key(&passphrase, &salt);
+
+        ClientEncryption {
+            secret: Secret::Passphrase(Arc::new(passphrase)),
+            upload_key,
+            upload_salt: Some(salt),
+            derived_keys: Arc::new(Mutex::new(HashMap::from([(salt.to_vec(), upload_key)]))),
+        }
+    }
+
+    pub fn is_encrypted(metadata: Option<&HashMap<String, String>>) -> bool {
+        metadata
+            .and_then(|metadata| metadata.get(META_VERSION))
+            .is_some()
+    }
+
+    /// Size of the encrypted body of a `size` bytes plaintext.
+    pub fn encrypted_size(size: u64) -> u64 {
+        let chunks = size.div_ceil(CHUNK_SIZE as u64).max(1);
+        size + chunks * TAG_SIZE as u64
+    }
+
+    /// Size of the plaintext of a `size` bytes encrypted body.
+    pub fn plaintext_size(size: u64) -> u64 {
+        let chunks = size.div_ceil((CHUNK_SIZE + TAG_SIZE) as u64).max(1);
+        size.saturating_sub(chunks * TAG_SIZE as u64)
+    }
+
+    fn master_key(&self, salt: Option<&str>) -> Result<Key<Aes256Gcm>, Box<dyn std::error::Error>> {
+        match (&self.secret, salt) {
+            (Secret::Key(key), None) => Ok(*key),
+            (Secret::Passphrase(passphrase), Some(salt)) => {
+                let salt = BASE64.decode(salt)?;
+                let mut derived_keys = self.derived_keys.lock().unwrap();
+                let key = derived_keys
+                    .entry(salt.clone())
+                    .or_insert_with(|| derive_key(passphrase, &salt));
+                Ok(*key)
+            }
+            (Secret::Key(_), Some(_)) => {
+                Err("Object was encrypted with a passphrase, not a key file".into())
+            }
+            (Secret::Passphrase(_), None) => {
+                Err("Object was encrypted with a key file, not a passphrase".into())
+            }
+        }
+    }
+
+    /// Encrypts `reader` as it is read, returning the encrypted body and the
+    /// metadata to store with the object.
+    pub fn encrypt<R>(&self, reader: R) -> (impl AsyncRead, HashMap<String, String>)
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+    {
+        let mut data_key = [0u8; 32];
+        let mut nonce_prefix = [0u8; NONCE_PREFIX_SIZE];
+        rand::thread_rng().fill_bytes(&mut data_key);
+        rand::thread_rng().fill_bytes(&mut nonce_prefix);
+
+        let mut metadata = HashMap::from([
+            (META_VERSION.to_owned(), VERSION.to_owned()),
+            (
+                META_WRAPPED_KEY.to_owned(),
+                wrap_key(&self.upload_key, &data_key),
+            ),
+            (META_NONCE_PREFIX.to_owned(), BASE64.encode(nonce_prefix)),
+        ]);
+        if let Some(salt) = self.upload_salt {
+            metadata.insert(META_SALT.to_owned(), BASE64.encode(salt));
+        }
+
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&data_key));
+        let body = transform_chunks(reader, CHUNK_SIZE, move |index, chunk, last| {
+            cipher
+                .encrypt(&chunk_nonce(&nonce_prefix, index, last), chunk)
+                .map_err(|_| io::Error::other("Encryption failed"))
+        });
+
+        (body, metadata)
+    }
+
+    /// Decrypts the body of an object encrypted by [`ClientEncryption::encrypt`].
+    pub fn decrypt<R>(
+        &self,
+        reader: R,
+        metadata: &HashMap<String, String>,
+    ) -> Result<impl AsyncRead, Box<dyn std::error::Error>>
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+    {
+        let version = metadata.get(META_VERSION).map(String::as_str);
+        if version != Some(VERSION) {
+            return Err(format!("Unsupported client-side encryption: {:?}", version).into());
+        }
+
+        let wrapped_key = metadata
+            .get(META_WRAPPED_KEY)
+            .ok_or("Missing wrapped data key")?;
+        let nonce_prefix = BASE64.decode(
+            metadata
+                .get(META_NONCE_PREFIX)
+                .ok_or("Missing nonce prefix")?,
+        )?;
+        if nonce_prefix.len() != NONCE_PREFIX_SIZE {
+            return Err("Invalid nonce prefix".into());
+        }
+
+        let master_key = self.master_key(metadata.get(META_SALT).map(String::as_str))?;
+        let data_key = unwrap_key(&master_key, wrapped_key)?;
+        let cipher = Aes256Gcm::new(&data_key);
+
+        Ok(transform_chunks(
+            reader,
+            CHUNK_SIZE + TAG_SIZE,
+            move |index, chunk, last| {
+                cipher
+                    .decrypt(&chunk_nonce(&nonce_prefix, index, last), chunk)
+                    .map_err(|_| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "Decryption failed: wrong key or corrupted object",
+                        )
+                    })
+            },
+        ))
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Key<Aes256Gcm> {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, PBKDF2_ROUNDS, &mut key);
+    *Key::<Aes256Gcm>::from_slice(&key)
+}
+
+fn wrap_key(master_key: &Key<Aes256Gcm>, data_key: &[u8]) -> String {
+    let mut nonce = [0u8; 12];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let payload = Payload {
+        msg: data_key,
+        aad: WRAP_AAD,
+    };
+    let wrapped = Aes256Gcm::new(master_key)
+        .encrypt(Nonce::from_slice(&nonce), payload)
+        .expect("Failed to wrap data key");
+
+    BASE64.encode([nonce.as_slice(), &wrapped].concat())
+}
+
+fn unwrap_key(
+    master_key: &Key<Aes256Gcm>,
+    wrapped_key: &str,
+) -> Result<Key<Aes256Gcm>, Box<dyn std::error::Error>> {
+    let wrapped_key = BASE64.decode(wrapped_key)?;
+    if wrapped_key.len() < 12 {
+        return Err("Invalid wrapped data key".into());
+    }
+
+    let (nonce, wrapped) = wrapped_key.split_at(12);
+    let payload = Payload {
+        msg: wrapped,
+        aad: WRAP_AAD,
+    };
+    let data_key = Aes256Gcm::new(master_key)
+        .decrypt(Nonce::from_slice(nonce), payload)
+        .map_err(|_| "Failed to unwrap data key: wrong key or passphrase")?;
+
+    Ok(*Key::<Aes256Gcm>::from_slice(&data_key))
+}
+
+fn chunk_nonce(prefix: &[u8], index: u32, last: bool) -> Nonce<aes_gcm::aead::consts::U12> {
+    let mut nonce = [0u8; 12];
+    nonce[..NONCE_PREFIX_SIZE].copy_from_slice(prefix);
+    nonce[NONCE_PREFIX_SIZE..11].copy_from_slice(&index.to_be_bytes());
+    nonce[11] = last as u8;
+    *Nonce::from_slice(&nonce)
+}
+
+/// Streams `reader` through `transform` in `chunk_size` chunks, reading one
+/// chunk ahead to tell `transform` which chunk is the last one.
+fn transform_chunks<R, F>(reader: R, chunk_size: usize, mut transform: F) -> impl AsyncRead
+where
+    R: AsyncRead + Unpin + Send + 'static,
+    F: FnMut(u32, &[u8], bool) -> io::Result<Vec<u8>> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel::<io::Result<Bytes>>(4);
+
+    tokio::spawn(async move {
+        let mut reader = reader;
+        let result: io::Result<()> = async {
+            let mut index = 0u32;
+            let mut chunk = read_chunk(&mut reader, chunk_size).await?;
+            loop {
+                let next = read_chunk(&mut reader, chunk_size).await?;
+                let last = next.is_empty();
+                let output = transform(index, &chunk, last)?;
+                if tx.send(Ok(Bytes::from(output))).await.is_err() || last {
+                    return Ok(());
+                }
+                index = index
+                    .checked_add(1)
+                    .ok_or_else(|| io::Error::other("Object too large"))?;
+                chunk = next;
+            }
+        }
+        .await;
+
+        if let Err(e) = result {
+            let _ = tx.send(Err(e)).await;
+        }
+    });
+
+    StreamReader::new(ReceiverStream::new(rx))
+}
+
+async fn read_chunk<R>(reader: &mut R, chunk_size: usize) -> io::Result<Vec<u8>>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut chunk = Vec::with_capacity(chunk_size);
+    reader
+        .take(chunk_size as u64)
+        .read_to_end(&mut chunk)
+        .await?;
+    Ok(chunk)
+}
@@ -0,0 +1,140 @@
+use futures_util::stream::{self, BoxStream};
+use futures_util::StreamExt;
+
+use crate::config::{RemoteConfig, RemotePath};
+use crate::store::{ObjectInfo, ObjectVersion, S3Store, StorePath};
+use crate::tags::is_tagged;
+use crate::transfer::{Progress, TransferEvent, TransferOptions, TransferSummary};
+
+/// Deletes the objects under `location` with all their versions, then the
+/// bucket itself unless `location` has a prefix or `keep_bucket` is set.
+/// With a manifest, a tag filter or a date filter, only its objects are
+/// deleted, and the bucket is kept.
+pub async fn delete_objects(
+    location: &RemotePath,
+    remote: &RemoteConfig,
+    keep_bucket: bool,
+    options: &TransferOptions,
+) -> Result<TransferSummary, Box<dyn std::error::Error>> {
+    let concurrency = options.concurrency(remote.concurrency, 10);
+    let throttle = options.throttle(concurrency, remote.max_requests_per_second);
+    let store = S3Store::connect(remote, &location.bucket, throttle)?;
+    let prefix = location.key_prefix();
+    let delete_bucket = !keep_bucket
+        && prefix.is_empty()
+        && options.manifest.is_none()
+        && options.where_tags.is_empty()
+        && options.modified.is_empty();
+
+    delete_from(
+        &StorePath::new(store, prefix),
+        delete_bucket,
+        &TransferOptions {
+            concurrency: Some(concurrency),
+            ..options.clone()
+        },
+    )
+    .await
+}
+
+/// Deletes every version of the objects under `location`, or the objects
+/// and versions of the manifest, then its bucket when asked to.
+///
+/// Manifest keys outside of the prefix of `location` are skipped, as are
+/// versions without the `where_tags` of `options` and, then, delete markers,
+/// and versions last modified outside of its `modified` window. Delete
+/// markers are kept under a window too, as removing one would bring back
+/// the version under it.
+pub async fn delete_from(
+    location: &StorePath,
+    delete_bucket: bool,
+    options: &TransferOptions,
+) -> Result<TransferSummary, Box<dyn std::error::Error>> {
+    if options.manifest.is_some() && !options.modified.is_empty() {
+        return Err("A manifest has no modification dates to filter by".into());
+    }
+    let concurrency = options.concurrency.unwrap_or(10);
+    let progress = Progress::new(options.events.clone());
+
+    let versions: BoxStream<_> = match &options.manifest {
+        Some(manifest) => stream::iter(manifest.entries())
+            .map(|entry| {
+                Ok(ObjectVersion {
+                    info: ObjectInfo {
+                        key: entry.key.clone(),
+                        ..Default::default()
+                    },
+                    version_id: entry.version_id.clone(),
+                    ..Default::default()
+                })
+            })
+            .boxed(),
+        None => location.store.list_versions(&location.prefix),
+    };
+
+    versions
+        .filter_map(|version| {
+            let version = match version {
+                Ok(version) if !version.info.key.starts_with(&location.prefix) => {
+                    progress.emit(TransferEvent::Skipped {
+                        key: version.info.key,
+                        reason: "outside of the prefix".to_owned(),
+                    });
+                    None
+                }
+                Ok(version) if !options.modified.matches(version.info.modified) => None,
+                Ok(version) if version.delete_marker && !options.modified.is_empty() => None,
+                Ok(version) => Some(version),
+                Err(e) => {
+                    progress.emit(TransferEvent::ListingFailed {
+                        prefix: location.prefix.clone(),
+                        error: e.to_string(),
+                    });
+                    None
+                }
+            };
+            async move { version }
+        })
+        .for_each_concurrent(concurrency, |version| {
+            let progress = progress.clone();
+            async move {
+                if !options.where_tags.is_empty() {
+                    let tagged = match version.delete_marker {
+                        true => Ok(false),
+                        false => {
+                            is_tagged(
+                                location.store.as_ref(),
+                                &version.info.key,
+                                version.version_id.as_deref(),
+                                &options.where_tags,
+                            )
+                            .await
+                        }
+                    };
+                    match tagged {
+                        Ok(true) => {}
+                        Ok(false) => return,
+                        Err(e) => return progress.fail(&version.info.key, e),
+                    }
+                }
+                let key = version.info.key;
+                match location
+                    .store
+                    .delete_version(&key, version.version_id.as_deref())
+                    .await
+                {
+                    Ok(_) => progress.emit(TransferEvent::Deleted { key }),
+                    Err(e) => progress.fail(&key, e),
+                }
+            }
+        })
+        .await;
+
+    if delete_bucket {
+        if let Err(e) = location.store.delete_bucket().await {
+            progress.fail(&location.prefix, e);
+        }
+    }
+
+    Ok(progress.summary())
+}
@@ -1,115 +1,141 @@
 use clap::Parser;
-use futures_util::StreamExt;
-use rusoto_core::Region;
-use rusoto_credential::StaticProvider;
-use rusoto_s3::{DeleteObjectRequest, ListObjectsV2Request, S3Client, S3};
-use tokio::sync::mpsc;
+use std::path::PathBuf;
+
+use s3_utils::client::Provider;
+use s3_utils::config::{resolve_location, static_credentials};
+use s3_utils::filter::{parse_age, TimeFilter};
+use s3_utils::tags::tags_from_args;
+use s3_utils::{
+    delete_objects, parse_time, Config, Manifest, RemoteConfig, TransferOptions, TransferSummary,
+};
+
+use crate::console::{print_events, print_summary};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
     pub db: Option<String>,
 
+    /// Bucket to delete, `[remote:]bucket[/prefix]`. With a prefix only the
+    /// objects under it are deleted and the bucket is kept.
+    pub location: Option<String>,
+
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
     #[arg(long)]
-    pub bucket: String,
+    pub bucket: Option<String>,
 
     #[arg(long)]
-    pub region: String,
+    pub region: Option<String>,
 
     #[arg(long)]
-    pub aws_access_key_id: String,
+    pub aws_access_key_id: Option<String>,
 
     #[arg(long)]
-    pub aws_secret_access_key: String,
+    pub aws_secret_access_key: Option<String>,
 
     #[arg(long)]
     pub endpoint: Option<String>,
 
+    #[arg(long, value_enum)]
+    pub provider: Option<Provider>,
+
+    #[arg(long)]
+    pub account_id: Option<String>,
+
+    #[arg(long)]
+    pub force_path_style: bool,
+
+    #[arg(long)]
+    pub use_http: bool,
+
+    #[arg(long)]
+    pub insecure: bool,
+
+    #[arg(long)]
+    pub ca_bundle: Option<PathBuf>,
+
     #[arg(long)]
     pub concurrency: Option<usize>,
+
+    #[arg(long)]
+    pub max_requests_per_second: Option<f64>,
+
+    /// Only delete the objects of this file, keeping the bucket: one key per
+    /// line, or `.csv` and `.jsonl` files with `key` and `version_id` fields
+    #[arg(long)]
+    pub from_manifest: Option<PathBuf>,
+
+    /// Only delete the objects tagged `key=value`, keeping the bucket, can
+    /// be repeated
+    #[arg(long)]
+    pub where_tag: Vec<String>,
+
+    /// Only delete the objects and versions last modified at or after this time, e.g.
+    /// `2024-05-01T12:00:00Z`, or a date for its midnight UTC
+    #[arg(long, value_parser = parse_time)]
+    pub modified_after: Option<chrono::DateTime<chrono::Utc>>,
+
+    /// Only delete the objects and versions last modified before this time
+    #[arg(long, value_parser = parse_time)]
+    pub modified_before: Option<chrono::DateTime<chrono::Utc>>,
+
+    /// Only delete the objects and versions last modified within this age, e.g. `7d`,
+    /// in `s`, `m`, `h`, `d` or `w`
+    #[arg(long, value_parser = parse_age)]
+    pub newer_than: Option<chrono::Duration>,
+
+    /// Only delete the objects and versions last modified longer ago than this age,
+    /// e.g. `30d`
+    #[arg(long, value_parser = parse_age)]
+    pub older_than: Option<chrono::Duration>,
+
+    /// Keep the bucket, even once it is empty
+    #[arg(long)]
+    pub keep_bucket: bool,
 }
 
-pub async fn delete_bucket() -> Result<(), Box<dyn std::error::Error>> {
+pub async fn delete_bucket() -> Result<TransferSummary, Box<dyn std::error::Error>> {
     // Read command-line arguments
     let args = Args::parse();
-    let concurrency = args.concurrency.unwrap_or(10);
-    let bucket = args.bucket;
-    let endpoint = args
-        .endpoint
-        .unwrap_or(format!("s3.{}.amazonaws.com", args.region.clone()));
-
-    // Create AWS credentials provider
-    let credentials_provider =
-        StaticProvider::new_minimal(args.aws_access_key_id, args.aws_secret_access_key);
-
-    // Create S3 clients for origin and target regions
-    let client = S3Client::new_with(
-        rusoto_core::HttpClient::new().expect("Failed to create HTTP client"),
-        credentials_provider,
-        Region::Custom {
-            name: args.region.clone(),
-            endpoint,
-        },
-    );
-
-    let (tx, rx) = mpsc::channel(concurrency);
-    tokio::spawn({
-        let client = client.clone();
-        let bucket = bucket.clone();
-
-        async move {
-            let mut continuation_token = None;
-            loop {
-                let list_objects_request = ListObjectsV2Request {
-                    bucket: bucket.to_owned(),
-                    continuation_token: continuation_token.clone(),
-                    ..Default::default()
-                };
-
-                let list_objects_output =
-                    client.list_objects_v2(list_objects_request).await.unwrap();
-
-                if let Some(contents) = list_objects_output.contents {
-                    for object in contents {
-                        tx.send(object.key.unwrap()).await.unwrap();
-                    }
-                }
-
-                if !list_objects_output.is_truncated.unwrap_or_default() {
-                    break;
-                }
-
-                continuation_token = list_objects_output.next_continuation_token;
-            }
-        }
-    });
-
-    tokio_stream::wrappers::ReceiverStream::new(rx)
-        .for_each_concurrent(concurrency, |key| {
-            let key = key.clone();
-            let client = client.clone();
-            let bucket = bucket.clone();
-
-            async move {
-                let delete_object_request = DeleteObjectRequest {
-                    bucket: bucket.to_owned(),
-                    key: key.clone(),
-                    bypass_governance_retention: Some(true),
-                    ..Default::default()
-                };
-
-                client.delete_object(delete_object_request).await.ok();
-                println!("Deleted {}", key);
-            }
-        })
-        .await;
-
-    let delete_bucket_request = rusoto_s3::DeleteBucketRequest {
-        bucket: bucket.to_owned(),
+    let config = Config::load(args.config.as_deref())?;
+    let location = resolve_location(args.location.as_deref(), args.bucket, "--bucket")?;
+    let remote = RemoteConfig {
+        provider: args.provider,
+        endpoint: args.endpoint,
+        region: args.region,
+        account_id: args.account_id,
+        credentials: static_credentials(args.aws_access_key_id, args.aws_secret_access_key),
+        force_path_style: args.force_path_style.then_some(true),
+        use_http: args.use_http.then_some(true),
+        insecure: args.insecure.then_some(true),
+        ca_bundle: args.ca_bundle,
+        ..Default::default()
+    }
+    .or(config.remote_for(&location)?);
+
+    let options = TransferOptions {
+        concurrency: args.concurrency,
+        max_requests_per_second: args.max_requests_per_second,
+        manifest: args
+            .from_manifest
+            .as_deref()
+            .map(Manifest::read)
+            .transpose()?,
+        where_tags: tags_from_args(&args.where_tag, "--where-tag")?,
+        modified: TimeFilter::from_args(
+            args.modified_after,
+            args.modified_before,
+            args.newer_than,
+            args.older_than,
+        )?,
+        events: print_events(),
         ..Default::default()
     };
 
-    let _ = client.delete_bucket(delete_bucket_request).await.ok();
+    let summary = delete_objects(&location, &remote, args.keep_bucket, &options).await?;
+    print_summary(&summary);
 
-    return Ok(());
+    Ok(summary)
 }
@@ -0,0 +1,229 @@
+use clap::{Parser, ValueEnum};
+use std::path::PathBuf;
+
+use s3_utils::client::Provider;
+use s3_utils::config::static_credentials;
+use s3_utils::{
+    diff, Config, DiffEntry, DiffOptions, DiffReport, DiffStatus, EventHandler, LocalStore,
+    Location, RemoteConfig, S3Store, StorePath, TransferEvent, TransferOptions, TransferSummary,
+};
+
+use crate::console::csv_row;
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+    Csv,
+}
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+pub struct Args {
+    pub diff: String,
+
+    /// Local directory, `s3://bucket/prefix` or `remote:bucket/prefix`
+    pub origin: String,
+
+    /// Local directory, `s3://bucket/prefix` or `remote:bucket/prefix`
+    pub target: String,
+
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    #[arg(long)]
+    pub region: Option<String>,
+
+    #[arg(long)]
+    pub aws_access_key_id: Option<String>,
+
+    #[arg(long)]
+    pub aws_secret_access_key: Option<String>,
+
+    #[arg(long)]
+    pub endpoint: Option<String>,
+
+    #[arg(long, value_enum)]
+    pub provider: Option<Provider>,
+
+    #[arg(long)]
+    pub account_id: Option<String>,
+
+    #[arg(long)]
+    pub force_path_style: bool,
+
+    #[arg(long)]
+    pub use_http: bool,
+
+    #[arg(long)]
+    pub insecure: bool,
+
+    #[arg(long)]
+    pub ca_bundle: Option<PathBuf>,
+
+    #[arg(long)]
+    pub concurrency: Option<usize>,
+
+    #[arg(long)]
+    pub max_requests_per_second: Option<f64>,
+
+    /// Compare the MD5 of objects whose ETags are not one, reading them,
+    /// instead of reporting them as unverified
+    #[arg(long)]
+    pub checksum: bool,
+
+    #[arg(long, value_enum, default_value_t)]
+    pub format: OutputFormat,
+}
+
+/// `diff`: compares two buckets, or a bucket and a local directory, and
+/// reports the keys that do not match.
+///
+/// Differences count as failures, so the exit status tells whether both
+/// sides match.
+pub async fn compare() -> Result<TransferSummary, Box<dyn std::error::Error>> {
+    // Read command-line arguments
+    let args = Args::parse();
+    let config = Config::load(args.config.as_deref())?;
+    let origin = Location::parse(&args.origin, &config)?;
+    let target = Location::parse(&args.target, &config)?;
+
+    // Flags apply to both sides, on top of the settings of their remote
+    let flags = RemoteConfig {
+        provider: args.provider,
+        endpoint: args.endpoint,
+        region: args.region,
+        account_id: args.account_id,
+        credentials: static_credentials(args.aws_access_key_id, args.aws_secret_access_key),
+        force_path_style: args.force_path_style.then_some(true),
+        use_http: args.use_http.then_some(true),
+        insecure: args.insecure.then_some(true),
+        ca_bundle: args.ca_bundle,
+        ..Default::default()
+    };
+
+    // Remotes of the buckets, whose settings the throttle falls back to
+    let remotes = [&origin, &target]
+        .into_iter()
+        .filter_map(|location| match location {
+            Location::Bucket(path) => Some(path),
+            Location::Local(_) => None,
+        })
+        .map(|path| Ok(flags.clone().or(config.remote_for(path)?)))
+        .collect::<Result<Vec<_>, Box<dyn std::error::Error>>>()?;
+
+    let transfer_options = TransferOptions {
+        concurrency: args.concurrency,
+        max_requests_per_second: args.max_requests_per_second,
+        // Keep stdout for the report
+        events: EventHandler::new(|event| match event {
+            TransferEvent::ListingFailed { prefix, error } => {
+                eprintln!("Error listing {}: {}", prefix, error)
+            }
+            TransferEvent::Throttled { concurrency } => eprintln!(
+                "Throttled by provider, concurrency reduced to {}",
+                concurrency
+            ),
+            _ => {}
+        }),
+        ..Default::default()
+    };
+    let concurrency =
+        transfer_options.concurrency(remotes.iter().find_map(|remote| remote.concurrency), 10);
+    let throttle = transfer_options.throttle(
+        concurrency,
+        remotes
+            .iter()
+            .find_map(|remote| remote.max_requests_per_second),
+    );
+    let open = |location: Location| -> Result<StorePath, Box<dyn std::error::Error>> {
+        Ok(match location {
+            Location::Local(directory) => {
+                if !directory.is_dir() {
+                    return Err(format!("Not a directory: {}", directory.display()).into());
+                }
+                StorePath::new(LocalStore::new(directory), "")
+            }
+            Location::Bucket(path) => {
+                let remote = flags.clone().or(config.remote_for(&path)?);
+                let store = S3Store::connect(&remote, &path.bucket, throttle.clone())?;
+                StorePath::new(store, path.key_prefix())
+            }
+        })
+    };
+
+    let options = DiffOptions {
+        checksum: args.checksum,
+        concurrency: Some(concurrency),
+        events: transfer_options.events.clone(),
+    };
+    let report = diff(&open(origin)?, &open(target)?, &options).await?;
+
+    match args.format {
+        OutputFormat::Text => print_text(&report),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+        OutputFormat::Csv => print_csv(&report),
+    }
+
+    Ok(TransferSummary {
+        transferred: report.matching,
+        failed: report.differences.len() as u64 + report.errors,
+        ..Default::default()
+    })
+}
+
+fn print_text(report: &DiffReport) {
+    for entry in &report.differences {
+        match entry.status {
+            DiffStatus::OnlyInOrigin => println!("Only in origin: {}", entry.key),
+            DiffStatus::OnlyInTarget => println!("Only in target: {}", entry.key),
+            DiffStatus::SizeDiffers => println!(
+                "Size differs: {} ({} != {} bytes)",
+                entry.key,
+                entry.origin_size.unwrap_or_default(),
+                entry.target_size.unwrap_or_default()
+            ),
+            DiffStatus::ContentDiffers => println!("Content differs: {}", entry.key),
+            DiffStatus::Unverified => println!("Unverified: {}", entry.key),
+        }
+    }
+    println!(
+        "Done: {} matching, {} only in origin, {} only in target, {} different, {} unverified, {} errors",
+        report.matching,
+        report.only_in_origin,
+        report.only_in_target,
+        report.different,
+        report.unverified,
+        report.errors
+    );
+}
+
+fn print_csv(report: &DiffReport) {
+    println!(
+        "{}",
+        csv_row(&[
+            "key",
+            "status",
+            "origin_size",
+            "target_size",
+            "origin_etag",
+            "target_etag"
+        ])
+    );
+    for entry in &report.differences {
+        println!("{}", csv_row(&csv_fields(entry)));
+    }
+}
+
+fn csv_fields(entry: &DiffEntry) -> [String; 6] {
+    let optional = |value: Option<String>| value.unwrap_or_default();
+    [
+        entry.key.clone(),
+        entry.status.as_str().to_owned(),
+        optional(entry.origin_size.map(|size| size.to_string())),
+        optional(entry.target_size.map(|size| size.to_string())),
+        optional(entry.origin_e_tag.clone()),
+        optional(entry.target_e_tag.clone()),
+    ]
+}
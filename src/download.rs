@@ -0,0 +1,34 @@
+use std::path::Path;
+
+use crate::config::{RemoteConfig, RemotePath};
+use crate::copy::transfer_between;
+use crate::store::{LocalStore, S3Store, StorePath};
+use crate::transfer::{TransferOptions, TransferSummary};
+
+/// Downloads the objects under `origin` into `directory`.
+pub async fn download_directory(
+    origin: &RemotePath,
+    remote: &RemoteConfig,
+    directory: impl AsRef<Path>,
+    options: &TransferOptions,
+) -> Result<TransferSummary, Box<dyn std::error::Error>> {
+    let concurrency = options.concurrency(remote.concurrency, 50);
+    let throttle = options.throttle(concurrency, remote.max_requests_per_second);
+    let store =
+        S3Store::connect(remote, &origin.bucket, throttle)?.with_sse(options.source_sse.clone());
+
+    transfer_between(
+        &StorePath::new(store, origin.key_prefix()),
+        &StorePath::new(
+            LocalStore::new(directory.as_ref())
+                .with_keys(options.local_keys)
+                .with_walk(options.walk),
+            "",
+        ),
+        &TransferOptions {
+            concurrency: Some(concurrency),
+            ..options.clone()
+        },
+    )
+    .await
+}
@@ -0,0 +1,172 @@
+use clap::Parser;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use s3_utils::client::Provider;
+use s3_utils::config::static_credentials;
+use s3_utils::throttle::Throttle;
+use s3_utils::{
+    usage, Config, EventHandler, LocalStore, Location, RemoteConfig, S3Store, StorePath,
+    TransferEvent, TransferSummary, UsageOptions, UsageTotals,
+};
+
+use crate::console::format_bytes;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+pub struct Args {
+    pub du: String,
+
+    /// `s3://bucket/prefix`, `remote:bucket/prefix` or a local directory
+    pub location: String,
+
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    #[arg(long)]
+    pub region: Option<String>,
+
+    #[arg(long)]
+    pub aws_access_key_id: Option<String>,
+
+    #[arg(long)]
+    pub aws_secret_access_key: Option<String>,
+
+    #[arg(long)]
+    pub endpoint: Option<String>,
+
+    #[arg(long, value_enum)]
+    pub provider: Option<Provider>,
+
+    #[arg(long)]
+    pub account_id: Option<String>,
+
+    #[arg(long)]
+    pub force_path_style: bool,
+
+    #[arg(long)]
+    pub use_http: bool,
+
+    #[arg(long)]
+    pub insecure: bool,
+
+    #[arg(long)]
+    pub ca_bundle: Option<PathBuf>,
+
+    #[arg(long)]
+    pub max_requests_per_second: Option<f64>,
+
+    /// Levels of prefixes to show
+    #[arg(long, default_value_t = 1)]
+    pub depth: usize,
+
+    /// Count noncurrent versions too
+    #[arg(long)]
+    pub versions: bool,
+}
+
+/// `du`: totals the objects of a bucket by prefix, storage class and age.
+pub async fn disk_usage() -> Result<TransferSummary, Box<dyn std::error::Error>> {
+    // Read command-line arguments
+    let args = Args::parse();
+    let config = Config::load(args.config.as_deref())?;
+
+    let location = match Location::parse(&args.location, &config)? {
+        Location::Local(directory) => {
+            if !directory.is_dir() {
+                return Err(format!("Not a directory: {}", directory.display()).into());
+            }
+            StorePath::new(LocalStore::new(directory), "")
+        }
+        Location::Bucket(path) => {
+            let remote = RemoteConfig {
+                provider: args.provider,
+                endpoint: args.endpoint,
+                region: args.region,
+                account_id: args.account_id,
+                credentials: static_credentials(args.aws_access_key_id, args.aws_secret_access_key),
+                force_path_style: args.force_path_style.then_some(true),
+                use_http: args.use_http.then_some(true),
+                insecure: args.insecure.then_some(true),
+                ca_bundle: args.ca_bundle,
+                ..Default::default()
+            }
+            .or(config.remote_for(&path)?);
+            let max_requests_per_second = args
+                .max_requests_per_second
+                .or(remote.max_requests_per_second);
+            let throttle = Throttle::new(1, max_requests_per_second);
+            let store = S3Store::connect(&remote, &path.bucket, throttle)?;
+            StorePath::new(store, path.key_prefix())
+        }
+    };
+
+    let options = UsageOptions {
+        depth: args.depth,
+        versions: args.versions,
+        events: EventHandler::new(|event| {
+            if let TransferEvent::ListingFailed { prefix, error } = event {
+                println!("Error listing {}: {}", prefix, error);
+            }
+        }),
+    };
+    let report = usage(&location, &options).await?;
+
+    println!("Prefixes:");
+    print_line(&report.total, 0, &args.location);
+    print_tree(&report.prefixes, "", 1);
+
+    println!("Storage classes:");
+    let mut storage_classes = report.storage_classes.iter().collect::<Vec<_>>();
+    storage_classes.sort_by_key(|(_, totals)| std::cmp::Reverse(totals.bytes));
+    for (storage_class, totals) in storage_classes {
+        print_line(totals, 0, storage_class);
+    }
+
+    println!("Ages:");
+    for (age, totals) in &report.ages {
+        print_line(totals, 0, age.label());
+    }
+
+    println!(
+        "Done: {} objects ({} bytes), {} errors",
+        report.total.objects, report.total.bytes, report.errors
+    );
+
+    Ok(TransferSummary {
+        transferred: report.total.objects,
+        bytes: report.total.bytes,
+        failed: report.errors,
+        ..Default::default()
+    })
+}
+
+/// Prints the prefixes directly under `parent`, largest first, each
+/// followed by its own.
+fn print_tree(prefixes: &BTreeMap<String, UsageTotals>, parent: &str, level: usize) {
+    let mut children = prefixes
+        .iter()
+        .filter(|(prefix, _)| {
+            prefix
+                .strip_prefix(parent)
+                .and_then(|name| name.strip_suffix('/'))
+                .is_some_and(|name| !name.is_empty() && !name.contains('/'))
+        })
+        .collect::<Vec<_>>();
+    children.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then(a.0.cmp(b.0)));
+
+    for (prefix, totals) in children {
+        print_line(totals, level, &prefix[parent.len()..]);
+        print_tree(prefixes, prefix, level + 1);
+    }
+}
+
+fn print_line(totals: &UsageTotals, level: usize, name: &str) {
+    println!(
+        "{:>12} {:>10} objects  {}{}",
+        format_bytes(totals.bytes),
+        totals.objects,
+        "  ".repeat(level),
+        name
+    );
+}
@@ -1,117 +1,259 @@
 use clap::Parser;
-use futures_util::StreamExt;
-use rusoto_core::Region;
-use rusoto_credential::StaticProvider;
-use rusoto_s3::S3Client;
 use std::path::PathBuf;
-use tokio::fs::File;
-use tokio::sync::mpsc;
-use walkdir::WalkDir;
+use std::time::Duration;
 
-use crate::utils::{get_file_key, upload_s3_object};
+use s3_utils::client::Provider;
+use s3_utils::compress::{CompressOptions, Compression, CompressionMode};
+use s3_utils::config::{resolve_location, static_credentials};
+use s3_utils::cse::ClientEncryption;
+use s3_utils::filter::{parse_age, TimeFilter};
+use s3_utils::keys::{KeyCase, KeyRules};
+use s3_utils::sse::{ServerSideEncryption, SseOptions};
+use s3_utils::store::{KeyNormalization, LocalKeys, NonUtf8Names, WalkOptions};
+use s3_utils::tags::tags_from_args;
+use s3_utils::{
+    parse_time, upload_directory, watch_directory, Config, RemoteConfig, TransferOptions,
+    TransferSummary, WatchOptions,
+};
+
+use crate::console::{print_events, print_summary};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
     pub f2b: Option<String>,
 
+    /// Target location, `[remote:]bucket[/prefix]`
+    pub target: Option<String>,
+
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
     #[arg(long)]
     pub directory: String,
 
     #[arg(long)]
-    pub bucket: String,
+    pub bucket: Option<String>,
 
     #[arg(long)]
-    pub region: String,
+    pub region: Option<String>,
 
     #[arg(long)]
-    pub aws_access_key_id: String,
+    pub aws_access_key_id: Option<String>,
 
     #[arg(long)]
-    pub aws_secret_access_key: String,
+    pub aws_secret_access_key: Option<String>,
 
     #[arg(long)]
     pub endpoint: Option<String>,
 
+    #[arg(long, value_enum)]
+    pub provider: Option<Provider>,
+
+    #[arg(long)]
+    pub account_id: Option<String>,
+
+    #[arg(long)]
+    pub force_path_style: bool,
+
+    #[arg(long)]
+    pub use_http: bool,
+
+    #[arg(long)]
+    pub insecure: bool,
+
+    #[arg(long)]
+    pub ca_bundle: Option<PathBuf>,
+
     #[arg(long)]
     pub concurrency: Option<usize>,
 
+    #[arg(long)]
+    pub max_requests_per_second: Option<f64>,
+
     #[arg(long)]
     pub flatten: Option<bool>,
+
+    /// Remove this directory prefix from the keys starting with it
+    #[arg(long)]
+    pub strip_prefix: Option<String>,
+
+    /// Rename keys with `s/pattern/replacement/[gi]`, can be repeated
+    #[arg(long)]
+    pub rename: Vec<String>,
+
+    #[arg(long, value_enum)]
+    pub key_case: Option<KeyCase>,
+
+    /// Add this directory prefix to every key
+    #[arg(long)]
+    pub target_prefix: Option<String>,
+
+    /// What to do with file names that are not valid UTF-8
+    #[arg(long, value_enum, default_value_t)]
+    pub non_utf8_names: NonUtf8Names,
+
+    /// Unicode normalization form of the keys of local files
+    #[arg(long, value_enum)]
+    pub normalize_keys: Option<KeyNormalization>,
+
+    /// Upload the targets of symbolic links instead of skipping them
+    #[arg(long)]
+    pub follow_symlinks: bool,
+
+    /// Leave out files and directories whose name starts with `.`
+    #[arg(long)]
+    pub skip_hidden: bool,
+
+    /// Leave out files matched by `.gitignore` and `.s3ignore` files
+    #[arg(long)]
+    pub ignore_files: bool,
+
+    /// Levels of directories to list, 1 for the files of the directory only
+    #[arg(long)]
+    pub max_depth: Option<usize>,
+
+    /// Do not descend into other file systems
+    #[arg(long)]
+    pub one_file_system: bool,
+
+    #[arg(long, value_enum)]
+    pub sse: Option<ServerSideEncryption>,
+
+    #[arg(long)]
+    pub sse_kms_key_id: Option<String>,
+
+    /// Base64 encoded 256-bit SSE-C key
+    #[arg(long)]
+    pub sse_customer_key: Option<String>,
+
+    /// Encrypt files before upload with this 256-bit key (raw or base64)
+    #[arg(long)]
+    pub cse_key_file: Option<PathBuf>,
+
+    /// Encrypt files before upload with a key derived from this passphrase
+    #[arg(long, env = "S3_UTILS_CSE_PASSPHRASE", hide_env_values = true)]
+    pub cse_passphrase: Option<String>,
+
+    /// Compress files while uploading them
+    #[arg(long, value_enum)]
+    pub compress: Option<Compression>,
+
+    #[arg(long, value_enum)]
+    pub compress_mode: Option<CompressionMode>,
+
+    /// Only compress files matching this glob, can be repeated
+    #[arg(long)]
+    pub compress_glob: Vec<String>,
+
+    /// Tag the uploaded objects with `key=value`, can be repeated
+    #[arg(long)]
+    pub tag: Vec<String>,
+
+    /// Keep uploading the changes to the directory until interrupted
+    #[arg(long)]
+    pub watch: bool,
+
+    /// Milliseconds without changes to wait for before uploading them
+    #[arg(long, default_value_t = 1000, requires = "watch")]
+    pub debounce_ms: u64,
+
+    /// Delete the objects of removed files while watching
+    #[arg(long, requires = "watch")]
+    pub delete: bool,
+
+    /// Only upload the files last modified at or after this time, e.g.
+    /// `2024-05-01T12:00:00Z`, or a date for its midnight UTC
+    #[arg(long, value_parser = parse_time, conflicts_with = "watch")]
+    pub modified_after: Option<chrono::DateTime<chrono::Utc>>,
+
+    /// Only upload the files last modified before this time
+    #[arg(long, value_parser = parse_time, conflicts_with = "watch")]
+    pub modified_before: Option<chrono::DateTime<chrono::Utc>>,
+
+    /// Only upload the files last modified within this age, e.g. `7d`,
+    /// in `s`, `m`, `h`, `d` or `w`
+    #[arg(long, value_parser = parse_age, conflicts_with = "watch")]
+    pub newer_than: Option<chrono::Duration>,
+
+    /// Only upload the files last modified longer ago than this age,
+    /// e.g. `30d`
+    #[arg(long, value_parser = parse_age, conflicts_with = "watch")]
+    pub older_than: Option<chrono::Duration>,
 }
 
-pub async fn folder_to_bucket() -> Result<(), Box<dyn std::error::Error>> {
+pub async fn folder_to_bucket() -> Result<TransferSummary, Box<dyn std::error::Error>> {
     // Read command-line arguments
     let args = Args::parse();
-    let concurrency = args.concurrency.unwrap_or(50);
-    let bucket = args.bucket;
-    let endpoint = args
-        .endpoint
-        .unwrap_or(format!("s3.{}.amazonaws.com", args.region.clone()));
-    let directory = args.directory.clone();
-
-    // Create AWS credentials provider
-    let credentials_provider =
-        StaticProvider::new_minimal(args.aws_access_key_id, args.aws_secret_access_key);
-
-    // Create S3 clients for origin and target regions
-    let client = S3Client::new_with(
-        rusoto_core::HttpClient::new().expect("Failed to create HTTP client"),
-        credentials_provider,
-        Region::Custom {
-            name: args.region.clone(),
-            endpoint,
+    let config = Config::load(args.config.as_deref())?;
+    let location = resolve_location(args.target.as_deref(), args.bucket, "--bucket")?;
+    let remote = RemoteConfig {
+        provider: args.provider,
+        endpoint: args.endpoint,
+        region: args.region,
+        account_id: args.account_id,
+        credentials: static_credentials(args.aws_access_key_id, args.aws_secret_access_key),
+        force_path_style: args.force_path_style.then_some(true),
+        use_http: args.use_http.then_some(true),
+        insecure: args.insecure.then_some(true),
+        ca_bundle: args.ca_bundle,
+        ..Default::default()
+    }
+    .or(config.remote_for(&location)?);
+
+    let options = TransferOptions {
+        concurrency: args.concurrency,
+        max_requests_per_second: args.max_requests_per_second,
+        flatten: args.flatten.unwrap_or_default(),
+        keys: KeyRules::from_args(
+            args.strip_prefix,
+            &args.rename,
+            args.key_case,
+            args.target_prefix,
+        )?,
+        local_keys: LocalKeys {
+            non_utf8: args.non_utf8_names,
+            normalization: args.normalize_keys,
         },
-    );
-
-    let (tx, rx) = mpsc::channel(concurrency);
-
-    tokio::spawn({
-        let directory = directory.clone();
-        async move {
-            for file in WalkDir::new(directory).into_iter() {
-                let file = file.unwrap();
-                if file.file_type().is_file() {
-                    tx.send(file).await.unwrap();
-                }
-            }
-        }
-    });
-
-    tokio_stream::wrappers::ReceiverStream::new(rx)
-        .for_each_concurrent(concurrency, |file| {
-            let directory = directory.clone();
-            let client = client.clone();
-            let bucket = bucket.clone();
-            let root_dir = PathBuf::from(directory);
-            let root_dir = root_dir
-                .canonicalize()
-                .unwrap()
-                .to_str()
-                .unwrap()
-                .to_owned();
-
-            async move {
-                let file_path = format!("{}", file.path().display());
-                let root_directory = match args.flatten {
-                    Some(true) => None,
-                    _ => Some(root_dir.clone()),
-                };
-                let key = get_file_key(&file, root_directory);
-
-                // Upload the file to the target bucket
-                let file = File::open(file_path.clone()).await.unwrap();
-                match upload_s3_object(file, &key, bucket, &client).await {
-                    Ok(_) => {}
-                    Err(e) => {
-                        println!("Error uploading file {}: {:?}", file_path, e);
-                    }
-                }
-
-                println!("File uploaded: {}", key);
-            }
-        })
-        .await;
-
-    return Ok(());
+        walk: WalkOptions {
+            follow_symlinks: args.follow_symlinks,
+            skip_hidden: args.skip_hidden,
+            ignore_files: args.ignore_files,
+            max_depth: args.max_depth,
+            one_file_system: args.one_file_system,
+        },
+        sse: SseOptions::new(
+            args.sse,
+            args.sse_kms_key_id,
+            args.sse_customer_key.as_deref(),
+        )?,
+        encryption: ClientEncryption::from_args(args.cse_key_file.as_deref(), args.cse_passphrase)?,
+        compress: CompressOptions::from_args(
+            args.compress,
+            args.compress_mode,
+            &args.compress_glob,
+        )?,
+        tags: tags_from_args(&args.tag, "--tag")?,
+        modified: TimeFilter::from_args(
+            args.modified_after,
+            args.modified_before,
+            args.newer_than,
+            args.older_than,
+        )?,
+        events: print_events(),
+        ..Default::default()
+    };
+
+    let summary = if args.watch {
+        let watch = WatchOptions {
+            debounce: Duration::from_millis(args.debounce_ms),
+            delete: args.delete,
+        };
+        watch_directory(&args.directory, &location, &remote, &options, &watch).await?
+    } else {
+        upload_directory(&args.directory, &location, &remote, &options).await?
+    };
+    print_summary(&summary);
+
+    Ok(summary)
 }
@@ -0,0 +1,77 @@
+use chrono::{DateTime, Duration, Utc};
+
+/// Window of modification times of the objects to act on: from `after`,
+/// included, to `before`, excluded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimeFilter {
+    pub after: Option<DateTime<Utc>>,
+    pub before: Option<DateTime<Utc>>,
+}
+
+impl TimeFilter {
+    /// From dates and ages given on the command line, `newer_than` and
+    /// `older_than` being counted back from now. The narrowest bounds win.
+    pub fn from_args(
+        modified_after: Option<DateTime<Utc>>,
+        modified_before: Option<DateTime<Utc>>,
+        newer_than: Option<Duration>,
+        older_than: Option<Duration>,
+    ) -> Result<TimeFilter, String> {
+        let now = Utc::now();
+        let after = [modified_after, newer_than.map(|age| now - age)]
+            .into_iter()
+            .flatten()
+            .max();
+        let before = [modified_before, older_than.map(|age| now - age)]
+            .into_iter()
+            .flatten()
+            .min();
+        if let (Some(after), Some(before)) = (after, before) {
+            if after >= before {
+                return Err(format!(
+                    "No object can be modified both after {} and before {}",
+                    after.to_rfc3339(),
+                    before.to_rfc3339()
+                ));
+            }
+        }
+        Ok(TimeFilter { after, before })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.after.is_none() && self.before.is_none()
+    }
+
+    /// Whether an object modified at `modified` is in the window. Objects
+    /// whose modification time is unknown only match a window without
+    /// bounds.
+    pub fn matches(&self, modified: Option<DateTime<Utc>>) -> bool {
+        match modified {
+            Some(modified) => {
+                self.after.is_none_or(|after| modified >= after)
+                    && self.before.is_none_or(|before| modified < before)
+            }
+            None => self.is_empty(),
+        }
+    }
+}
+
+/// An age given on the command line, a number of `s`econds, `m`inutes,
+/// `h`ours, `d`ays or `w`eeks, e.g. `30d`.
+pub fn parse_age(value: &str) -> Result<Duration, String> {
+    let invalid = || format!("Invalid age {}: expected e.g. 30d, 12h or 2w", value);
+    let unit_start = value
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(invalid)?;
+    let (count, unit) = value.split_at(unit_start);
+    let count = count.parse::<i64>().map_err(|_| invalid())?;
+    let age = match unit {
+        "s" => Duration::try_seconds(count),
+        "m" => Duration::try_minutes(count),
+        "h" => Duration::try_hours(count),
+        "d" => Duration::try_days(count),
+        "w" => Duration::try_weeks(count),
+        _ => None,
+    };
+    age.ok_or_else(invalid)
+}
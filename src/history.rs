@@ -0,0 +1,313 @@
+use futures_util::stream::{self, BoxStream};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::config::{RemoteConfig, RemotePath};
+use crate::copy::{transfer_object, SourceObject};
+use crate::store::{ObjectVersion, S3Store, StoreError, StorePath};
+use crate::transfer::{Progress, TransferEvent, TransferOptions, TransferSummary};
+
+/// What a version became in the target, as a line of a version mapping
+/// file. With its `key`, `version_id` and `target_key`, the file is also a
+/// manifest of the source versions.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionMapping {
+    pub key: String,
+    /// `null` for objects written before versioning was enabled
+    pub version_id: String,
+    pub target_key: String,
+    pub target_version_id: Option<String>,
+    #[serde(default)]
+    pub delete_marker: bool,
+}
+
+/// Copies every version and delete marker under `origin` to `target`, which
+/// must have versioning enabled, and records their target version ids in
+/// the `mapping` file.
+pub async fn copy_object_versions(
+    origin: &RemotePath,
+    origin_remote: &RemoteConfig,
+    target: &RemotePath,
+    target_remote: &RemoteConfig,
+    mapping: &Path,
+    options: &TransferOptions,
+) -> Result<TransferSummary, Box<dyn std::error::Error>> {
+    let concurrency =
+        options.concurrency(origin_remote.concurrency.or(target_remote.concurrency), 50);
+    let throttle = options.throttle(
+        concurrency,
+        origin_remote
+            .max_requests_per_second
+            .or(target_remote.max_requests_per_second),
+    );
+
+    let origin_store = S3Store::connect(origin_remote, &origin.bucket, throttle.clone())?
+        .with_sse(options.source_sse.clone());
+    let target_store = S3Store::connect(target_remote, &target.bucket, throttle)?
+        .with_sse(options.sse.clone())
+        .with_events(options.events.clone());
+    let versioning = target_store
+        .versioning()
+        .await
+        .map_err(|e| format!("Could not check the versioning of {}: {}", target.bucket, e))?;
+    if versioning.as_deref() != Some("Enabled") {
+        return Err(format!(
+            "Versioning is not enabled on {}, which would only keep the latest versions",
+            target.bucket
+        )
+        .into());
+    }
+
+    copy_versions_between(
+        &StorePath::new(origin_store, origin.key_prefix()),
+        &StorePath::new(target_store, target.key_prefix()),
+        mapping,
+        &TransferOptions {
+            concurrency: Some(concurrency),
+            ..options.clone()
+        },
+    )
+    .await
+}
+
+/// Replays the versions and delete markers under `source` onto `target`,
+/// oldest first, so that each key gets the same history, and appends what
+/// each one became to the `mapping` file as JSON lines.
+///
+/// Only the versions last modified within the `modified` window of
+/// `options` are replayed. Keys are replayed concurrently, the versions of a
+/// key one after the other. Versions already in the mapping file are skipped, so that an
+/// interrupted copy resumes where it stopped, and a version that fails
+/// stops the replay of its key, whose later versions would come out of
+/// order.
+pub async fn copy_versions_between(
+    source: &StorePath,
+    target: &StorePath,
+    mapping: &Path,
+    options: &TransferOptions,
+) -> Result<TransferSummary, Box<dyn std::error::Error>> {
+    if options.manifest.is_some() {
+        return Err("Every version is copied from a listing, not a manifest".into());
+    }
+    if options.sync {
+        return Err("Versions are replayed, not synced".into());
+    }
+    if !options.where_tags.is_empty() {
+        return Err("Copying every version cannot be filtered by tags".into());
+    }
+    // Histories of several keys would be interleaved into one
+    if options.flatten || options.keys.may_collide() {
+        return Err("Copying every version requires key rules that keep keys apart".into());
+    }
+
+    let concurrency = options.concurrency.unwrap_or(50);
+    let progress = Progress::new(options.events.clone());
+    let copied = read_mapping(mapping)?;
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(mapping)
+        .map_err(|e| format!("Could not open {}: {}", mapping.display(), e))?;
+    let file = Mutex::new(file);
+
+    list_histories(source, &progress)
+        .for_each_concurrent(concurrency, |(key, versions)| {
+            let progress = progress.clone();
+            let copied = &copied;
+            let file = &file;
+            async move {
+                let versions: Vec<_> = versions
+                    .into_iter()
+                    .filter(|version| options.modified.matches(version.info.modified))
+                    .collect();
+                if versions.is_empty() {
+                    return;
+                }
+                let target_key = target.key(&options.keys.apply(source.relative_key(&key)));
+                if !target.store.accepts_key(&target_key) {
+                    progress.emit(TransferEvent::Skipped {
+                        key,
+                        reason: "not a valid key or file path for the target".to_owned(),
+                    });
+                    return;
+                }
+
+                for (index, version) in versions.iter().enumerate() {
+                    let version_id = version.version_id.as_deref().unwrap_or("null");
+                    if copied.contains(&(key.clone(), version_id.to_owned())) {
+                        progress.emit(TransferEvent::UpToDate {
+                            key: target_key.clone(),
+                        });
+                        continue;
+                    }
+
+                    let written = match version.delete_marker {
+                        true => match target.store.delete(&target_key).await {
+                            Ok(version_id) => {
+                                progress.emit(TransferEvent::Deleted {
+                                    key: target_key.clone(),
+                                });
+                                Some(version_id)
+                            }
+                            Err(e) => {
+                                progress.fail(&target_key, e);
+                                None
+                            }
+                        },
+                        false => {
+                            let object = SourceObject {
+                                info: version.info.clone(),
+                                version_id: version.version_id.clone(),
+                                target_key: None,
+                            };
+                            transfer_object(
+                                source,
+                                target,
+                                object,
+                                target_key.clone(),
+                                None,
+                                options,
+                                &progress,
+                            )
+                            .await
+                            .map(|written| written.version_id)
+                        }
+                    };
+                    let recorded = match &written {
+                        Some(target_version_id) => record(
+                            file,
+                            &key,
+                            version,
+                            &target_key,
+                            version_id,
+                            target_version_id.clone(),
+                        ),
+                        None => Ok(()),
+                    };
+                    if let Err(e) = &recorded {
+                        progress.fail(&target_key, e);
+                    }
+                    if written.is_none() || recorded.is_err() {
+                        for _ in &versions[index + 1..] {
+                            progress.fail(&key, "not copied, as an older version failed");
+                        }
+                        return;
+                    }
+                }
+            }
+        })
+        .await;
+
+    Ok(progress.summary())
+}
+
+/// Versions and delete markers under `location` by key, oldest first, as
+/// the listing gets to them.
+///
+/// A listing error is reported to `progress` and ends the stream, without
+/// the key being listed, whose oldest versions could be missing.
+pub(crate) fn list_histories(
+    location: &StorePath,
+    progress: &Progress,
+) -> BoxStream<'static, (String, Vec<ObjectVersion>)> {
+    let prefix = location.prefix.clone();
+    let progress = progress.clone();
+    let versions = location.store.list_versions(&location.prefix);
+
+    // Versions of a key are listed together: a key is complete once the
+    // next one starts, which is carried over to the next history
+    stream::unfold(Some((versions, None)), move |state| {
+        let prefix = prefix.clone();
+        let progress = progress.clone();
+        async move {
+            let (mut versions, next) = state?;
+            let mut history: Vec<ObjectVersion> = Vec::from_iter(next);
+            loop {
+                match versions.next().await {
+                    Some(Ok(version)) => match history.first() {
+                        Some(first) if first.info.key != version.info.key => {
+                            let key = first.info.key.clone();
+                            let state = Some((versions, Some(version)));
+                            return Some(((key, oldest_first(history)), state));
+                        }
+                        _ => history.push(version),
+                    },
+                    Some(Err(StoreError::Skipped { key, reason })) => {
+                        progress.emit(TransferEvent::Skipped { key, reason })
+                    }
+                    Some(Err(e)) => {
+                        progress.emit(TransferEvent::ListingFailed {
+                            prefix,
+                            error: e.to_string(),
+                        });
+                        return None;
+                    }
+                    None => {
+                        let key = history.first()?.info.key.clone();
+                        return Some(((key, oldest_first(history)), None));
+                    }
+                }
+            }
+        }
+    })
+    .boxed()
+}
+
+/// Versions of a key, listed newest first, in the order they were written.
+fn oldest_first(mut versions: Vec<ObjectVersion>) -> Vec<ObjectVersion> {
+    versions.reverse();
+    // Versions and delete markers are listed apart, and dates only go down
+    // to the millisecond, so the current one goes last among equals
+    versions.sort_by_key(|version| (version.info.modified, version.is_latest));
+    versions
+}
+
+/// Appends the version just written to `target_key` to the mapping file.
+fn record(
+    file: &Mutex<File>,
+    key: &str,
+    version: &ObjectVersion,
+    target_key: &str,
+    version_id: &str,
+    target_version_id: Option<String>,
+) -> Result<(), String> {
+    let line = serde_json::to_string(&VersionMapping {
+        key: key.to_owned(),
+        version_id: version_id.to_owned(),
+        target_key: target_key.to_owned(),
+        target_version_id,
+        delete_marker: version.delete_marker,
+    })
+    .map_err(|e| e.to_string())?;
+    writeln!(file.lock().unwrap(), "{}", line)
+        .map_err(|e| format!("Copied, but could not be recorded: {}", e))
+}
+
+/// Source keys and version ids of the versions a previous copy recorded.
+fn read_mapping(path: &Path) -> Result<HashSet<(String, String)>, Box<dyn std::error::Error>> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashSet::new()),
+        Err(e) => return Err(format!("Could not read {}: {}", path.display(), e).into()),
+    };
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            let entry: VersionMapping = serde_json::from_str(line).map_err(|e| {
+                format!(
+                    "Invalid version mapping {}, line {}: {}",
+                    path.display(),
+                    index + 1,
+                    e
+                )
+            })?;
+            Ok((entry.key, entry.version_id))
+        })
+        .collect()
+}
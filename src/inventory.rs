@@ -0,0 +1,115 @@
+use futures_util::StreamExt;
+use std::collections::BTreeMap;
+
+use crate::store::{ObjectVersion, StorePath};
+use crate::transfer::{EventHandler, Progress, TransferEvent};
+
+/// Settings of an `inventory`.
+#[derive(Debug, Clone, Default)]
+pub struct InventoryOptions {
+    /// List every version and delete marker rather than the current objects
+    pub versions: bool,
+    /// Only list the objects directly under the prefix, and the totals of
+    /// the common prefixes up to the next delimiter
+    pub delimiter: Option<String>,
+    pub events: EventHandler,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InventoryEntry {
+    Object(ObjectVersion),
+    /// Objects under a common prefix, which ends with the delimiter
+    Prefix {
+        prefix: String,
+        objects: u64,
+        bytes: u64,
+    },
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InventorySummary {
+    /// Objects or versions, under common prefixes or not
+    pub objects: u64,
+    pub bytes: u64,
+    pub prefixes: u64,
+    /// Listing errors, after which the inventory is incomplete
+    pub errors: u64,
+}
+
+/// Lists the objects under `location` and hands each entry to `on_entry`
+/// as it comes. With a delimiter, common prefixes come last, once their
+/// totals are known.
+pub async fn inventory<F>(
+    location: &StorePath,
+    options: &InventoryOptions,
+    mut on_entry: F,
+) -> Result<InventorySummary, Box<dyn std::error::Error>>
+where
+    F: FnMut(InventoryEntry) -> Result<(), Box<dyn std::error::Error>>,
+{
+    let progress = Progress::new(options.events.clone());
+    let mut entries = match options.versions {
+        true => location.store.list_versions(&location.prefix),
+        false => location
+            .store
+            .list(&location.prefix)
+            .map(|info| {
+                info.map(|info| ObjectVersion {
+                    info,
+                    is_latest: true,
+                    ..Default::default()
+                })
+            })
+            .boxed(),
+    };
+
+    let mut summary = InventorySummary::default();
+    let mut prefixes = BTreeMap::<String, (u64, u64)>::new();
+    while let Some(entry) = entries.next().await {
+        let version = match entry {
+            Ok(version) => version,
+            Err(e) => {
+                progress.emit(TransferEvent::ListingFailed {
+                    prefix: location.prefix.clone(),
+                    error: e.to_string(),
+                });
+                summary.errors += 1;
+                continue;
+            }
+        };
+        summary.objects += 1;
+        summary.bytes += version.info.size;
+
+        let common_prefix = options
+            .delimiter
+            .as_deref()
+            .and_then(|delimiter| common_prefix(&location.prefix, &version.info.key, delimiter));
+        match common_prefix {
+            Some(common_prefix) => {
+                let totals = prefixes.entry(common_prefix.to_owned()).or_default();
+                totals.0 += 1;
+                totals.1 += version.info.size;
+            }
+            None => on_entry(InventoryEntry::Object(version))?,
+        }
+    }
+
+    summary.prefixes = prefixes.len() as u64;
+    for (prefix, (objects, bytes)) in prefixes {
+        on_entry(InventoryEntry::Prefix {
+            prefix,
+            objects,
+            bytes,
+        })?;
+    }
+
+    Ok(summary)
+}
+
+/// `key` up to the first `delimiter` after `prefix`, included, if there is
+/// one.
+fn common_prefix<'a>(prefix: &str, key: &'a str, delimiter: &str) -> Option<&'a str> {
+    let relative_key = key.strip_prefix(prefix)?;
+    let end = relative_key.find(delimiter)? + delimiter.len();
+    Some(&key[..prefix.len() + end])
+}
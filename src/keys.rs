@@ -0,0 +1,175 @@
+use clap::ValueEnum;
+use regex::{Regex, RegexBuilder};
+
+/// Case the target keys are converted to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum KeyCase {
+    Lower,
+    Upper,
+}
+
+/// A sed-like `s/pattern/replacement/flags` substitution.
+#[derive(Debug, Clone)]
+pub struct RenameRule {
+    pattern: Regex,
+    replacement: String,
+    global: bool,
+}
+
+impl RenameRule {
+    /// Parses `s/pattern/replacement/` with any delimiter following the `s`.
+    /// The replacement refers to groups with `\1` or `$1` and to the whole
+    /// match with `&`; flags are `g` (every match) and `i` (ignore case).
+    pub fn parse(rule: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let invalid = |reason: &str| format!("Invalid --rename {}: {}", rule, reason);
+        let mut chars = rule.chars();
+        if chars.next() != Some('s') {
+            return Err(invalid("expected s/pattern/replacement/").into());
+        }
+        let delimiter = chars
+            .next()
+            .filter(|delimiter| !delimiter.is_alphanumeric() && *delimiter != '\\')
+            .ok_or_else(|| invalid("expected a delimiter after s"))?;
+
+        let parts = split_unescaped(chars.as_str(), delimiter);
+        let [pattern, replacement, flags] = parts.as_slice() else {
+            return Err(invalid("expected s/pattern/replacement/").into());
+        };
+        let mut global = false;
+        let mut case_insensitive = false;
+        for flag in flags.chars() {
+            match flag {
+                'g' => global = true,
+                'i' => case_insensitive = true,
+                flag => return Err(invalid(&format!("unknown flag {}", flag)).into()),
+            }
+        }
+
+        let pattern = RegexBuilder::new(pattern)
+            .case_insensitive(case_insensitive)
+            .build()
+            .map_err(|e| invalid(&e.to_string()))?;
+        Ok(RenameRule {
+            pattern,
+            replacement: replacement_template(replacement),
+            global,
+        })
+    }
+
+    pub fn apply(&self, key: &str) -> String {
+        let limit = if self.global { 0 } else { 1 };
+        self.pattern
+            .replacen(key, limit, self.replacement.as_str())
+            .into_owned()
+    }
+}
+
+/// Splits `text` on the `delimiter`s not escaped by a backslash, and
+/// unescapes them.
+fn split_unescaped(text: &str, delimiter: char) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&delimiter) => {
+                parts.last_mut().unwrap().push(delimiter);
+                chars.next();
+            }
+            '\\' => {
+                let part = parts.last_mut().unwrap();
+                part.push('\\');
+                part.extend(chars.next());
+            }
+            c if c == delimiter => parts.push(String::new()),
+            c => parts.last_mut().unwrap().push(c),
+        }
+    }
+    parts
+}
+
+/// Converts a sed replacement to the syntax of `Regex::replace`.
+fn replacement_template(replacement: &str) -> String {
+    let mut template = String::new();
+    let mut chars = replacement.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(digit @ '0'..='9') => template.push_str(&format!("${{{}}}", digit)),
+                Some('$') => template.push_str("$$"),
+                Some(c) => template.push(c),
+                None => template.push('\\'),
+            },
+            '&' => template.push_str("${0}"),
+            c => template.push(c),
+        }
+    }
+    template
+}
+
+/// Changes applied to the keys of a transfer, relative to the source and
+/// target prefixes, in the order of the fields.
+#[derive(Debug, Clone, Default)]
+pub struct KeyRules {
+    /// Removed from the keys starting with it, ends with `/`
+    pub strip_prefix: Option<String>,
+    pub renames: Vec<RenameRule>,
+    pub case: Option<KeyCase>,
+    /// Added to every key, ends with `/`
+    pub target_prefix: Option<String>,
+}
+
+impl KeyRules {
+    /// Prefixes are directories, with or without their trailing `/`.
+    pub fn from_args(
+        strip_prefix: Option<String>,
+        renames: &[String],
+        case: Option<KeyCase>,
+        target_prefix: Option<String>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(KeyRules {
+            strip_prefix: strip_prefix.as_deref().and_then(directory_prefix),
+            renames: renames
+                .iter()
+                .map(|rule| RenameRule::parse(rule))
+                .collect::<Result<_, _>>()?,
+            case,
+            target_prefix: target_prefix.as_deref().and_then(directory_prefix),
+        })
+    }
+
+    /// Whether keys are kept as they are.
+    pub fn is_empty(&self) -> bool {
+        !self.may_collide() && self.target_prefix.is_none()
+    }
+
+    /// Whether two keys may end up the same once changed.
+    pub fn may_collide(&self) -> bool {
+        self.strip_prefix.is_some() || !self.renames.is_empty() || self.case.is_some()
+    }
+
+    pub fn apply(&self, key: &str) -> String {
+        let key = self
+            .strip_prefix
+            .as_deref()
+            .and_then(|prefix| key.strip_prefix(prefix))
+            .unwrap_or(key);
+        let mut key = self
+            .renames
+            .iter()
+            .fold(key.to_owned(), |key, rule| rule.apply(&key));
+        match self.case {
+            Some(KeyCase::Lower) => key = key.to_lowercase(),
+            Some(KeyCase::Upper) => key = key.to_uppercase(),
+            None => {}
+        }
+        match &self.target_prefix {
+            Some(prefix) => format!("{}{}", prefix, key),
+            None => key,
+        }
+    }
+}
+
+fn directory_prefix(prefix: &str) -> Option<String> {
+    let prefix = prefix.trim_matches('/');
+    (!prefix.is_empty()).then(|| format!("{}/", prefix))
+}
@@ -0,0 +1,70 @@
+//! Bulk transfers between local directories and S3-compatible buckets.
+//!
+//! ```no_run
+//! use s3_utils::{upload_directory, RemoteConfig, RemotePath, TransferOptions};
+//!
+//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let remote = RemoteConfig {
+//!     region: Some("eu-west-1".to_owned()),
+//!     ..Default::default()
+//! };
+//! let target: RemotePath = "my-bucket/backups".parse()?;
+//! let summary = upload_directory("./data", &target, &remote, &TransferOptions::default()).await?;
+//! println!("{} files uploaded, {} failed", summary.transferred, summary.failed);
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod client;
+pub mod compress;
+pub mod config;
+pub mod cse;
+pub mod filter;
+pub mod keys;
+pub mod sse;
+pub mod store;
+pub mod tags;
+pub mod throttle;
+pub mod transfer;
+
+mod archive;
+mod bucket_config;
+mod compare;
+mod copy;
+mod delete;
+mod download;
+mod history;
+mod inventory;
+mod listing;
+mod manifest;
+mod point_in_time;
+mod replication;
+mod upload;
+mod usage;
+mod utils;
+mod watch;
+
+pub use archive::{
+    restore_from, restore_objects, transition_from, transition_objects, RestoreOptions,
+};
+pub use bucket_config::{copy_bucket_config, create_bucket, BucketSetting, SettingOutcome};
+pub use compare::{diff, DiffEntry, DiffOptions, DiffReport, DiffStatus};
+pub use config::{Config, Location, RemoteConfig, RemotePath};
+pub use copy::{copy_objects, transfer_between};
+pub use delete::{delete_from, delete_objects};
+pub use download::download_directory;
+pub use history::{copy_object_versions, copy_versions_between, VersionMapping};
+pub use inventory::{inventory, InventoryEntry, InventoryOptions, InventorySummary};
+pub use manifest::{Manifest, ManifestEntry, ManifestFormat};
+pub use point_in_time::{restore_point_in_time, PointInTimeOptions};
+pub use replication::{mirror, MirrorOptions};
+pub use store::{
+    LocalStore, MemoryStore, ObjectStore, ObjectVersion, RestoreStatus, RestoreTier, S3Store,
+    StorePath, WrittenObject,
+};
+pub use tags::{tag_from, tag_objects, TagChanges};
+pub use transfer::{EventHandler, TransferEvent, TransferOptions, TransferSummary};
+pub use upload::upload_directory;
+pub use usage::{usage, Age, UsageOptions, UsageReport, UsageTotals};
+pub use utils::parse_time;
+pub use watch::{watch_directory, WatchOptions};
@@ -0,0 +1,59 @@
+use futures_util::StreamExt;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::store::{ObjectInfo, ObjectStore, StoreError};
+use crate::transfer::{Progress, TransferEvent};
+
+/// Lists every object under `prefix` and sends them to `tx` as they come,
+/// so consumers can start before the listing is complete.
+pub fn spawn_listing(
+    store: Arc<dyn ObjectStore>,
+    prefix: String,
+    progress: Progress,
+    tx: mpsc::Sender<ObjectInfo>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut objects = store.list(&prefix);
+        while let Some(object) = objects.next().await {
+            match object {
+                Ok(object) => {
+                    if tx.send(object).await.is_err() {
+                        return;
+                    }
+                }
+                Err(StoreError::Skipped { key, reason }) => {
+                    progress.emit(TransferEvent::Skipped { key, reason })
+                }
+                Err(e) => progress.emit(TransferEvent::ListingFailed {
+                    prefix: prefix.clone(),
+                    error: e.to_string(),
+                }),
+            }
+        }
+    })
+}
+
+/// Lists the objects under `prefix`, indexed by their key relative to it.
+pub async fn list_objects_by_key(
+    store: &Arc<dyn ObjectStore>,
+    prefix: &str,
+    progress: &Progress,
+) -> HashMap<String, ObjectInfo> {
+    let (tx, mut rx) = mpsc::channel(1000);
+    spawn_listing(store.clone(), prefix.to_owned(), progress.clone(), tx);
+
+    let mut objects = HashMap::new();
+    while let Some(object) = rx.recv().await {
+        let relative_key = object
+            .key
+            .strip_prefix(prefix)
+            .unwrap_or(&object.key)
+            .to_owned();
+        objects.insert(relative_key, object);
+    }
+
+    objects
+}
@@ -0,0 +1,552 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use clap::{Parser, ValueEnum};
+use parquet::data_type::{BoolType, ByteArray, ByteArrayType, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::{SerializedColumnWriter, SerializedFileWriter};
+use parquet::schema::parser::parse_message_type;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use s3_utils::client::Provider;
+use s3_utils::config::static_credentials;
+use s3_utils::throttle::Throttle;
+use s3_utils::{
+    inventory, Config, EventHandler, InventoryEntry, InventoryOptions, LocalStore, Location,
+    RemoteConfig, S3Store, StorePath, TransferEvent, TransferSummary,
+};
+
+use crate::console::csv_row;
+
+/// Rows of a Parquet row group, held in memory until written
+const PARQUET_ROW_GROUP_SIZE: usize = 100_000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum InventoryFormat {
+    #[default]
+    Text,
+    Csv,
+    Jsonl,
+    Parquet,
+}
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+pub struct Args {
+    pub ls: String,
+
+    /// `s3://bucket/prefix`, `remote:bucket/prefix` or a local directory
+    pub location: String,
+
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    #[arg(long)]
+    pub region: Option<String>,
+
+    #[arg(long)]
+    pub aws_access_key_id: Option<String>,
+
+    #[arg(long)]
+    pub aws_secret_access_key: Option<String>,
+
+    #[arg(long)]
+    pub endpoint: Option<String>,
+
+    #[arg(long, value_enum)]
+    pub provider: Option<Provider>,
+
+    #[arg(long)]
+    pub account_id: Option<String>,
+
+    #[arg(long)]
+    pub force_path_style: bool,
+
+    #[arg(long)]
+    pub use_http: bool,
+
+    #[arg(long)]
+    pub insecure: bool,
+
+    #[arg(long)]
+    pub ca_bundle: Option<PathBuf>,
+
+    #[arg(long)]
+    pub max_requests_per_second: Option<f64>,
+
+    /// List every version and delete marker
+    #[arg(long)]
+    pub versions: bool,
+
+    /// List what is directly under the prefix, with the totals of each
+    /// common prefix up to the delimiter, e.g. `/`
+    #[arg(long)]
+    pub delimiter: Option<String>,
+
+    #[arg(long, value_enum, default_value_t)]
+    pub format: InventoryFormat,
+
+    /// Write to this file rather than stdout
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+}
+
+/// Columns written besides the key, size, ETag, last modification date,
+/// storage class and owner.
+#[derive(Debug, Clone, Copy)]
+struct Columns {
+    versions: bool,
+    objects: bool,
+}
+
+/// An object, a version or a common prefix.
+struct Row {
+    key: String,
+    size: u64,
+    e_tag: Option<String>,
+    last_modified: Option<DateTime<Utc>>,
+    storage_class: Option<String>,
+    owner: Option<String>,
+    version_id: Option<String>,
+    is_latest: bool,
+    delete_marker: bool,
+    /// 1 for objects, the number of objects under common prefixes
+    objects: u64,
+    is_prefix: bool,
+}
+
+impl From<InventoryEntry> for Row {
+    fn from(entry: InventoryEntry) -> Self {
+        match entry {
+            InventoryEntry::Object(version) => Row {
+                key: version.info.key,
+                size: version.info.size,
+                e_tag: version.info.e_tag,
+                last_modified: version.info.modified,
+                storage_class: version.info.storage_class,
+                owner: version.info.owner,
+                version_id: version.version_id,
+                is_latest: version.is_latest,
+                delete_marker: version.delete_marker,
+                objects: 1,
+                is_prefix: false,
+            },
+            InventoryEntry::Prefix {
+                prefix,
+                objects,
+                bytes,
+            } => Row {
+                key: prefix,
+                size: bytes,
+                e_tag: None,
+                last_modified: None,
+                storage_class: None,
+                owner: None,
+                version_id: None,
+                is_latest: false,
+                delete_marker: false,
+                objects,
+                is_prefix: true,
+            },
+        }
+    }
+}
+
+/// `ls`/`inventory`: lists the objects of a bucket, and optionally their
+/// versions, as text, CSV, JSON Lines or Parquet.
+pub async fn list() -> Result<TransferSummary, Box<dyn std::error::Error>> {
+    // Read command-line arguments
+    let args = Args::parse();
+    let config = Config::load(args.config.as_deref())?;
+
+    let location = match Location::parse(&args.location, &config)? {
+        Location::Local(directory) => {
+            if !directory.is_dir() {
+                return Err(format!("Not a directory: {}", directory.display()).into());
+            }
+            StorePath::new(LocalStore::new(directory), "")
+        }
+        Location::Bucket(path) => {
+            let remote = RemoteConfig {
+                provider: args.provider,
+                endpoint: args.endpoint,
+                region: args.region,
+                account_id: args.account_id,
+                credentials: static_credentials(args.aws_access_key_id, args.aws_secret_access_key),
+                force_path_style: args.force_path_style.then_some(true),
+                use_http: args.use_http.then_some(true),
+                insecure: args.insecure.then_some(true),
+                ca_bundle: args.ca_bundle,
+                ..Default::default()
+            }
+            .or(config.remote_for(&path)?);
+            let max_requests_per_second = args
+                .max_requests_per_second
+                .or(remote.max_requests_per_second);
+            let throttle = Throttle::new(1, max_requests_per_second);
+            let store = S3Store::connect(&remote, &path.bucket, throttle)?;
+            StorePath::new(store, path.key_prefix())
+        }
+    };
+
+    let output: Box<dyn Write + Send> = match &args.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(std::io::stdout())),
+    };
+    let columns = Columns {
+        versions: args.versions,
+        objects: args.delimiter.is_some(),
+    };
+    let mut writer: Box<dyn InventoryWriter> = match args.format {
+        InventoryFormat::Text => Box::new(TextWriter { output, columns }),
+        InventoryFormat::Csv => Box::new(CsvWriter::new(output, columns)?),
+        InventoryFormat::Jsonl => Box::new(JsonLinesWriter { output, columns }),
+        InventoryFormat::Parquet => Box::new(ParquetWriter::new(output, columns)?),
+    };
+
+    let options = InventoryOptions {
+        versions: args.versions,
+        delimiter: args.delimiter,
+        // Keep stdout for the inventory
+        events: EventHandler::new(|event| {
+            if let TransferEvent::ListingFailed { prefix, error } = event {
+                eprintln!("Error listing {}: {}", prefix, error);
+            }
+        }),
+    };
+    let summary = inventory(&location, &options, |entry| writer.write(entry.into())).await?;
+    writer.finish()?;
+
+    let totals = format!(
+        "Total: {} objects ({} bytes), {} prefixes, {} errors",
+        summary.objects, summary.bytes, summary.prefixes, summary.errors
+    );
+    match (args.format, &args.output) {
+        (InventoryFormat::Text, None) => println!("{}", totals),
+        _ => eprintln!("{}", totals),
+    }
+
+    Ok(TransferSummary {
+        transferred: summary.objects,
+        bytes: summary.bytes,
+        failed: summary.errors,
+        ..Default::default()
+    })
+}
+
+trait InventoryWriter {
+    fn write(&mut self, row: Row) -> Result<(), Box<dyn std::error::Error>>;
+
+    fn finish(self: Box<Self>) -> Result<(), Box<dyn std::error::Error>>;
+}
+
+fn format_timestamp(timestamp: &DateTime<Utc>) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+struct TextWriter {
+    output: Box<dyn Write + Send>,
+    columns: Columns,
+}
+
+impl InventoryWriter for TextWriter {
+    fn write(&mut self, row: Row) -> Result<(), Box<dyn std::error::Error>> {
+        if row.is_prefix {
+            writeln!(
+                self.output,
+                "{:>24} {:>14} {} ({} objects)",
+                "PRE", row.size, row.key, row.objects
+            )?;
+            return Ok(());
+        }
+
+        let modified = row
+            .last_modified
+            .map(|modified| modified.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default();
+        write!(self.output, "{:>24} {:>14} {}", modified, row.size, row.key)?;
+        if self.columns.versions {
+            write!(
+                self.output,
+                " ({}{}{})",
+                row.version_id.as_deref().unwrap_or("null"),
+                if row.is_latest { ", latest" } else { "" },
+                if row.delete_marker {
+                    ", delete marker"
+                } else {
+                    ""
+                }
+            )?;
+        }
+        writeln!(self.output)?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), Box<dyn std::error::Error>> {
+        self.output.flush()?;
+        Ok(())
+    }
+}
+
+struct CsvWriter {
+    output: Box<dyn Write + Send>,
+    columns: Columns,
+}
+
+impl CsvWriter {
+    fn new(
+        mut output: Box<dyn Write + Send>,
+        columns: Columns,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut header = vec![
+            "key",
+            "size",
+            "etag",
+            "last_modified",
+            "storage_class",
+            "owner",
+        ];
+        if columns.versions {
+            header.extend(["version_id", "is_latest", "delete_marker"]);
+        }
+        if columns.objects {
+            header.push("objects");
+        }
+        writeln!(output, "{}", csv_row(&header))?;
+        Ok(CsvWriter { output, columns })
+    }
+}
+
+impl InventoryWriter for CsvWriter {
+    fn write(&mut self, row: Row) -> Result<(), Box<dyn std::error::Error>> {
+        let mut fields = vec![
+            row.key,
+            row.size.to_string(),
+            row.e_tag.unwrap_or_default(),
+            row.last_modified
+                .as_ref()
+                .map(format_timestamp)
+                .unwrap_or_default(),
+            row.storage_class.unwrap_or_default(),
+            row.owner.unwrap_or_default(),
+        ];
+        if self.columns.versions {
+            fields.extend([
+                row.version_id.unwrap_or_default(),
+                row.is_latest.to_string(),
+                row.delete_marker.to_string(),
+            ]);
+        }
+        if self.columns.objects {
+            fields.push(row.objects.to_string());
+        }
+        writeln!(self.output, "{}", csv_row(&fields))?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), Box<dyn std::error::Error>> {
+        self.output.flush()?;
+        Ok(())
+    }
+}
+
+struct JsonLinesWriter {
+    output: Box<dyn Write + Send>,
+    columns: Columns,
+}
+
+impl InventoryWriter for JsonLinesWriter {
+    fn write(&mut self, row: Row) -> Result<(), Box<dyn std::error::Error>> {
+        let mut line = serde_json::json!({
+            "key": row.key,
+            "size": row.size,
+            "etag": row.e_tag,
+            "last_modified": row.last_modified.as_ref().map(format_timestamp),
+            "storage_class": row.storage_class,
+            "owner": row.owner,
+        });
+        if self.columns.versions {
+            line["version_id"] = row.version_id.into();
+            line["is_latest"] = row.is_latest.into();
+            line["delete_marker"] = row.delete_marker.into();
+        }
+        if self.columns.objects {
+            line["objects"] = row.objects.into();
+        }
+        writeln!(self.output, "{}", line)?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), Box<dyn std::error::Error>> {
+        self.output.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes rows in row groups of `PARQUET_ROW_GROUP_SIZE`, uncompressed.
+struct ParquetWriter {
+    writer: SerializedFileWriter<Box<dyn Write + Send>>,
+    columns: Columns,
+    rows: Vec<Row>,
+}
+
+impl ParquetWriter {
+    fn new(
+        output: Box<dyn Write + Send>,
+        columns: Columns,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut schema = String::from(
+            "message inventory {
+                required binary key (UTF8);
+                required int64 size;
+                optional binary etag (UTF8);
+                optional int64 last_modified (TIMESTAMP(MILLIS,true));
+                optional binary storage_class (UTF8);
+                optional binary owner (UTF8);",
+        );
+        if columns.versions {
+            schema.push_str(
+                "optional binary version_id (UTF8);
+                required boolean is_latest;
+                required boolean delete_marker;",
+            );
+        }
+        if columns.objects {
+            schema.push_str("required int64 objects;");
+        }
+        schema.push('}');
+
+        let writer = SerializedFileWriter::new(
+            output,
+            Arc::new(parse_message_type(&schema)?),
+            Arc::new(WriterProperties::builder().build()),
+        )?;
+        Ok(ParquetWriter {
+            writer,
+            columns,
+            rows: Vec::new(),
+        })
+    }
+
+    fn write_row_group(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+        let rows = std::mem::take(&mut self.rows);
+
+        let mut row_group = self.writer.next_row_group()?;
+        let mut index = 0;
+        while let Some(mut column) = row_group.next_column()? {
+            match index {
+                0 => write_strings(&mut column, &rows, |row| Some(&row.key), false)?,
+                1 => write_integers(&mut column, &rows, |row| Some(row.size as i64), false)?,
+                2 => write_strings(&mut column, &rows, |row| row.e_tag.as_deref(), true)?,
+                3 => write_integers(
+                    &mut column,
+                    &rows,
+                    |row| {
+                        row.last_modified
+                            .map(|modified| modified.timestamp_millis())
+                    },
+                    true,
+                )?,
+                4 => write_strings(&mut column, &rows, |row| row.storage_class.as_deref(), true)?,
+                5 => write_strings(&mut column, &rows, |row| row.owner.as_deref(), true)?,
+                6 if self.columns.versions => {
+                    write_strings(&mut column, &rows, |row| row.version_id.as_deref(), true)?
+                }
+                7 if self.columns.versions => {
+                    write_booleans(&mut column, &rows, |row| row.is_latest)?
+                }
+                8 if self.columns.versions => {
+                    write_booleans(&mut column, &rows, |row| row.delete_marker)?
+                }
+                _ => write_integers(&mut column, &rows, |row| Some(row.objects as i64), false)?,
+            }
+            column.close()?;
+            index += 1;
+        }
+        row_group.close()?;
+        Ok(())
+    }
+}
+
+impl InventoryWriter for ParquetWriter {
+    fn write(&mut self, row: Row) -> Result<(), Box<dyn std::error::Error>> {
+        self.rows.push(row);
+        if self.rows.len() >= PARQUET_ROW_GROUP_SIZE {
+            self.write_row_group()?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), Box<dyn std::error::Error>> {
+        self.write_row_group()?;
+        let mut output = self.writer.into_inner()?;
+        output.flush()?;
+        Ok(())
+    }
+}
+
+/// Definition levels of an optional column, `None` for a required one.
+fn definition_levels<T>(values: &[Option<T>], optional: bool) -> Option<Vec<i16>> {
+    optional.then(|| values.iter().map(|value| value.is_some() as i16).collect())
+}
+
+fn write_strings<'a, F>(
+    column: &mut SerializedColumnWriter<'_>,
+    rows: &'a [Row],
+    value: F,
+    optional: bool,
+) -> parquet::errors::Result<()>
+where
+    F: Fn(&'a Row) -> Option<&'a str>,
+{
+    let values = rows.iter().map(value).collect::<Vec<_>>();
+    let present = values
+        .iter()
+        .flatten()
+        .map(|value| ByteArray::from(*value))
+        .collect::<Vec<_>>();
+    column.typed::<ByteArrayType>().write_batch(
+        &present,
+        definition_levels(&values, optional).as_deref(),
+        None,
+    )?;
+    Ok(())
+}
+
+fn write_integers<F>(
+    column: &mut SerializedColumnWriter<'_>,
+    rows: &[Row],
+    value: F,
+    optional: bool,
+) -> parquet::errors::Result<()>
+where
+    F: Fn(&Row) -> Option<i64>,
+{
+    let values = rows.iter().map(value).collect::<Vec<_>>();
+    let present = values.iter().flatten().copied().collect::<Vec<_>>();
+    column.typed::<Int64Type>().write_batch(
+        &present,
+        definition_levels(&values, optional).as_deref(),
+        None,
+    )?;
+    Ok(())
+}
+
+fn write_booleans<F>(
+    column: &mut SerializedColumnWriter<'_>,
+    rows: &[Row],
+    value: F,
+) -> parquet::errors::Result<()>
+where
+    F: Fn(&Row) -> bool,
+{
+    let values = rows.iter().map(value).collect::<Vec<_>>();
+    column
+        .typed::<BoolType>()
+        .write_batch(&values, None, None)?;
+    Ok(())
+}
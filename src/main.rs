@@ -1,12 +1,29 @@
 mod b2b;
-mod cli;
+mod console;
+mod cp;
 mod delete_bucket;
+mod diff;
+mod du;
 mod f2b;
-mod utils;
+mod ls;
+mod mirror;
+mod restore;
+mod restore_archive;
+mod tag;
+mod transition;
 
 use b2b::bucket_to_bucket;
+use cp::copy;
 use delete_bucket::delete_bucket;
+use diff::compare;
+use du::disk_usage;
 use f2b::folder_to_bucket;
+use ls::list;
+use mirror::mirror_bucket;
+use restore::restore_prefix;
+use restore_archive::restore_archive;
+use tag::tag;
+use transition::transition;
 
 #[tokio::main]
 async fn main() {
@@ -15,23 +32,38 @@ async fn main() {
 
     if method.is_none() {
         println!("No method specified");
-        return;
+        std::process::exit(1);
     }
 
     let method = method.unwrap();
 
-    match method.as_str() {
-        "b2b" => {
-            let _ = bucket_to_bucket().await;
-        }
-        "f2b" => {
-            let _ = folder_to_bucket().await;
-        }
-        "delete_bucket" => {
-            let _ = delete_bucket().await;
-        }
+    let result = match method.as_str() {
+        "b2b" => bucket_to_bucket().await,
+        "f2b" => folder_to_bucket().await,
+        "delete_bucket" => delete_bucket().await,
+        "cp" => copy(false).await,
+        "sync" => copy(true).await,
+        "diff" => compare().await,
+        "ls" | "inventory" => list().await,
+        "du" => disk_usage().await,
+        "mirror" => mirror_bucket().await,
+        "transition" => transition().await,
+        "restore" => restore_prefix().await,
+        "restore_archive" => restore_archive().await,
+        "tag" => tag().await,
         _ => {
             println!("Unknown method: {}", method);
+            std::process::exit(1);
+        }
+    };
+
+    // 1 when the command could not run, 2 when some objects failed
+    match result {
+        Ok(summary) if summary.failed > 0 => std::process::exit(2),
+        Ok(_) => {}
+        Err(e) => {
+            println!("Error: {}", e);
+            std::process::exit(1);
         }
     }
 }
@@ -0,0 +1,166 @@
+use serde::Deserialize;
+use std::path::Path;
+use std::sync::Arc;
+
+/// An object named by a manifest.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct ManifestEntry {
+    /// Full key of the object in the source
+    pub key: String,
+    #[serde(default)]
+    pub version_id: Option<String>,
+    /// Full key to write in the target, instead of the one derived from
+    /// `key`
+    #[serde(default)]
+    pub target_key: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManifestFormat {
+    /// One key per line
+    Lines,
+    /// A header line naming the `key`, `version_id` and `target_key`
+    /// columns, other columns being ignored
+    Csv,
+    /// One JSON object per line with `key`, `version_id` and `target_key`
+    JsonLines,
+}
+
+impl ManifestFormat {
+    /// From the extension of `path`: `.csv`, `.jsonl` or `.ndjson`, and
+    /// lines otherwise.
+    pub fn of(path: &Path) -> ManifestFormat {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("csv") => ManifestFormat::Csv,
+            Some("jsonl" | "ndjson") => ManifestFormat::JsonLines,
+            _ => ManifestFormat::Lines,
+        }
+    }
+}
+
+/// Objects to act on instead of listing a location. Clones share the
+/// entries.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+    entries: Arc<Vec<ManifestEntry>>,
+}
+
+impl Manifest {
+    pub fn new(entries: Vec<ManifestEntry>) -> Self {
+        Manifest {
+            entries: Arc::new(entries),
+        }
+    }
+
+    /// Reads a manifest in the format given by its extension.
+    pub fn read(path: &Path) -> Result<Manifest, Box<dyn std::error::Error>> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Could not read manifest {}: {}", path.display(), e))?;
+        Manifest::parse(&text, ManifestFormat::of(path))
+            .map_err(|e| format!("Invalid manifest {}: {}", path.display(), e).into())
+    }
+
+    pub fn parse(text: &str, format: ManifestFormat) -> Result<Manifest, String> {
+        let entries = match format {
+            ManifestFormat::Lines => text
+                .lines()
+                .map(|line| line.strip_suffix('\r').unwrap_or(line))
+                .filter(|line| !line.is_empty())
+                .map(|key| ManifestEntry {
+                    key: key.to_owned(),
+                    ..Default::default()
+                })
+                .collect(),
+            ManifestFormat::Csv => parse_csv(text)?,
+            ManifestFormat::JsonLines => text
+                .lines()
+                .enumerate()
+                .filter(|(_, line)| !line.trim().is_empty())
+                .map(|(index, line)| {
+                    serde_json::from_str(line).map_err(|e| format!("line {}: {}", index + 1, e))
+                })
+                .collect::<Result<_, _>>()?,
+        };
+        Ok(Manifest::new(entries))
+    }
+
+    pub fn entries(&self) -> &[ManifestEntry] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+fn parse_csv(text: &str) -> Result<Vec<ManifestEntry>, String> {
+    let mut records = csv_records(text)?.into_iter();
+    let header = records.next().ok_or("missing header line")?;
+    let column = |name: &str| header.iter().position(|column| column.trim() == name);
+    let key_column = column("key").ok_or("missing key column")?;
+    let version_id_column = column("version_id");
+    let target_key_column = column("target_key");
+
+    let field = |record: &[String], column: Option<usize>| {
+        column
+            .and_then(|column| record.get(column))
+            .filter(|value| !value.is_empty())
+            .cloned()
+    };
+    records
+        .enumerate()
+        .filter(|(_, record)| record.iter().any(|value| !value.is_empty()))
+        .map(|(index, record)| {
+            Ok(ManifestEntry {
+                key: field(&record, Some(key_column))
+                    .ok_or_else(|| format!("record {}: missing key", index + 1))?,
+                version_id: field(&record, version_id_column),
+                target_key: field(&record, target_key_column),
+            })
+        })
+        .collect()
+}
+
+/// Splits CSV into records of fields, with quoted fields possibly holding
+/// separators, quotes (doubled) and line breaks.
+fn csv_records(text: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => record.push(std::mem::take(&mut field)),
+            (false, '\r') if chars.peek() == Some(&'\n') => {}
+            (false, '\n') => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            (false, c) => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("unterminated quoted field".to_owned());
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    Ok(records)
+}
@@ -0,0 +1,161 @@
+use clap::Parser;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use s3_utils::client::Provider;
+use s3_utils::config::static_credentials;
+use s3_utils::{
+    mirror, Config, LocalStore, Location, MirrorOptions, RemoteConfig, S3Store, StorePath,
+    TransferOptions, TransferSummary,
+};
+
+use crate::console::{print_events, print_summary};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+pub struct Args {
+    pub mirror: String,
+
+    /// `s3://bucket/prefix`, `remote:bucket/prefix` or local directory
+    pub source: String,
+
+    /// `s3://bucket/prefix`, `remote:bucket/prefix` or local directory
+    pub destination: String,
+
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    #[arg(long)]
+    pub region: Option<String>,
+
+    #[arg(long)]
+    pub aws_access_key_id: Option<String>,
+
+    #[arg(long)]
+    pub aws_secret_access_key: Option<String>,
+
+    #[arg(long)]
+    pub endpoint: Option<String>,
+
+    #[arg(long, value_enum)]
+    pub provider: Option<Provider>,
+
+    #[arg(long)]
+    pub account_id: Option<String>,
+
+    #[arg(long)]
+    pub force_path_style: bool,
+
+    #[arg(long)]
+    pub use_http: bool,
+
+    #[arg(long)]
+    pub insecure: bool,
+
+    #[arg(long)]
+    pub ca_bundle: Option<PathBuf>,
+
+    #[arg(long)]
+    pub concurrency: Option<usize>,
+
+    #[arg(long)]
+    pub max_requests_per_second: Option<f64>,
+
+    /// Seconds between the start of two polls
+    #[arg(long, default_value_t = 60)]
+    pub interval: u64,
+
+    /// File keeping the objects seen by the last poll, to resume from
+    #[arg(long)]
+    pub state: Option<PathBuf>,
+
+    /// Delete the destination objects whose source is gone
+    #[arg(long)]
+    pub delete: bool,
+
+    /// Poll once and exit, e.g. from cron with `--state`
+    #[arg(long)]
+    pub once: bool,
+}
+
+/// `mirror`: copies a bucket to another bucket or a local directory, then
+/// keeps copying what changes in it, polling every `--interval` seconds.
+pub async fn mirror_bucket() -> Result<TransferSummary, Box<dyn std::error::Error>> {
+    // Read command-line arguments
+    let args = Args::parse();
+    let config = Config::load(args.config.as_deref())?;
+    let source = Location::parse(&args.source, &config)?;
+    let destination = Location::parse(&args.destination, &config)?;
+
+    // Flags apply to both sides, on top of the settings of their remote
+    let flags = RemoteConfig {
+        provider: args.provider,
+        endpoint: args.endpoint,
+        region: args.region,
+        account_id: args.account_id,
+        credentials: static_credentials(args.aws_access_key_id, args.aws_secret_access_key),
+        force_path_style: args.force_path_style.then_some(true),
+        use_http: args.use_http.then_some(true),
+        insecure: args.insecure.then_some(true),
+        ca_bundle: args.ca_bundle,
+        ..Default::default()
+    };
+
+    // Remotes of the buckets, whose settings the throttle falls back to
+    let remotes = [&source, &destination]
+        .into_iter()
+        .filter_map(|location| match location {
+            Location::Bucket(path) => Some(path),
+            Location::Local(_) => None,
+        })
+        .map(|path| Ok(flags.clone().or(config.remote_for(path)?)))
+        .collect::<Result<Vec<_>, Box<dyn std::error::Error>>>()?;
+
+    let mut options = TransferOptions {
+        concurrency: args.concurrency,
+        max_requests_per_second: args.max_requests_per_second,
+        events: print_events(),
+        ..Default::default()
+    };
+    let concurrency = options.concurrency(remotes.iter().find_map(|remote| remote.concurrency), 50);
+    let throttle = options.throttle(
+        concurrency,
+        remotes
+            .iter()
+            .find_map(|remote| remote.max_requests_per_second),
+    );
+    options.concurrency = Some(concurrency);
+    let open = |location: Location| -> Result<StorePath, Box<dyn std::error::Error>> {
+        Ok(match location {
+            Location::Local(directory) => StorePath::new(LocalStore::new(directory), ""),
+            Location::Bucket(path) => {
+                let remote = flags.clone().or(config.remote_for(&path)?);
+                let store = S3Store::connect(&remote, &path.bucket, throttle.clone())?
+                    .with_events(options.events.clone());
+                StorePath::new(store, path.key_prefix())
+            }
+        })
+    };
+    if let Location::Local(directory) = &destination {
+        std::fs::create_dir_all(directory)
+            .map_err(|e| format!("Could not create {}: {}", directory.display(), e))?;
+    }
+    let source = open(source)?;
+    let destination = open(destination)?;
+
+    let summary = mirror(
+        &source,
+        &destination,
+        &options,
+        &MirrorOptions {
+            interval: Duration::from_secs(args.interval),
+            state: args.state,
+            delete: args.delete,
+            once: args.once,
+        },
+    )
+    .await?;
+    print_summary(&summary);
+
+    Ok(summary)
+}
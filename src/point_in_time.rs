@@ -0,0 +1,137 @@
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+
+use crate::copy::{transfer_object, SourceObject};
+use crate::history::list_histories;
+use crate::store::{StorePath, MAX_COPY_SIZE};
+use crate::transfer::{Progress, TransferEvent, TransferOptions, TransferSummary};
+
+/// Settings of `restore_point_in_time`, on top of the `TransferOptions`.
+#[derive(Debug, Clone)]
+pub struct PointInTimeOptions {
+    /// Time to restore the objects as they were at
+    pub at: DateTime<Utc>,
+    /// Delete the objects whose key did not exist then
+    pub delete: bool,
+}
+
+/// Restores the objects under `source`, a versioned bucket, as they were at
+/// a point in time: for each key, the version current then is copied to
+/// `destination`, or without one made the current version again by
+/// copying it in place, which keeps the later versions. Within a store, it
+/// is copied without being read. In place, keys whose current version has
+/// the same content as then are left alone.
+///
+/// Keys created later, or deleted then, are left alone unless `delete` is
+/// set, in which case they are deleted from the destination: in place, with
+/// a delete marker.
+pub async fn restore_point_in_time(
+    source: &StorePath,
+    destination: Option<&StorePath>,
+    options: &TransferOptions,
+    restore: &PointInTimeOptions,
+) -> Result<TransferSummary, Box<dyn std::error::Error>> {
+    if options.manifest.is_some() {
+        return Err("Objects are restored for a prefix, not a manifest".into());
+    }
+    if restore.at > Utc::now() {
+        return Err(format!("{} is in the future", restore.at.to_rfc3339()).into());
+    }
+    let concurrency = options.concurrency.unwrap_or(50);
+    let progress = Progress::new(options.events.clone());
+    let in_place = destination.is_none();
+    let destination = destination.unwrap_or(source);
+
+    list_histories(source, &progress)
+        .for_each_concurrent(concurrency, |(key, versions)| {
+            let progress = progress.clone();
+            async move {
+                let target_key = destination.key(source.relative_key(&key));
+                // The last version written by then, unless it was deleted
+                let then = versions
+                    .iter()
+                    .rev()
+                    .find(|version| {
+                        version
+                            .info
+                            .modified
+                            .is_some_and(|modified| modified <= restore.at)
+                    })
+                    .filter(|version| !version.delete_marker);
+
+                // The current version, unless the key is deleted
+                let current = versions.last().filter(|version| !version.delete_marker);
+
+                match then {
+                    // Or restored already, with the same content
+                    Some(version)
+                        if in_place
+                            && current.is_some_and(|current| {
+                                current.version_id == version.version_id
+                                    || (current.info.e_tag.is_some()
+                                        && current.info.e_tag == version.info.e_tag
+                                        && current.info.size == version.info.size)
+                            }) =>
+                    {
+                        progress.emit(TransferEvent::UpToDate { key: target_key })
+                    }
+                    // Copied without being read, unless too large for it
+                    Some(version)
+                        if source.same_store(destination) && version.info.size <= MAX_COPY_SIZE =>
+                    {
+                        match destination
+                            .store
+                            .copy(&key, version.version_id.as_deref(), &target_key)
+                            .await
+                        {
+                            Ok(_) => progress.emit(TransferEvent::Copied {
+                                key: target_key,
+                                bytes: version.info.size,
+                            }),
+                            Err(e) => progress.fail(&target_key, e),
+                        }
+                    }
+                    Some(version) => {
+                        let object = SourceObject {
+                            info: version.info.clone(),
+                            version_id: version.version_id.clone(),
+                            target_key: None,
+                        };
+                        transfer_object(
+                            source,
+                            destination,
+                            object,
+                            target_key,
+                            None,
+                            options,
+                            &progress,
+                        )
+                        .await;
+                    }
+                    None if !restore.delete => progress.emit(TransferEvent::Skipped {
+                        key,
+                        reason: format!("did not exist at {}", restore.at.to_rfc3339()),
+                    }),
+                    None => {
+                        let exists = match in_place {
+                            true => current.is_some(),
+                            false => match destination.store.head(&target_key).await {
+                                Ok(head) => head.is_some(),
+                                Err(e) => return progress.fail(&target_key, e),
+                            },
+                        };
+                        if !exists {
+                            return progress.emit(TransferEvent::UpToDate { key: target_key });
+                        }
+                        match destination.store.delete(&target_key).await {
+                            Ok(_) => progress.emit(TransferEvent::Deleted { key: target_key }),
+                            Err(e) => progress.fail(&target_key, e),
+                        }
+                    }
+                }
+            }
+        })
+        .await;
+
+    Ok(progress.summary())
+}
@@ -0,0 +1,246 @@
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::copy::transfer_between;
+use crate::delete::delete_from;
+use crate::manifest::{Manifest, ManifestEntry};
+use crate::store::{StoreError, StorePath};
+use crate::transfer::{Progress, TransferEvent, TransferOptions, TransferSummary};
+
+/// Settings of `mirror`, on top of the `TransferOptions`.
+#[derive(Debug, Clone)]
+pub struct MirrorOptions {
+    /// Time between the start of two polls
+    pub interval: Duration,
+    /// File keeping the objects seen by the last poll across runs
+    pub state: Option<PathBuf>,
+    /// Delete the target objects whose source is gone
+    pub delete: bool,
+    /// Poll once and return
+    pub once: bool,
+}
+
+impl Default for MirrorOptions {
+    fn default() -> Self {
+        MirrorOptions {
+            interval: Duration::from_secs(60),
+            state: None,
+            delete: false,
+            once: false,
+        }
+    }
+}
+
+/// What an object looked like when last polled.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Seen {
+    size: u64,
+    e_tag: Option<String>,
+    /// RFC 3339, for stores without ETags
+    modified: Option<String>,
+}
+
+/// Objects seen by the last poll, by key relative to the source prefix.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Snapshot {
+    objects: BTreeMap<String, Seen>,
+}
+
+impl Snapshot {
+    fn load(path: &Path) -> Result<Option<Snapshot>, Box<dyn std::error::Error>> {
+        match std::fs::read(path) {
+            Ok(data) => Ok(Some(serde_json::from_slice(&data).map_err(|e| {
+                format!("Invalid mirror state {}: {}", path.display(), e)
+            })?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("Could not read mirror state {}: {}", path.display(), e).into()),
+        }
+    }
+
+    /// Replaces the file at once, so that an interruption leaves the
+    /// previous state.
+    fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let temporary = path.with_extension("tmp");
+        std::fs::write(&temporary, serde_json::to_vec(self)?)?;
+        std::fs::rename(&temporary, path)?;
+        Ok(())
+    }
+}
+
+/// Copies the objects of `source` to `target`, then polls `source` for
+/// new, changed and deleted objects and mirrors them, until interrupted
+/// with Ctrl-C once the poll in progress is done. Returns the totals of
+/// every poll.
+///
+/// Each poll lists `source` and compares it with the previous one, so only
+/// what changed is read from `source` and `target` is not listed. Without a
+/// previous poll, `source` and `target` are synced instead.
+pub async fn mirror(
+    source: &StorePath,
+    target: &StorePath,
+    options: &TransferOptions,
+    mirror: &MirrorOptions,
+) -> Result<TransferSummary, Box<dyn std::error::Error>> {
+    if options.manifest.is_some() {
+        return Err("A mirror lists its source and cannot be given a manifest".into());
+    }
+    // Deletions need the target key of removed objects
+    if mirror.delete && (options.flatten || !options.keys.is_empty()) {
+        return Err("A mirror deleting objects keeps their keys as they are".into());
+    }
+    let mut snapshot = match &mirror.state {
+        Some(path) => Snapshot::load(path)?,
+        None => None,
+    };
+
+    let mut interrupted = tokio::spawn(tokio::signal::ctrl_c());
+    let mut total = TransferSummary::default();
+    loop {
+        let started = tokio::time::Instant::now();
+        let summary = poll(source, target, options, mirror, &mut snapshot).await?;
+        total.add(&summary);
+        if let (Some(path), Some(snapshot)) = (&mirror.state, &snapshot) {
+            snapshot.save(path)?;
+        }
+
+        if mirror.once {
+            break;
+        }
+        tokio::select! {
+            _ = &mut interrupted => break,
+            _ = tokio::time::sleep_until(started + mirror.interval) => {}
+        }
+    }
+
+    Ok(total)
+}
+
+/// Mirrors the changes since `snapshot`, and updates it with what was
+/// mirrored.
+async fn poll(
+    source: &StorePath,
+    target: &StorePath,
+    options: &TransferOptions,
+    mirror: &MirrorOptions,
+    snapshot: &mut Option<Snapshot>,
+) -> Result<TransferSummary, Box<dyn std::error::Error>> {
+    // Without a complete listing, changes and deletions are unknown
+    let progress = Progress::new(options.events.clone());
+    let Some(current) = list(source, &progress).await else {
+        return Ok(progress.summary());
+    };
+
+    let Some(previous) = snapshot.take() else {
+        let options = TransferOptions {
+            sync: true,
+            delete: mirror.delete,
+            ..options.clone()
+        };
+        let summary = transfer_between(source, target, &options).await?;
+        // Listed before syncing, so that later changes are mirrored next
+        // time, and kept unless the sync has to be tried again
+        if summary.failed == 0 {
+            *snapshot = Some(current);
+        }
+        return Ok(summary);
+    };
+
+    let changed = current
+        .objects
+        .iter()
+        .filter(|(key, seen)| previous.objects.get(*key) != Some(*seen))
+        .map(|(key, _)| key.clone())
+        .collect::<Vec<_>>();
+    let removed = previous
+        .objects
+        .keys()
+        .filter(|key| !current.objects.contains_key(*key))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let mut summary = TransferSummary::default();
+    if !changed.is_empty() {
+        let manifest = manifest(changed.iter().map(|key| source.key(key)));
+        let copied = transfer_between(
+            source,
+            target,
+            &TransferOptions {
+                manifest: Some(manifest),
+                ..options.clone()
+            },
+        )
+        .await?;
+        summary.add(&copied);
+    }
+    if mirror.delete && !removed.is_empty() {
+        let manifest = manifest(removed.iter().map(|key| target.key(key)));
+        let deleted = delete_from(
+            target,
+            false,
+            &TransferOptions {
+                manifest: Some(manifest),
+                ..options.clone()
+            },
+        )
+        .await?;
+        summary.add(&deleted);
+    }
+
+    // Which objects failed is not known, so retry every change next time
+    let mut next = current;
+    if summary.failed > 0 {
+        for key in changed.iter().chain(&removed) {
+            match previous.objects.get(key) {
+                Some(seen) => next.objects.insert(key.clone(), seen.clone()),
+                None => next.objects.remove(key),
+            };
+        }
+    }
+    *snapshot = Some(next);
+
+    Ok(summary)
+}
+
+/// The objects of `source`, or nothing if part of it could not be listed.
+async fn list(source: &StorePath, progress: &Progress) -> Option<Snapshot> {
+    let mut objects = source.store.list(&source.prefix);
+    let mut snapshot = Snapshot::default();
+    let mut complete = true;
+    while let Some(object) = objects.next().await {
+        match object {
+            Ok(info) => {
+                let seen = Seen {
+                    size: info.size,
+                    e_tag: info.e_tag,
+                    modified: info.modified.map(|modified| modified.to_rfc3339()),
+                };
+                let relative_key = source.relative_key(&info.key).to_owned();
+                snapshot.objects.insert(relative_key, seen);
+            }
+            Err(StoreError::Skipped { key, reason }) => {
+                progress.emit(TransferEvent::Skipped { key, reason })
+            }
+            Err(e) => {
+                progress.emit(TransferEvent::ListingFailed {
+                    prefix: source.prefix.clone(),
+                    error: e.to_string(),
+                });
+                complete = false;
+            }
+        }
+    }
+    complete.then_some(snapshot)
+}
+
+fn manifest(keys: impl Iterator<Item = String>) -> Manifest {
+    Manifest::new(
+        keys.map(|key| ManifestEntry {
+            key,
+            ..Default::default()
+        })
+        .collect(),
+    )
+}
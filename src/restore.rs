@@ -0,0 +1,205 @@
+use clap::Parser;
+use std::path::PathBuf;
+
+use s3_utils::client::Provider;
+use s3_utils::config::static_credentials;
+use s3_utils::{
+    parse_time, restore_point_in_time, Config, LocalStore, Location, PointInTimeOptions,
+    RemoteConfig, S3Store, StorePath, TransferOptions, TransferSummary,
+};
+
+use crate::console::{print_events, print_summary};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+pub struct Args {
+    pub restore: String,
+
+    /// Objects to restore, `s3://bucket/prefix` or `remote:bucket/prefix` of
+    /// a versioned bucket
+    pub location: String,
+
+    /// Time to restore the objects as they were at, e.g.
+    /// `2024-05-01T12:00:00Z`, or a date for its midnight UTC
+    #[arg(long, value_parser = parse_time)]
+    pub at: chrono::DateTime<chrono::Utc>,
+
+    /// Restore into this `s3://bucket/prefix`, `remote:bucket/prefix` or
+    /// local directory rather than in place
+    #[arg(long)]
+    pub to: Option<String>,
+
+    /// Delete the objects whose key did not exist at that time
+    #[arg(long)]
+    pub delete: bool,
+
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    #[arg(long)]
+    pub region: Option<String>,
+
+    #[arg(long)]
+    pub aws_access_key_id: Option<String>,
+
+    #[arg(long)]
+    pub aws_secret_access_key: Option<String>,
+
+    #[arg(long)]
+    pub endpoint: Option<String>,
+
+    #[arg(long, value_enum)]
+    pub provider: Option<Provider>,
+
+    #[arg(long)]
+    pub account_id: Option<String>,
+
+    #[arg(long)]
+    pub force_path_style: bool,
+
+    #[arg(long)]
+    pub use_http: bool,
+
+    #[arg(long)]
+    pub insecure: bool,
+
+    #[arg(long)]
+    pub ca_bundle: Option<PathBuf>,
+
+    #[arg(long)]
+    pub concurrency: Option<usize>,
+
+    #[arg(long)]
+    pub max_requests_per_second: Option<f64>,
+}
+
+/// `restore`: restores the objects under a prefix of a versioned bucket as
+/// they were at a point in time, in place or into another location.
+pub async fn restore_prefix() -> Result<TransferSummary, Box<dyn std::error::Error>> {
+    // Read command-line arguments
+    let args = Args::parse();
+    let config = Config::load(args.config.as_deref())?;
+    let location = match Location::parse(&args.location, &config)? {
+        Location::Bucket(path) => path,
+        Location::Local(directory) => {
+            return Err(format!(
+                "restore needs a versioned bucket, not the local directory {}",
+                directory.display()
+            )
+            .into())
+        }
+    };
+    let destination = args
+        .to
+        .as_deref()
+        .map(|to| Location::parse(to, &config))
+        .transpose()?;
+
+    // Flags apply to both sides, on top of the settings of their remote
+    let flags = RemoteConfig {
+        provider: args.provider,
+        endpoint: args.endpoint,
+        region: args.region,
+        account_id: args.account_id,
+        credentials: static_credentials(args.aws_access_key_id, args.aws_secret_access_key),
+        force_path_style: args.force_path_style.then_some(true),
+        use_http: args.use_http.then_some(true),
+        insecure: args.insecure.then_some(true),
+        ca_bundle: args.ca_bundle,
+        ..Default::default()
+    };
+
+    let remote = flags.clone().or(config.remote_for(&location)?);
+    let destination_remote = match &destination {
+        Some(Location::Bucket(path)) => Some(flags.clone().or(config.remote_for(path)?)),
+        _ => None,
+    };
+    let mut options = TransferOptions {
+        concurrency: args.concurrency,
+        max_requests_per_second: args.max_requests_per_second,
+        events: print_events(),
+        ..Default::default()
+    };
+    let concurrency = options.concurrency(
+        remote.concurrency.or_else(|| {
+            destination_remote
+                .as_ref()
+                .and_then(|remote| remote.concurrency)
+        }),
+        50,
+    );
+    let throttle = options.throttle(
+        concurrency,
+        remote.max_requests_per_second.or_else(|| {
+            destination_remote
+                .as_ref()
+                .and_then(|remote| remote.max_requests_per_second)
+        }),
+    );
+    options.concurrency = Some(concurrency);
+
+    // Without versioning, there is nothing but the current objects
+    let store = S3Store::connect(&remote, &location.bucket, throttle.clone())?
+        .with_events(options.events.clone());
+    let versioning = store.versioning().await.map_err(|e| {
+        format!(
+            "Could not check the versioning of {}: {}",
+            location.bucket, e
+        )
+    })?;
+    match versioning.as_deref() {
+        None => {
+            return Err(format!(
+                "Versioning was never enabled on {}, which has no earlier versions",
+                location.bucket
+            )
+            .into())
+        }
+        // Copies and delete markers would replace the null version, which
+        // may be the one to restore
+        Some(status) if status != "Enabled" && destination.is_none() => {
+            return Err(format!(
+                "Versioning is {} on {}, so restoring in place would overwrite its null version",
+                status.to_lowercase(),
+                location.bucket
+            )
+            .into())
+        }
+        Some(_) => {}
+    }
+    let source = StorePath::new(store, location.key_prefix());
+    let destination = match destination {
+        None => None,
+        Some(Location::Local(directory)) => {
+            std::fs::create_dir_all(&directory)
+                .map_err(|e| format!("Could not create {}: {}", directory.display(), e))?;
+            Some(StorePath::new(LocalStore::new(directory), ""))
+        }
+        // Within the bucket, versions are copied without being read
+        Some(Location::Bucket(path))
+            if path.remote == location.remote && path.bucket == location.bucket =>
+        {
+            Some(source.with_prefix(path.key_prefix()))
+        }
+        Some(Location::Bucket(path)) => {
+            let remote = flags.clone().or(config.remote_for(&path)?);
+            let store = S3Store::connect(&remote, &path.bucket, throttle)?
+                .with_events(options.events.clone());
+            Some(StorePath::new(store, path.key_prefix()))
+        }
+    };
+
+    let summary = restore_point_in_time(
+        &source,
+        destination.as_ref(),
+        &options,
+        &PointInTimeOptions {
+            at: args.at,
+            delete: args.delete,
+        },
+    )
+    .await?;
+    print_summary(&summary);
+
+    Ok(summary)
+}
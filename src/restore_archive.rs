@@ -0,0 +1,125 @@
+use clap::Parser;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use s3_utils::client::Provider;
+use s3_utils::config::static_credentials;
+use s3_utils::sse::SseOptions;
+use s3_utils::{
+    restore_objects, Config, RemoteConfig, RemotePath, RestoreOptions, RestoreTier,
+    TransferOptions, TransferSummary,
+};
+
+use crate::console::print_events;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+pub struct Args {
+    pub restore_archive: String,
+
+    /// Objects to restore, `[remote:]bucket[/prefix]`
+    pub location: String,
+
+    /// Days the restored copies stay readable
+    #[arg(long, default_value_t = 7)]
+    pub days: u32,
+
+    #[arg(long, value_enum, default_value_t)]
+    pub tier: RestoreTier,
+
+    /// Exit once every restore is done, checking on them every `--interval`
+    #[arg(long)]
+    pub wait: bool,
+
+    /// Seconds between two checks of the restores with `--wait`
+    #[arg(long, default_value_t = 60, requires = "wait")]
+    pub interval: u64,
+
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    #[arg(long)]
+    pub region: Option<String>,
+
+    #[arg(long)]
+    pub aws_access_key_id: Option<String>,
+
+    #[arg(long)]
+    pub aws_secret_access_key: Option<String>,
+
+    #[arg(long)]
+    pub endpoint: Option<String>,
+
+    #[arg(long, value_enum)]
+    pub provider: Option<Provider>,
+
+    #[arg(long)]
+    pub account_id: Option<String>,
+
+    #[arg(long)]
+    pub force_path_style: bool,
+
+    #[arg(long)]
+    pub use_http: bool,
+
+    #[arg(long)]
+    pub insecure: bool,
+
+    #[arg(long)]
+    pub ca_bundle: Option<PathBuf>,
+
+    #[arg(long)]
+    pub concurrency: Option<usize>,
+
+    #[arg(long)]
+    pub max_requests_per_second: Option<f64>,
+
+    /// Base64 encoded 256-bit SSE-C key of the objects
+    #[arg(long)]
+    pub sse_customer_key: Option<String>,
+}
+
+/// `restore_archive`: asks for the `GLACIER` and `DEEP_ARCHIVE` objects
+/// under a prefix to be restored, and reports the restores already asked
+/// for.
+pub async fn restore_archive() -> Result<TransferSummary, Box<dyn std::error::Error>> {
+    // Read command-line arguments
+    let args = Args::parse();
+    let config = Config::load(args.config.as_deref())?;
+    let location: RemotePath = args.location.parse()?;
+    let remote = RemoteConfig {
+        provider: args.provider,
+        endpoint: args.endpoint,
+        region: args.region,
+        account_id: args.account_id,
+        credentials: static_credentials(args.aws_access_key_id, args.aws_secret_access_key),
+        force_path_style: args.force_path_style.then_some(true),
+        use_http: args.use_http.then_some(true),
+        insecure: args.insecure.then_some(true),
+        ca_bundle: args.ca_bundle,
+        ..Default::default()
+    }
+    .or(config.remote_for(&location)?);
+
+    let options = TransferOptions {
+        concurrency: args.concurrency,
+        max_requests_per_second: args.max_requests_per_second,
+        source_sse: SseOptions::customer_key(args.sse_customer_key.as_deref())?,
+        events: print_events(),
+        ..Default::default()
+    };
+    let restore = RestoreOptions {
+        days: args.days,
+        tier: args.tier,
+        wait: args.wait,
+        interval: Duration::from_secs(args.interval),
+    };
+
+    let summary = restore_objects(&location, &remote, &restore, &options).await?;
+    println!(
+        "Done: {} requested, {} not archived or already requested, {} failed",
+        summary.transferred, summary.skipped, summary.failed
+    );
+
+    Ok(summary)
+}
@@ -0,0 +1,153 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use clap::ValueEnum;
+use rusoto_s3::{
+    CopyObjectRequest, CreateMultipartUploadRequest, GetObjectRequest, PutObjectRequest,
+    UploadPartRequest,
+};
+
+const CUSTOMER_ALGORITHM: &str = "AES256";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ServerSideEncryption {
+    #[value(name = "AES256")]
+    Aes256,
+    #[value(name = "aws:kms")]
+    AwsKms,
+}
+
+impl ServerSideEncryption {
+    fn header_value(&self) -> String {
+        match self {
+            ServerSideEncryption::Aes256 => "AES256".to_owned(),
+            ServerSideEncryption::AwsKms => "aws:kms".to_owned(),
+        }
+    }
+}
+
+/// A 256-bit SSE-C key, sent with every request touching the object.
+#[derive(Debug, Clone)]
+pub struct CustomerKey {
+    key: String,
+    key_md5: String,
+}
+
+impl CustomerKey {
+    /// Parses a base64 encoded 256-bit key.
+    pub fn parse(value: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let key = BASE64
+            .decode(value.trim())
+            .map_err(|e| format!("Invalid SSE-C key, expected base64: {}", e))?;
+        if key.len() != 32 {
+            return Err(format!("Invalid SSE-C key, expected 32 bytes, got {}", key.len()).into());
+        }
+
+        Ok(CustomerKey {
+            key: BASE64.encode(&key),
+            key_md5: BASE64.encode(md5::compute(&key).0),
+        })
+    }
+}
+
+/// Server-side encryption settings for the requests of one bucket.
+#[derive(Debug, Clone, Default)]
+pub struct SseOptions {
+    pub sse: Option<ServerSideEncryption>,
+    pub kms_key_id: Option<String>,
+    pub customer_key: Option<CustomerKey>,
+}
+
+impl SseOptions {
+    pub fn new(
+        sse: Option<ServerSideEncryption>,
+        kms_key_id: Option<String>,
+        customer_key: Option<&str>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        // A KMS key implies KMS encryption
+        let sse = match (sse, &kms_key_id) {
+            (None, Some(_)) => Some(ServerSideEncryption::AwsKms),
+            (Some(ServerSideEncryption::Aes256), Some(_)) => {
+                return Err("--sse-kms-key-id requires --sse aws:kms".into());
+            }
+            (sse, _) => sse,
+        };
+        let customer_key = customer_key.map(CustomerKey::parse).transpose()?;
+        if sse.is_some() && customer_key.is_some() {
+            return Err("SSE-C keys cannot be combined with --sse".into());
+        }
+
+        Ok(SseOptions {
+            sse,
+            kms_key_id,
+            customer_key,
+        })
+    }
+
+    /// Options to read objects encrypted with `customer_key`.
+    pub fn customer_key(customer_key: Option<&str>) -> Result<Self, Box<dyn std::error::Error>> {
+        SseOptions::new(None, None, customer_key)
+    }
+
+    pub(crate) fn customer_fields(&self) -> (Option<String>, Option<String>, Option<String>) {
+        match &self.customer_key {
+            Some(customer_key) => (
+                Some(CUSTOMER_ALGORITHM.to_owned()),
+                Some(customer_key.key.clone()),
+                Some(customer_key.key_md5.clone()),
+            ),
+            None => (None, None, None),
+        }
+    }
+
+    pub fn apply_to_put(&self, request: &mut PutObjectRequest) {
+        request.server_side_encryption = self.sse.map(|sse| sse.header_value());
+        request.ssekms_key_id = self.kms_key_id.clone();
+        (
+            request.sse_customer_algorithm,
+            request.sse_customer_key,
+            request.sse_customer_key_md5,
+        ) = self.customer_fields();
+    }
+
+    pub fn apply_to_create_multipart(&self, request: &mut CreateMultipartUploadRequest) {
+        request.server_side_encryption = self.sse.map(|sse| sse.header_value());
+        request.ssekms_key_id = self.kms_key_id.clone();
+        (
+            request.sse_customer_algorithm,
+            request.sse_customer_key,
+            request.sse_customer_key_md5,
+        ) = self.customer_fields();
+    }
+
+    pub fn apply_to_upload_part(&self, request: &mut UploadPartRequest) {
+        (
+            request.sse_customer_algorithm,
+            request.sse_customer_key,
+            request.sse_customer_key_md5,
+        ) = self.customer_fields();
+    }
+
+    /// For copies within a bucket, whose source shares the SSE-C key.
+    pub fn apply_to_copy(&self, request: &mut CopyObjectRequest) {
+        request.server_side_encryption = self.sse.map(|sse| sse.header_value());
+        request.ssekms_key_id = self.kms_key_id.clone();
+        (
+            request.sse_customer_algorithm,
+            request.sse_customer_key,
+            request.sse_customer_key_md5,
+        ) = self.customer_fields();
+        (
+            request.copy_source_sse_customer_algorithm,
+            request.copy_source_sse_customer_key,
+            request.copy_source_sse_customer_key_md5,
+        ) = self.customer_fields();
+    }
+
+    pub fn apply_to_get(&self, request: &mut GetObjectRequest) {
+        (
+            request.sse_customer_algorithm,
+            request.sse_customer_key,
+            request.sse_customer_key_md5,
+        ) = self.customer_fields();
+    }
+}
@@ -0,0 +1,478 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use futures_util::stream::BoxStream;
+use futures_util::StreamExt;
+use rusoto_core::RusotoError;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::sync::mpsc;
+
+use crate::transfer::{EventHandler, TransferEvent};
+
+mod local;
+mod memory;
+mod s3;
+
+pub use local::{KeyNormalization, LocalKeys, LocalStore, NonUtf8Names, WalkOptions};
+pub use memory::MemoryStore;
+pub use s3::S3Store;
+
+const PART_SIZE: usize = 20 * 1024 * 1024;
+/// Largest object a single copy can write
+pub(crate) const MAX_COPY_SIZE: u64 = 5 * 1024 * 1024 * 1024;
+/// Parts of a single object uploaded at once, each one held in memory
+const PART_CONCURRENCY: usize = 10;
+
+pub type ByteReader = Box<dyn AsyncRead + Unpin + Send>;
+
+/// Tags of an object, by key.
+pub type Tags = BTreeMap<String, String>;
+
+#[derive(Debug)]
+pub enum StoreError {
+    NotFound(String),
+    Io(std::io::Error),
+    Request(String),
+    /// A listed entry left out, e.g. a file whose name cannot be a key
+    Skipped {
+        key: String,
+        reason: String,
+    },
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::NotFound(key) => write!(f, "Not found: {}", key),
+            StoreError::Io(e) => write!(f, "{}", e),
+            StoreError::Request(message) => write!(f, "{}", message),
+            StoreError::Skipped { key, reason } => write!(f, "{}: {}", key, reason),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<std::io::Error> for StoreError {
+    fn from(e: std::io::Error) -> Self {
+        match e.kind() {
+            std::io::ErrorKind::NotFound => StoreError::NotFound(e.to_string()),
+            _ => StoreError::Io(e),
+        }
+    }
+}
+
+impl<E: std::error::Error + 'static> From<RusotoError<E>> for StoreError {
+    fn from(e: RusotoError<E>) -> Self {
+        match e {
+            RusotoError::Unknown(response) if response.status.as_u16() == 404 => {
+                StoreError::NotFound(String::from_utf8_lossy(&response.body).into_owned())
+            }
+            RusotoError::Unknown(response) => StoreError::Request(format!(
+                "HTTP {}: {}",
+                response.status,
+                String::from_utf8_lossy(&response.body)
+            )),
+            e => StoreError::Request(e.to_string()),
+        }
+    }
+}
+
+impl From<String> for StoreError {
+    fn from(message: String) -> Self {
+        StoreError::Request(message)
+    }
+}
+
+/// An entry of a listing.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ObjectInfo {
+    pub key: String,
+    pub size: u64,
+    pub modified: Option<DateTime<Utc>>,
+    pub e_tag: Option<String>,
+    pub storage_class: Option<String>,
+    /// Display name or ID of the owner, when the store knows it
+    pub owner: Option<String>,
+}
+
+/// What is known about an object without reading its body.
+#[derive(Debug, Clone, Default)]
+pub struct ObjectHead {
+    pub info: ObjectInfo,
+    /// User metadata (`x-amz-meta-*`)
+    pub metadata: HashMap<String, String>,
+    pub content_encoding: Option<String>,
+    /// Temporary copy of an archived object, once asked for
+    pub restore: Option<RestoreStatus>,
+    /// Number of tags, when known without asking for them
+    pub tag_count: usize,
+    /// Server-side encryption with a key of the store, `AES256` or `aws:kms`
+    pub server_side_encryption: Option<String>,
+    /// Key of `aws:kms` encryption
+    pub ssekms_key_id: Option<String>,
+    /// Whether the object is encrypted with a key of the client (SSE-C)
+    pub customer_encrypted: bool,
+}
+
+/// Progress of the restore of an archived object.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RestoreStatus {
+    InProgress,
+    /// Readable until `expiry`
+    Restored {
+        expiry: Option<DateTime<Utc>>,
+    },
+}
+
+/// Retrieval speed of an archived object, the faster the more expensive.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum RestoreTier {
+    /// Minutes, not available for `DEEP_ARCHIVE`
+    Expedited,
+    /// Hours
+    #[default]
+    Standard,
+    /// Up to two days
+    Bulk,
+}
+
+impl RestoreTier {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RestoreTier::Expedited => "Expedited",
+            RestoreTier::Standard => "Standard",
+            RestoreTier::Bulk => "Bulk",
+        }
+    }
+}
+
+pub struct ObjectBody {
+    pub head: ObjectHead,
+    pub body: ByteReader,
+}
+
+/// Settings of a written object.
+#[derive(Debug, Clone, Default)]
+pub struct PutOptions {
+    pub metadata: Option<HashMap<String, String>>,
+    pub content_encoding: Option<String>,
+    pub tags: Tags,
+}
+
+impl PutOptions {
+    /// Keeps the metadata and encoding of `head`, but not its tags, which
+    /// it does not hold.
+    pub fn from_head(head: &ObjectHead) -> Self {
+        PutOptions {
+            metadata: (!head.metadata.is_empty()).then(|| head.metadata.clone()),
+            content_encoding: head.content_encoding.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// An entry of a listing of versions: an object or a delete marker.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ObjectVersion {
+    pub info: ObjectInfo,
+    /// Missing for stores without versioning
+    pub version_id: Option<String>,
+    pub is_latest: bool,
+    pub delete_marker: bool,
+}
+
+/// An object just written.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WrittenObject {
+    pub size: u64,
+    /// Id of the new version, in stores with versioning enabled
+    pub version_id: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UploadedPart {
+    pub part_number: usize,
+    pub e_tag: Option<String>,
+}
+
+/// A flat namespace of objects: an S3 bucket, a local directory or memory.
+///
+/// Keys always use `/` as separator, whatever the backend.
+#[async_trait]
+pub trait ObjectStore: Send + Sync {
+    /// Every object whose key starts with `prefix`, in no particular order.
+    fn list(&self, prefix: &str) -> BoxStream<'static, Result<ObjectInfo, StoreError>>;
+
+    async fn head(&self, key: &str) -> Result<Option<ObjectHead>, StoreError>;
+
+    /// Reads an object, or the given byte range of it.
+    async fn get(&self, key: &str, range: Option<Range<u64>>) -> Result<ObjectBody, StoreError>;
+
+    /// Writes an object and returns the id of its new version, if any.
+    async fn put(
+        &self,
+        key: &str,
+        body: Bytes,
+        options: &PutOptions,
+    ) -> Result<Option<String>, StoreError>;
+
+    async fn create_multipart(&self, key: &str, options: &PutOptions)
+        -> Result<String, StoreError>;
+
+    async fn upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: usize,
+        body: Bytes,
+    ) -> Result<UploadedPart, StoreError>;
+
+    /// Assembles the parts, which may be given in any order, and returns
+    /// the id of the new version, if any.
+    async fn complete_multipart(
+        &self,
+        key: &str,
+        upload_id: &str,
+        parts: Vec<UploadedPart>,
+    ) -> Result<Option<String>, StoreError>;
+
+    async fn abort_multipart(&self, key: &str, upload_id: &str) -> Result<(), StoreError>;
+
+    /// Deletes an object and returns the id of the delete marker left in
+    /// its place, if any.
+    async fn delete(&self, key: &str) -> Result<Option<String>, StoreError>;
+
+    /// Every version and delete marker under `prefix`, those of a key one
+    /// after the other. Stores without versioning list their objects.
+    fn list_versions(&self, prefix: &str) -> BoxStream<'static, Result<ObjectVersion, StoreError>> {
+        self.list(prefix)
+            .map(|info| {
+                info.map(|info| ObjectVersion {
+                    info,
+                    version_id: None,
+                    is_latest: true,
+                    delete_marker: false,
+                })
+            })
+            .boxed()
+    }
+
+    /// Deletes a version of an object for good, or the object itself
+    /// without `version_id`.
+    async fn delete_version(&self, key: &str, _version_id: Option<&str>) -> Result<(), StoreError> {
+        self.delete(key).await.map(|_| ())
+    }
+
+    /// Reads a version of an object, or the object itself without
+    /// `version_id`.
+    async fn get_version(
+        &self,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> Result<ObjectBody, StoreError> {
+        match version_id {
+            Some(_) => Err(StoreError::Request(format!(
+                "Versions are not supported here: {}",
+                key
+            ))),
+            None => self.get(key, None).await,
+        }
+    }
+
+    /// Copies an object, or a version of it, within the store, keeping its
+    /// metadata.
+    async fn copy(
+        &self,
+        source_key: &str,
+        version_id: Option<&str>,
+        key: &str,
+    ) -> Result<(), StoreError>;
+
+    /// Tags of a version of an object, or of the object itself without
+    /// `version_id`. Stores without tags have none.
+    async fn get_tags(&self, _key: &str, _version_id: Option<&str>) -> Result<Tags, StoreError> {
+        Ok(Tags::new())
+    }
+
+    /// Replaces the tags of an object.
+    async fn set_tags(&self, key: &str, _tags: &Tags) -> Result<(), StoreError> {
+        Err(StoreError::Request(format!(
+            "Tags are not supported here: {}",
+            key
+        )))
+    }
+
+    /// Rewrites an object in place with another storage class, keeping its
+    /// metadata and the encryption of its `head`.
+    async fn set_storage_class(
+        &self,
+        key: &str,
+        _storage_class: &str,
+        _head: &ObjectHead,
+    ) -> Result<(), StoreError> {
+        Err(StoreError::Request(format!(
+            "Storage classes are not supported here: {}",
+            key
+        )))
+    }
+
+    /// Asks for a temporary copy of an archived object, readable for `days`.
+    async fn restore(&self, key: &str, _days: u32, _tier: RestoreTier) -> Result<(), StoreError> {
+        Err(StoreError::Request(format!(
+            "Archived objects are not supported here: {}",
+            key
+        )))
+    }
+
+    /// Removes the bucket or directory itself, which must be empty.
+    async fn delete_bucket(&self) -> Result<(), StoreError>;
+
+    /// Writes everything `reader` yields to `key`, whose size does not need
+    /// to be known in advance.
+    async fn put_reader(
+        &self,
+        key: &str,
+        reader: ByteReader,
+        options: &PutOptions,
+    ) -> Result<WrittenObject, StoreError> {
+        upload_in_parts(self, key, reader, options, &EventHandler::default()).await
+    }
+
+    /// Whether `key` can be stored here, e.g. is a valid path.
+    fn accepts_key(&self, _key: &str) -> bool {
+        true
+    }
+
+    /// Directory of a local store, which keeps no metadata.
+    fn local_root(&self) -> Option<&Path> {
+        None
+    }
+}
+
+/// A store and the prefix of the objects to act on.
+#[derive(Clone)]
+pub struct StorePath {
+    pub store: Arc<dyn ObjectStore>,
+    /// Empty or ending with `/`
+    pub prefix: String,
+}
+
+impl StorePath {
+    pub fn new(store: impl ObjectStore + 'static, prefix: impl Into<String>) -> Self {
+        StorePath {
+            store: Arc::new(store),
+            prefix: prefix.into(),
+        }
+    }
+
+    pub fn key(&self, relative_key: &str) -> String {
+        format!("{}{}", self.prefix, relative_key)
+    }
+
+    pub fn relative_key<'a>(&self, key: &'a str) -> &'a str {
+        key.strip_prefix(&self.prefix).unwrap_or(key)
+    }
+
+    /// Another prefix of the same store.
+    pub fn with_prefix(&self, prefix: impl Into<String>) -> Self {
+        StorePath {
+            store: self.store.clone(),
+            prefix: prefix.into(),
+        }
+    }
+
+    /// Whether both paths are in the same store, where objects can be
+    /// copied without being read.
+    pub fn same_store(&self, other: &StorePath) -> bool {
+        Arc::as_ptr(&self.store) as *const () == Arc::as_ptr(&other.store) as *const ()
+    }
+}
+
+async fn read_part(reader: &mut ByteReader) -> std::io::Result<Vec<u8>> {
+    let mut body = Vec::with_capacity(PART_SIZE);
+    reader.take(PART_SIZE as u64).read_to_end(&mut body).await?;
+    Ok(body)
+}
+
+/// Writes `reader` with a single `put` when it fits in one part and a
+/// multipart upload otherwise, uploading several parts at once.
+pub(crate) async fn upload_in_parts<S>(
+    store: &S,
+    key: &str,
+    mut reader: ByteReader,
+    options: &PutOptions,
+    events: &EventHandler,
+) -> Result<WrittenObject, StoreError>
+where
+    S: ObjectStore + ?Sized,
+{
+    let first_part = read_part(&mut reader).await?;
+
+    if first_part.len() < PART_SIZE {
+        let size = first_part.len() as u64;
+        let version_id = store.put(key, first_part.into(), options).await?;
+        return Ok(WrittenObject { size, version_id });
+    }
+
+    let upload_id = store.create_multipart(key, options).await?;
+
+    let (tx, rx) = mpsc::channel(PART_CONCURRENCY);
+    let producer = tokio::spawn(async move {
+        let mut part_number = 1;
+        let mut size = 0;
+        let mut body = first_part;
+        while !body.is_empty() {
+            size += body.len() as u64;
+            if tx.send((part_number, body)).await.is_err() {
+                break;
+            }
+            part_number += 1;
+            body = read_part(&mut reader).await?;
+        }
+        Ok::<u64, std::io::Error>(size)
+    });
+
+    let results = tokio_stream::wrappers::ReceiverStream::new(rx)
+        .map(|(part_number, body): (usize, Vec<u8>)| {
+            let upload_id = upload_id.clone();
+            async move {
+                let part = store
+                    .upload_part(key, &upload_id, part_number, body.into())
+                    .await?;
+                events.emit(TransferEvent::PartUploaded {
+                    key: key.to_owned(),
+                    part_number,
+                });
+                Ok::<_, StoreError>(part)
+            }
+        })
+        .buffer_unordered(PART_CONCURRENCY)
+        .collect::<Vec<_>>()
+        .await;
+
+    let read_result = producer
+        .await
+        .map_err(|e| StoreError::Request(e.to_string()))?;
+    let parts = read_result
+        .map_err(StoreError::from)
+        .and_then(|size| Ok((size, results.into_iter().collect::<Result<Vec<_>, _>>()?)));
+
+    match parts {
+        Ok((size, parts)) => {
+            let version_id = store.complete_multipart(key, &upload_id, parts).await?;
+            Ok(WrittenObject { size, version_id })
+        }
+        Err(e) => {
+            // Do not leave the uploaded parts behind
+            let _ = store.abort_multipart(key, &upload_id).await;
+            Err(e)
+        }
+    }
+}
@@ -0,0 +1,471 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use futures_util::stream::BoxStream;
+use futures_util::StreamExt;
+use ignore::{DirEntry, WalkBuilder};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::future::Future;
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::fs::{self, File};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::mpsc;
+use unicode_normalization::UnicodeNormalization;
+
+use super::{
+    ByteReader, ObjectBody, ObjectHead, ObjectInfo, ObjectStore, PutOptions, StoreError,
+    UploadedPart, WrittenObject,
+};
+
+/// Parts of multipart uploads in progress, under the root
+const UPLOADS_DIRECTORY: &str = ".s3-utils-uploads";
+/// Suffix of the files being written, moved over their destination once
+/// complete
+const PARTIAL_SUFFIX: &str = ".s3-utils-partial";
+/// Files listing patterns of files to leave out, like `.gitignore`
+const IGNORE_FILE: &str = ".s3ignore";
+
+/// What to do with file names that are not valid UTF-8, which keys must be.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum NonUtf8Names {
+    /// Leave the file out of listings, reporting it as skipped
+    #[default]
+    Skip,
+    /// Replace the invalid bytes with U+FFFD
+    Lossy,
+    /// Replace the invalid bytes with `%XX`
+    PercentEncode,
+}
+
+/// Unicode normalization form of the keys, as file systems differ: macOS
+/// tends to decompose accented letters, Linux and Windows to keep them as
+/// typed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum KeyNormalization {
+    Nfc,
+    Nfd,
+}
+
+/// How keys are derived from the paths of local files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LocalKeys {
+    pub non_utf8: NonUtf8Names,
+    pub normalization: Option<KeyNormalization>,
+}
+
+impl LocalKeys {
+    /// Key of a path relative to the root, with `/` separators, or why it
+    /// cannot have one.
+    fn key(&self, relative_path: &Path) -> Result<String, &'static str> {
+        let mut names = Vec::new();
+        for component in relative_path.components() {
+            let name = component.as_os_str();
+            names.push(match (name.to_str(), self.non_utf8) {
+                (Some(name), _) => Cow::Borrowed(name),
+                (None, NonUtf8Names::Skip) => return Err("file name is not valid UTF-8"),
+                (None, NonUtf8Names::Lossy) => name.to_string_lossy(),
+                (None, NonUtf8Names::PercentEncode) => Cow::Owned(percent_encode_invalid(name)),
+            });
+        }
+        let key = names.join("/");
+        Ok(match self.normalization {
+            Some(KeyNormalization::Nfc) => key.nfc().collect(),
+            Some(KeyNormalization::Nfd) => key.nfd().collect(),
+            None => key,
+        })
+    }
+}
+
+#[cfg(unix)]
+fn percent_encode_invalid(name: &OsStr) -> String {
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut encoded = String::new();
+    for chunk in name.as_bytes().utf8_chunks() {
+        encoded.push_str(chunk.valid());
+        for byte in chunk.invalid() {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// Names are UTF-16 there, whose unpaired surrogates have no bytes to encode.
+#[cfg(not(unix))]
+fn percent_encode_invalid(name: &OsStr) -> String {
+    name.to_string_lossy().into_owned()
+}
+
+/// Which entries of the directory tree are listed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WalkOptions {
+    /// List the targets of symbolic links rather than skipping them
+    pub follow_symlinks: bool,
+    /// Leave out the files and directories whose name starts with `.`
+    pub skip_hidden: bool,
+    /// Leave out the files matched by `.gitignore` and `.s3ignore` files
+    pub ignore_files: bool,
+    /// Levels of directories listed, 1 for the files of the root only
+    pub max_depth: Option<usize>,
+    /// Do not descend into other file systems, e.g. mounts
+    pub one_file_system: bool,
+}
+
+impl WalkOptions {
+    fn builder(&self, root: &Path) -> WalkBuilder {
+        let mut builder = WalkBuilder::new(root);
+        builder
+            .standard_filters(false)
+            .follow_links(self.follow_symlinks)
+            .hidden(self.skip_hidden)
+            .git_ignore(self.ignore_files)
+            .require_git(false)
+            .max_depth(self.max_depth)
+            .same_file_system(self.one_file_system)
+            .filter_entry(|entry| entry.depth() != 1 || entry.file_name() != UPLOADS_DIRECTORY);
+        if self.ignore_files {
+            builder.add_custom_ignore_filename(IGNORE_FILE);
+        }
+        builder
+    }
+}
+
+/// Listed object of a walked entry, nothing for directories.
+fn list_entry(
+    entry: Result<DirEntry, ignore::Error>,
+    root: &Path,
+    keys: LocalKeys,
+    paths: &Mutex<HashMap<String, PathBuf>>,
+    prefix: &str,
+) -> Option<Result<ObjectInfo, StoreError>> {
+    let entry = match entry {
+        Ok(entry) => entry,
+        Err(e) => return Some(Err(StoreError::Request(e.to_string()))),
+    };
+    let relative_path = entry.path().strip_prefix(root).unwrap_or(entry.path());
+    let skipped = |reason: &str| {
+        Some(Err(StoreError::Skipped {
+            key: relative_path.to_string_lossy().into_owned(),
+            reason: reason.to_owned(),
+        }))
+    };
+    let file_type = entry.file_type()?;
+    if file_type.is_dir() || is_partial(entry.file_name()) {
+        return None;
+    }
+    if file_type.is_symlink() {
+        return skipped("symbolic link, not followed");
+    }
+    if !file_type.is_file() {
+        return skipped("not a regular file");
+    }
+
+    let key = match keys.key(relative_path) {
+        Ok(key) => key,
+        Err(reason) => return skipped(reason),
+    };
+    if !key.starts_with(prefix) {
+        return None;
+    }
+    if root.join(&key) != entry.path() {
+        paths
+            .lock()
+            .unwrap()
+            .insert(key.clone(), entry.path().to_owned());
+    }
+
+    Some(
+        entry
+            .metadata()
+            .map(|metadata| object_info(key, &metadata))
+            .map_err(|e| StoreError::Request(e.to_string())),
+    )
+}
+
+/// A local directory, whose files are objects keyed by their path relative
+/// to it. Metadata and content encodings are not kept.
+#[derive(Debug, Clone)]
+pub struct LocalStore {
+    root: PathBuf,
+    keys: LocalKeys,
+    walk: WalkOptions,
+    /// Listed files whose key is not their path, e.g. once normalized
+    paths: Arc<Mutex<HashMap<String, PathBuf>>>,
+}
+
+impl LocalStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        LocalStore {
+            root: root.into(),
+            keys: LocalKeys::default(),
+            walk: WalkOptions::default(),
+            paths: Default::default(),
+        }
+    }
+
+    pub fn with_keys(mut self, keys: LocalKeys) -> Self {
+        self.keys = keys;
+        self
+    }
+
+    pub fn with_walk(mut self, walk: WalkOptions) -> Self {
+        self.walk = walk;
+        self
+    }
+
+    /// Path of `key`, unless it is a directory marker or would escape the root.
+    fn path(&self, key: &str) -> Result<PathBuf, StoreError> {
+        if let Some(path) = self.paths.lock().unwrap().get(key) {
+            return Ok(path.clone());
+        }
+        if !self.accepts_key(key) {
+            return Err(StoreError::Request(format!(
+                "Not a valid file path: {}",
+                key
+            )));
+        }
+        Ok(self.root.join(key))
+    }
+
+    fn parts_directory(&self, upload_id: &str) -> PathBuf {
+        self.root.join(UPLOADS_DIRECTORY).join(upload_id)
+    }
+}
+
+fn is_partial(file_name: &OsStr) -> bool {
+    file_name.to_string_lossy().ends_with(PARTIAL_SUFFIX)
+}
+
+/// Writes the file at `path` with `write` into a partial file next to it,
+/// then moves it into place: a failed write leaves the previous file, if
+/// any, as it was.
+async fn replace_file<F, Fut, T>(path: &Path, write: F) -> Result<T, StoreError>
+where
+    F: FnOnce(File) -> Fut,
+    Fut: Future<Output = std::io::Result<T>>,
+{
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let partial = path.with_file_name(format!(
+        ".{}.{:08x}{}",
+        file_name,
+        rand::random::<u32>(),
+        PARTIAL_SUFFIX
+    ));
+
+    let result = match File::create(&partial).await {
+        Ok(file) => write(file).await,
+        Err(e) => Err(e),
+    };
+    let result = match result {
+        Ok(output) => fs::rename(&partial, path).await.map(|_| output),
+        Err(e) => Err(e),
+    };
+    if result.is_err() {
+        let _ = fs::remove_file(&partial).await;
+    }
+    Ok(result?)
+}
+
+fn object_info(key: String, metadata: &std::fs::Metadata) -> ObjectInfo {
+    ObjectInfo {
+        key,
+        size: metadata.len(),
+        modified: metadata.modified().ok().map(DateTime::<Utc>::from),
+        ..Default::default()
+    }
+}
+
+#[async_trait]
+impl ObjectStore for LocalStore {
+    fn list(&self, prefix: &str) -> BoxStream<'static, Result<ObjectInfo, StoreError>> {
+        let root = self.root.clone();
+        let keys = self.keys;
+        let paths = self.paths.clone();
+        let prefix = prefix.to_owned();
+        let walker = self.walk.builder(&root);
+        let (tx, rx) = mpsc::channel(1000);
+
+        tokio::task::spawn_blocking(move || {
+            let walker = walker.build();
+            for entry in walker {
+                let Some(result) = list_entry(entry, &root, keys, &paths, &prefix) else {
+                    continue;
+                };
+                if tx.blocking_send(result).is_err() {
+                    return;
+                }
+            }
+        });
+
+        tokio_stream::wrappers::ReceiverStream::new(rx).boxed()
+    }
+
+    async fn head(&self, key: &str) -> Result<Option<ObjectHead>, StoreError> {
+        match fs::metadata(self.path(key)?).await {
+            Ok(metadata) if metadata.is_file() => Ok(Some(ObjectHead {
+                info: object_info(key.to_owned(), &metadata),
+                ..Default::default()
+            })),
+            Ok(_) => Ok(None),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn get(&self, key: &str, range: Option<Range<u64>>) -> Result<ObjectBody, StoreError> {
+        let mut file = File::open(self.path(key)?).await?;
+        let metadata = file.metadata().await?;
+        let head = ObjectHead {
+            info: object_info(key.to_owned(), &metadata),
+            ..Default::default()
+        };
+
+        let body: ByteReader = match range {
+            Some(range) => {
+                file.seek(std::io::SeekFrom::Start(range.start)).await?;
+                Box::new(file.take(range.end.saturating_sub(range.start)))
+            }
+            None => Box::new(file),
+        };
+        Ok(ObjectBody { head, body })
+    }
+
+    async fn put(
+        &self,
+        key: &str,
+        body: Bytes,
+        _options: &PutOptions,
+    ) -> Result<Option<String>, StoreError> {
+        replace_file(&self.path(key)?, |mut file| async move {
+            file.write_all(&body).await?;
+            file.flush().await
+        })
+        .await?;
+        Ok(None)
+    }
+
+    async fn create_multipart(
+        &self,
+        key: &str,
+        _options: &PutOptions,
+    ) -> Result<String, StoreError> {
+        self.path(key)?;
+        let upload_id = format!("{:016x}", rand::random::<u64>());
+        fs::create_dir_all(self.parts_directory(&upload_id)).await?;
+        Ok(upload_id)
+    }
+
+    async fn upload_part(
+        &self,
+        _key: &str,
+        upload_id: &str,
+        part_number: usize,
+        body: Bytes,
+    ) -> Result<UploadedPart, StoreError> {
+        let part_path = self
+            .parts_directory(upload_id)
+            .join(part_number.to_string());
+        fs::write(part_path, body).await?;
+        Ok(UploadedPart {
+            part_number,
+            e_tag: None,
+        })
+    }
+
+    async fn complete_multipart(
+        &self,
+        key: &str,
+        upload_id: &str,
+        mut parts: Vec<UploadedPart>,
+    ) -> Result<Option<String>, StoreError> {
+        parts.sort_by_key(|part| part.part_number);
+        let parts_directory = self.parts_directory(upload_id);
+        let parts_directory = &parts_directory;
+        replace_file(&self.path(key)?, |mut file| async move {
+            for part in parts {
+                let mut part_file =
+                    File::open(parts_directory.join(part.part_number.to_string())).await?;
+                tokio::io::copy(&mut part_file, &mut file).await?;
+            }
+            file.flush().await
+        })
+        .await?;
+        fs::remove_dir_all(parts_directory).await?;
+        Ok(None)
+    }
+
+    async fn abort_multipart(&self, _key: &str, upload_id: &str) -> Result<(), StoreError> {
+        fs::remove_dir_all(self.parts_directory(upload_id)).await?;
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<Option<String>, StoreError> {
+        fs::remove_file(self.path(key)?).await?;
+        Ok(None)
+    }
+
+    async fn copy(
+        &self,
+        source_key: &str,
+        version_id: Option<&str>,
+        key: &str,
+    ) -> Result<(), StoreError> {
+        if version_id.is_some() {
+            return Err(StoreError::Request(format!(
+                "Versions are not supported here: {}",
+                source_key
+            )));
+        }
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::copy(self.path(source_key)?, path).await?;
+        Ok(())
+    }
+
+    async fn delete_bucket(&self) -> Result<(), StoreError> {
+        fs::remove_dir(&self.root).await?;
+        Ok(())
+    }
+
+    /// Streams into a partial file, moved over the previous one once the
+    /// whole body is read, so that a truncated or unauthenticated body
+    /// replaces nothing.
+    async fn put_reader(
+        &self,
+        key: &str,
+        mut reader: ByteReader,
+        _options: &PutOptions,
+    ) -> Result<WrittenObject, StoreError> {
+        replace_file(&self.path(key)?, |mut file| async move {
+            let size = tokio::io::copy(&mut reader, &mut file).await?;
+            file.flush().await?;
+            Ok(WrittenObject {
+                size,
+                version_id: None,
+            })
+        })
+        .await
+    }
+
+    fn accepts_key(&self, key: &str) -> bool {
+        !key.is_empty()
+            && !key.ends_with('/')
+            && Path::new(key)
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+    }
+
+    fn local_root(&self) -> Option<&Path> {
+        Some(&self.root)
+    }
+}
@@ -0,0 +1,221 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::Utc;
+use futures_util::stream::{self, BoxStream};
+use futures_util::StreamExt;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+use super::{
+    ObjectBody, ObjectHead, ObjectInfo, ObjectStore, PutOptions, StoreError, UploadedPart,
+};
+
+#[derive(Debug, Clone)]
+struct MemoryObject {
+    head: ObjectHead,
+    body: Bytes,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    objects: BTreeMap<String, MemoryObject>,
+    uploads: HashMap<String, (PutOptions, BTreeMap<usize, Bytes>)>,
+    next_upload_id: usize,
+}
+
+/// Objects held in memory, e.g. to test transfers without a provider.
+/// Clones share the same objects.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    state: Arc<Mutex<State>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+
+    /// Keys of every object, in order.
+    pub fn keys(&self) -> Vec<String> {
+        self.state.lock().unwrap().objects.keys().cloned().collect()
+    }
+
+    /// Body of the object at `key`.
+    pub fn body(&self, key: &str) -> Option<Bytes> {
+        let state = self.state.lock().unwrap();
+        state.objects.get(key).map(|object| object.body.clone())
+    }
+
+    fn insert(&self, key: &str, body: Bytes, options: &PutOptions) {
+        let head = ObjectHead {
+            info: ObjectInfo {
+                key: key.to_owned(),
+                size: body.len() as u64,
+                modified: Some(Utc::now()),
+                e_tag: Some(format!("\"{:x}\"", md5::compute(&body))),
+                ..Default::default()
+            },
+            metadata: options.metadata.clone().unwrap_or_default(),
+            content_encoding: options.content_encoding.clone(),
+            ..Default::default()
+        };
+        let mut state = self.state.lock().unwrap();
+        state
+            .objects
+            .insert(key.to_owned(), MemoryObject { head, body });
+    }
+
+    fn object(&self, key: &str) -> Result<MemoryObject, StoreError> {
+        let state = self.state.lock().unwrap();
+        state
+            .objects
+            .get(key)
+            .cloned()
+            .ok_or_else(|| StoreError::NotFound(key.to_owned()))
+    }
+}
+
+#[async_trait]
+impl ObjectStore for MemoryStore {
+    fn list(&self, prefix: &str) -> BoxStream<'static, Result<ObjectInfo, StoreError>> {
+        let state = self.state.lock().unwrap();
+        let objects = state
+            .objects
+            .range(prefix.to_owned()..)
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .map(|(_, object)| Ok(object.head.info.clone()))
+            .collect::<Vec<_>>();
+        stream::iter(objects).boxed()
+    }
+
+    async fn head(&self, key: &str) -> Result<Option<ObjectHead>, StoreError> {
+        let state = self.state.lock().unwrap();
+        Ok(state.objects.get(key).map(|object| object.head.clone()))
+    }
+
+    async fn get(&self, key: &str, range: Option<Range<u64>>) -> Result<ObjectBody, StoreError> {
+        let object = self.object(key)?;
+        let body = match range {
+            Some(range) => {
+                let end = (range.end as usize).min(object.body.len());
+                let start = (range.start as usize).min(end);
+                object.body.slice(start..end)
+            }
+            None => object.body,
+        };
+        Ok(ObjectBody {
+            head: object.head,
+            body: Box::new(std::io::Cursor::new(body)),
+        })
+    }
+
+    async fn put(
+        &self,
+        key: &str,
+        body: Bytes,
+        options: &PutOptions,
+    ) -> Result<Option<String>, StoreError> {
+        self.insert(key, body, options);
+        Ok(None)
+    }
+
+    async fn create_multipart(
+        &self,
+        _key: &str,
+        options: &PutOptions,
+    ) -> Result<String, StoreError> {
+        let mut state = self.state.lock().unwrap();
+        state.next_upload_id += 1;
+        let upload_id = state.next_upload_id.to_string();
+        state
+            .uploads
+            .insert(upload_id.clone(), (options.clone(), BTreeMap::new()));
+        Ok(upload_id)
+    }
+
+    async fn upload_part(
+        &self,
+        _key: &str,
+        upload_id: &str,
+        part_number: usize,
+        body: Bytes,
+    ) -> Result<UploadedPart, StoreError> {
+        let mut state = self.state.lock().unwrap();
+        let (_, parts) = state
+            .uploads
+            .get_mut(upload_id)
+            .ok_or_else(|| StoreError::NotFound(upload_id.to_owned()))?;
+        let e_tag = format!("\"{:x}\"", md5::compute(&body));
+        parts.insert(part_number, body);
+        Ok(UploadedPart {
+            part_number,
+            e_tag: Some(e_tag),
+        })
+    }
+
+    async fn complete_multipart(
+        &self,
+        key: &str,
+        upload_id: &str,
+        parts: Vec<UploadedPart>,
+    ) -> Result<Option<String>, StoreError> {
+        let (options, mut uploaded) = self
+            .state
+            .lock()
+            .unwrap()
+            .uploads
+            .remove(upload_id)
+            .ok_or_else(|| StoreError::NotFound(upload_id.to_owned()))?;
+
+        let mut part_numbers = parts
+            .iter()
+            .map(|part| part.part_number)
+            .collect::<Vec<_>>();
+        part_numbers.sort();
+        let mut body = Vec::new();
+        for part_number in part_numbers {
+            let part = uploaded
+                .remove(&part_number)
+                .ok_or_else(|| StoreError::Request(format!("Missing part {}", part_number)))?;
+            body.extend_from_slice(&part);
+        }
+
+        self.insert(key, body.into(), &options);
+        Ok(None)
+    }
+
+    async fn abort_multipart(&self, _key: &str, upload_id: &str) -> Result<(), StoreError> {
+        self.state.lock().unwrap().uploads.remove(upload_id);
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<Option<String>, StoreError> {
+        self.state.lock().unwrap().objects.remove(key);
+        Ok(None)
+    }
+
+    async fn copy(
+        &self,
+        source_key: &str,
+        version_id: Option<&str>,
+        key: &str,
+    ) -> Result<(), StoreError> {
+        if version_id.is_some() {
+            return Err(StoreError::Request(format!(
+                "Versions are not supported here: {}",
+                source_key
+            )));
+        }
+        let object = self.object(source_key)?;
+        self.insert(key, object.body, &PutOptions::from_head(&object.head));
+        Ok(())
+    }
+
+    async fn delete_bucket(&self) -> Result<(), StoreError> {
+        match self.state.lock().unwrap().objects.is_empty() {
+            true => Ok(()),
+            false => Err(StoreError::Request("The bucket is not empty".to_owned())),
+        }
+    }
+}
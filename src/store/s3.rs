@@ -0,0 +1,718 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::stream::{self, BoxStream};
+use futures_util::StreamExt;
+use rusoto_core::RusotoError;
+use rusoto_s3::{
+    AbortMultipartUploadRequest, CompleteMultipartUploadRequest, CompletedMultipartUpload,
+    CompletedPart, CopyObjectRequest, CreateMultipartUploadRequest, DeleteBucketRequest,
+    DeleteObjectRequest, GetBucketVersioningRequest, GetObjectRequest, GetObjectTaggingRequest,
+    GlacierJobParameters, HeadObjectRequest, ListObjectVersionsRequest, ListObjectsV2Request,
+    PutObjectRequest, PutObjectTaggingRequest, RestoreObjectRequest, RestoreRequest, S3Client, Tag,
+    Tagging, UploadPartRequest, S3,
+};
+use std::ops::Range;
+
+use super::{
+    upload_in_parts, ByteReader, ObjectBody, ObjectHead, ObjectInfo, ObjectStore, ObjectVersion,
+    PutOptions, RestoreStatus, RestoreTier, StoreError, Tags, UploadedPart, WrittenObject,
+};
+use crate::client::create_s3_client;
+use crate::config::RemoteConfig;
+use crate::sse::SseOptions;
+use crate::throttle::Throttle;
+use crate::transfer::EventHandler;
+use crate::utils::parse_timestamp;
+
+/// Longest key S3 accepts, in bytes of UTF-8
+const MAX_KEY_LENGTH: usize = 1024;
+
+/// A bucket of an S3-compatible provider. Every request goes through the
+/// throttle, which retries throttled requests.
+#[derive(Clone)]
+pub struct S3Store {
+    client: S3Client,
+    bucket: String,
+    throttle: Throttle,
+    sse: SseOptions,
+    supports_object_lock: bool,
+    events: EventHandler,
+}
+
+impl S3Store {
+    pub fn new(client: S3Client, bucket: impl Into<String>, throttle: Throttle) -> Self {
+        S3Store {
+            client,
+            bucket: bucket.into(),
+            throttle,
+            sse: SseOptions::default(),
+            supports_object_lock: false,
+            events: EventHandler::default(),
+        }
+    }
+
+    /// Connects to `bucket` with the settings of `remote`.
+    pub fn connect(
+        remote: &RemoteConfig,
+        bucket: impl Into<String>,
+        throttle: Throttle,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let client_options = remote.client_options()?;
+        Ok(S3Store {
+            supports_object_lock: client_options.quirks().supports_object_lock,
+            ..S3Store::new(create_s3_client(&client_options)?, bucket, throttle)
+        })
+    }
+
+    /// Server-side encryption of the written objects, and SSE-C key of the
+    /// read ones.
+    pub fn with_sse(mut self, sse: SseOptions) -> Self {
+        self.sse = sse;
+        self
+    }
+
+    /// Reports the progress of multipart uploads to `events`.
+    pub fn with_events(mut self, events: EventHandler) -> Self {
+        self.events = events;
+        self
+    }
+
+    pub fn bucket(&self) -> &str {
+        &self.bucket
+    }
+
+    /// Versioning status of the bucket, `Enabled` or `Suspended`, or `None`
+    /// if it was never enabled.
+    pub async fn versioning(&self) -> Result<Option<String>, StoreError> {
+        let versioning_request = GetBucketVersioningRequest {
+            bucket: self.bucket.clone(),
+            ..Default::default()
+        };
+        let output = self
+            .throttle
+            .run(|| {
+                self.client
+                    .get_bucket_versioning(versioning_request.clone())
+            })
+            .await?;
+        Ok(output.status)
+    }
+
+    /// Client and throttle for requests about the bucket itself.
+    pub(crate) fn client(&self) -> &S3Client {
+        &self.client
+    }
+
+    pub(crate) fn throttle(&self) -> &Throttle {
+        &self.throttle
+    }
+
+    async fn get_object(
+        &self,
+        key: &str,
+        version_id: Option<&str>,
+        range: Option<Range<u64>>,
+    ) -> Result<ObjectBody, StoreError> {
+        let mut download_request = GetObjectRequest {
+            bucket: self.bucket.clone(),
+            key: key.to_owned(),
+            version_id: version_id.map(str::to_owned),
+            range: range.map(|range| format!("bytes={}-{}", range.start, range.end - 1)),
+            ..Default::default()
+        };
+        self.sse.apply_to_get(&mut download_request);
+
+        let output = self
+            .throttle
+            .run(|| self.client.get_object(download_request.clone()))
+            .await?;
+
+        Ok(ObjectBody {
+            head: ObjectHead {
+                info: ObjectInfo {
+                    key: key.to_owned(),
+                    size: output.content_length.unwrap_or_default() as u64,
+                    modified: output.last_modified.as_deref().and_then(parse_http_date),
+                    e_tag: output.e_tag,
+                    storage_class: output.storage_class,
+                    ..Default::default()
+                },
+                metadata: output.metadata.unwrap_or_default(),
+                content_encoding: output.content_encoding,
+                restore: output.restore.as_deref().and_then(parse_restore),
+                tag_count: output.tag_count.unwrap_or_default() as usize,
+                server_side_encryption: output.server_side_encryption,
+                ssekms_key_id: output.ssekms_key_id,
+                customer_encrypted: output.sse_customer_algorithm.is_some(),
+            },
+            body: match output.body {
+                Some(body) => Box::new(body.into_async_read()),
+                None => Box::new(tokio::io::empty()),
+            },
+        })
+    }
+
+    /// Deletes a version, or the object itself, and returns the id of the
+    /// delete marker left, if any.
+    async fn delete_object(
+        &self,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> Result<Option<String>, StoreError> {
+        let delete_object_request = DeleteObjectRequest {
+            bucket: self.bucket.clone(),
+            key: key.to_owned(),
+            version_id: version_id.map(str::to_owned),
+            bypass_governance_retention: self.supports_object_lock.then_some(true),
+            ..Default::default()
+        };
+        let output = self
+            .throttle
+            .run(|| self.client.delete_object(delete_object_request.clone()))
+            .await?;
+        Ok(output.version_id)
+    }
+}
+
+#[async_trait]
+impl ObjectStore for S3Store {
+    fn list(&self, prefix: &str) -> BoxStream<'static, Result<ObjectInfo, StoreError>> {
+        let store = self.clone();
+        let prefix = prefix.to_owned();
+
+        // Pages are fetched one at a time, as the consumer gets to them
+        stream::unfold(Some(None), move |continuation_token| {
+            let store = store.clone();
+            let prefix = prefix.clone();
+            async move {
+                let continuation_token = continuation_token?;
+                let list_objects_request = ListObjectsV2Request {
+                    bucket: store.bucket.clone(),
+                    prefix: (!prefix.is_empty()).then(|| prefix.clone()),
+                    continuation_token,
+                    fetch_owner: Some(true),
+                    ..Default::default()
+                };
+
+                let output = match store
+                    .throttle
+                    .run(|| store.client.list_objects_v2(list_objects_request.clone()))
+                    .await
+                {
+                    Ok(output) => output,
+                    Err(e) => return Some((vec![Err(e.into())], None)),
+                };
+
+                let mut objects = output
+                    .contents
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|object| {
+                        Ok(ObjectInfo {
+                            key: object.key.unwrap_or_default(),
+                            size: object.size.unwrap_or_default() as u64,
+                            modified: object.last_modified.as_deref().and_then(parse_timestamp),
+                            e_tag: object.e_tag,
+                            storage_class: object.storage_class,
+                            owner: object.owner.and_then(owner_name),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                let next = match output.is_truncated.unwrap_or_default() {
+                    false => None,
+                    // Without a token, the listing would start over forever
+                    true if output.next_continuation_token.is_none() => {
+                        objects.push(Err(StoreError::Request(
+                            "Truncated listing without a continuation token".to_owned(),
+                        )));
+                        None
+                    }
+                    true => Some(output.next_continuation_token),
+                };
+                Some((objects, next))
+            }
+        })
+        .flat_map(stream::iter)
+        .boxed()
+    }
+
+    async fn head(&self, key: &str) -> Result<Option<ObjectHead>, StoreError> {
+        let mut head_request = HeadObjectRequest {
+            bucket: self.bucket.clone(),
+            key: key.to_owned(),
+            ..Default::default()
+        };
+        (
+            head_request.sse_customer_algorithm,
+            head_request.sse_customer_key,
+            head_request.sse_customer_key_md5,
+        ) = self.sse.customer_fields();
+
+        let output = match self
+            .throttle
+            .run(|| self.client.head_object(head_request.clone()))
+            .await
+            .map_err(StoreError::from)
+        {
+            Ok(output) => output,
+            Err(StoreError::NotFound(_)) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        Ok(Some(ObjectHead {
+            info: ObjectInfo {
+                key: key.to_owned(),
+                size: output.content_length.unwrap_or_default() as u64,
+                modified: output.last_modified.as_deref().and_then(parse_http_date),
+                e_tag: output.e_tag,
+                storage_class: output.storage_class,
+                ..Default::default()
+            },
+            metadata: output.metadata.unwrap_or_default(),
+            content_encoding: output.content_encoding,
+            restore: output.restore.as_deref().and_then(parse_restore),
+            server_side_encryption: output.server_side_encryption,
+            ssekms_key_id: output.ssekms_key_id,
+            customer_encrypted: output.sse_customer_algorithm.is_some(),
+            ..Default::default()
+        }))
+    }
+
+    async fn get(&self, key: &str, range: Option<Range<u64>>) -> Result<ObjectBody, StoreError> {
+        self.get_object(key, None, range).await
+    }
+
+    async fn get_version(
+        &self,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> Result<ObjectBody, StoreError> {
+        self.get_object(key, version_id, None).await
+    }
+
+    async fn put(
+        &self,
+        key: &str,
+        body: Bytes,
+        options: &PutOptions,
+    ) -> Result<Option<String>, StoreError> {
+        let output = self
+            .throttle
+            .run(|| {
+                let mut put_request = PutObjectRequest {
+                    bucket: self.bucket.clone(),
+                    key: key.to_owned(),
+                    body: Some(body.to_vec().into()),
+                    metadata: options.metadata.clone(),
+                    content_encoding: options.content_encoding.clone(),
+                    tagging: encode_tags(&options.tags),
+                    ..Default::default()
+                };
+                self.sse.apply_to_put(&mut put_request);
+                self.client.put_object(put_request)
+            })
+            .await?;
+        Ok(output.version_id)
+    }
+
+    async fn create_multipart(
+        &self,
+        key: &str,
+        options: &PutOptions,
+    ) -> Result<String, StoreError> {
+        let mut create_multipart_request = CreateMultipartUploadRequest {
+            bucket: self.bucket.clone(),
+            key: key.to_owned(),
+            metadata: options.metadata.clone(),
+            content_encoding: options.content_encoding.clone(),
+            tagging: encode_tags(&options.tags),
+            ..Default::default()
+        };
+        self.sse
+            .apply_to_create_multipart(&mut create_multipart_request);
+
+        let output = self
+            .throttle
+            .run(|| {
+                self.client
+                    .create_multipart_upload(create_multipart_request.clone())
+            })
+            .await?;
+        output
+            .upload_id
+            .ok_or_else(|| StoreError::Request("Missing upload id".to_owned()))
+    }
+
+    async fn upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: usize,
+        body: Bytes,
+    ) -> Result<UploadedPart, StoreError> {
+        let output = self
+            .throttle
+            .run(|| {
+                let mut upload_part_request = UploadPartRequest {
+                    bucket: self.bucket.clone(),
+                    key: key.to_owned(),
+                    part_number: part_number as i64,
+                    upload_id: upload_id.to_owned(),
+                    body: Some(body.to_vec().into()),
+                    ..Default::default()
+                };
+                self.sse.apply_to_upload_part(&mut upload_part_request);
+                self.client.upload_part(upload_part_request)
+            })
+            .await?;
+
+        Ok(UploadedPart {
+            part_number,
+            e_tag: output.e_tag,
+        })
+    }
+
+    async fn complete_multipart(
+        &self,
+        key: &str,
+        upload_id: &str,
+        mut parts: Vec<UploadedPart>,
+    ) -> Result<Option<String>, StoreError> {
+        parts.sort_by_key(|part| part.part_number);
+        let complete_multipart_request = CompleteMultipartUploadRequest {
+            bucket: self.bucket.clone(),
+            key: key.to_owned(),
+            upload_id: upload_id.to_owned(),
+            multipart_upload: Some(CompletedMultipartUpload {
+                parts: Some(
+                    parts
+                        .into_iter()
+                        .map(|part| CompletedPart {
+                            e_tag: part.e_tag,
+                            part_number: Some(part.part_number as i64),
+                        })
+                        .collect(),
+                ),
+            }),
+            ..Default::default()
+        };
+
+        let output = self
+            .throttle
+            .run(|| {
+                self.client
+                    .complete_multipart_upload(complete_multipart_request.clone())
+            })
+            .await?;
+        Ok(output.version_id)
+    }
+
+    async fn abort_multipart(&self, key: &str, upload_id: &str) -> Result<(), StoreError> {
+        let abort_request = AbortMultipartUploadRequest {
+            bucket: self.bucket.clone(),
+            key: key.to_owned(),
+            upload_id: upload_id.to_owned(),
+            ..Default::default()
+        };
+        self.throttle
+            .run(|| self.client.abort_multipart_upload(abort_request.clone()))
+            .await?;
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<Option<String>, StoreError> {
+        self.delete_object(key, None).await
+    }
+
+    fn list_versions(&self, prefix: &str) -> BoxStream<'static, Result<ObjectVersion, StoreError>> {
+        let store = self.clone();
+        let prefix = prefix.to_owned();
+
+        stream::unfold(Some((None, None)), move |markers| {
+            let store = store.clone();
+            let prefix = prefix.clone();
+            async move {
+                let (key_marker, version_id_marker) = markers?;
+                let list_versions_request = ListObjectVersionsRequest {
+                    bucket: store.bucket.clone(),
+                    prefix: (!prefix.is_empty()).then(|| prefix.clone()),
+                    key_marker,
+                    version_id_marker,
+                    ..Default::default()
+                };
+
+                let output = match store
+                    .throttle
+                    .run(|| {
+                        store
+                            .client
+                            .list_object_versions(list_versions_request.clone())
+                    })
+                    .await
+                {
+                    Ok(output) => output,
+                    Err(e) => return Some((vec![Err(e.into())], None)),
+                };
+
+                let versions = output
+                    .versions
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|version| ObjectVersion {
+                        info: ObjectInfo {
+                            key: version.key.unwrap_or_default(),
+                            size: version.size.unwrap_or_default() as u64,
+                            modified: version.last_modified.as_deref().and_then(parse_timestamp),
+                            e_tag: version.e_tag,
+                            storage_class: version.storage_class,
+                            owner: version.owner.and_then(owner_name),
+                        },
+                        version_id: version.version_id,
+                        is_latest: version.is_latest.unwrap_or_default(),
+                        delete_marker: false,
+                    });
+                let delete_markers =
+                    output
+                        .delete_markers
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|marker| ObjectVersion {
+                            info: ObjectInfo {
+                                key: marker.key.unwrap_or_default(),
+                                modified: marker.last_modified.as_deref().and_then(parse_timestamp),
+                                owner: marker.owner.and_then(owner_name),
+                                ..Default::default()
+                            },
+                            version_id: marker.version_id,
+                            is_latest: marker.is_latest.unwrap_or_default(),
+                            delete_marker: true,
+                        });
+                // Versions and delete markers come apart, each in key order
+                let mut versions = versions.chain(delete_markers).collect::<Vec<_>>();
+                versions.sort_by(|a, b| a.info.key.cmp(&b.info.key));
+                let mut versions = versions.into_iter().map(Ok).collect::<Vec<_>>();
+                let next = match output.is_truncated.unwrap_or_default() {
+                    false => None,
+                    // Without a key marker, the listing would start over forever
+                    true if output.next_key_marker.is_none() => {
+                        versions.push(Err(StoreError::Request(
+                            "Truncated listing without a key marker".to_owned(),
+                        )));
+                        None
+                    }
+                    true => Some((output.next_key_marker, output.next_version_id_marker)),
+                };
+                Some((versions, next))
+            }
+        })
+        .flat_map(stream::iter)
+        .boxed()
+    }
+
+    async fn delete_version(&self, key: &str, version_id: Option<&str>) -> Result<(), StoreError> {
+        self.delete_object(key, version_id).await.map(|_| ())
+    }
+
+    async fn copy(
+        &self,
+        source_key: &str,
+        version_id: Option<&str>,
+        key: &str,
+    ) -> Result<(), StoreError> {
+        let mut copy_source = encode_copy_source(&self.bucket, source_key);
+        if let Some(version_id) = version_id {
+            copy_source = format!(
+                "{}?versionId={}",
+                copy_source,
+                percent_encode(version_id, b"")
+            );
+        }
+        let mut copy_request = CopyObjectRequest {
+            bucket: self.bucket.clone(),
+            key: key.to_owned(),
+            copy_source,
+            ..Default::default()
+        };
+        self.sse.apply_to_copy(&mut copy_request);
+        self.throttle
+            .run(|| self.client.copy_object(copy_request.clone()))
+            .await?;
+        Ok(())
+    }
+
+    async fn get_tags(&self, key: &str, version_id: Option<&str>) -> Result<Tags, StoreError> {
+        let tagging_request = GetObjectTaggingRequest {
+            bucket: self.bucket.clone(),
+            key: key.to_owned(),
+            version_id: version_id.map(str::to_owned),
+            ..Default::default()
+        };
+        let output = self
+            .throttle
+            .run(|| self.client.get_object_tagging(tagging_request.clone()))
+            .await?;
+        Ok(output
+            .tag_set
+            .into_iter()
+            .map(|tag| (tag.key, tag.value))
+            .collect())
+    }
+
+    async fn set_tags(&self, key: &str, tags: &Tags) -> Result<(), StoreError> {
+        let tagging_request = PutObjectTaggingRequest {
+            bucket: self.bucket.clone(),
+            key: key.to_owned(),
+            tagging: Tagging {
+                tag_set: tags
+                    .iter()
+                    .map(|(key, value)| Tag {
+                        key: key.clone(),
+                        value: value.clone(),
+                    })
+                    .collect(),
+            },
+            ..Default::default()
+        };
+        self.throttle
+            .run(|| self.client.put_object_tagging(tagging_request.clone()))
+            .await?;
+        Ok(())
+    }
+
+    async fn set_storage_class(
+        &self,
+        key: &str,
+        storage_class: &str,
+        head: &ObjectHead,
+    ) -> Result<(), StoreError> {
+        let mut copy_request = CopyObjectRequest {
+            bucket: self.bucket.clone(),
+            key: key.to_owned(),
+            copy_source: encode_copy_source(&self.bucket, key),
+            storage_class: Some(storage_class.to_owned()),
+            metadata_directive: Some("COPY".to_owned()),
+            ..Default::default()
+        };
+        self.sse.apply_to_copy(&mut copy_request);
+        // Without settings of its own, the copy would get the bucket default
+        if self.sse.sse.is_none() && self.sse.customer_key.is_none() {
+            copy_request.server_side_encryption = head.server_side_encryption.clone();
+            copy_request.ssekms_key_id = head.ssekms_key_id.clone();
+        }
+        self.throttle
+            .run(|| self.client.copy_object(copy_request.clone()))
+            .await?;
+        Ok(())
+    }
+
+    async fn restore(&self, key: &str, days: u32, tier: RestoreTier) -> Result<(), StoreError> {
+        let restore_request = RestoreObjectRequest {
+            bucket: self.bucket.clone(),
+            key: key.to_owned(),
+            restore_request: Some(RestoreRequest {
+                days: Some(days as i64),
+                glacier_job_parameters: Some(GlacierJobParameters {
+                    tier: tier.as_str().to_owned(),
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        match self
+            .throttle
+            .run(|| self.client.restore_object(restore_request.clone()))
+            .await
+        {
+            Ok(_) => Ok(()),
+            // Asked for meanwhile, which is as good
+            Err(RusotoError::Unknown(response)) if response.status.as_u16() == 409 => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn delete_bucket(&self) -> Result<(), StoreError> {
+        let delete_bucket_request = DeleteBucketRequest {
+            bucket: self.bucket.clone(),
+            ..Default::default()
+        };
+        self.throttle
+            .run(|| self.client.delete_bucket(delete_bucket_request.clone()))
+            .await?;
+        Ok(())
+    }
+
+    async fn put_reader(
+        &self,
+        key: &str,
+        reader: ByteReader,
+        options: &PutOptions,
+    ) -> Result<WrittenObject, StoreError> {
+        upload_in_parts(self, key, reader, options, &self.events).await
+    }
+
+    fn accepts_key(&self, key: &str) -> bool {
+        !key.is_empty() && key.len() <= MAX_KEY_LENGTH
+    }
+}
+
+fn owner_name(owner: rusoto_s3::Owner) -> Option<String> {
+    owner.display_name.or(owner.id)
+}
+
+/// Timestamps of object headers, e.g. `Wed, 21 Oct 2015 07:28:00 GMT`.
+fn parse_http_date(value: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+    chrono::DateTime::parse_from_rfc2822(value)
+        .ok()
+        .map(|timestamp| timestamp.with_timezone(&chrono::Utc))
+}
+
+/// `x-amz-restore` header, e.g. `ongoing-request="false", expiry-date="Fri,
+/// 21 Dec 2012 00:00:00 GMT"`.
+fn parse_restore(value: &str) -> Option<RestoreStatus> {
+    let field = |name: &str| {
+        let start = value.find(&format!("{}=\"", name))? + name.len() + 2;
+        let end = start + value[start..].find('"')?;
+        Some(&value[start..end])
+    };
+    match field("ongoing-request")? {
+        "true" => Some(RestoreStatus::InProgress),
+        _ => Some(RestoreStatus::Restored {
+            expiry: field("expiry-date").and_then(parse_http_date),
+        }),
+    }
+}
+
+/// `x-amz-copy-source` header, URL encoded except for the separators.
+fn encode_copy_source(bucket: &str, key: &str) -> String {
+    percent_encode(&format!("{}/{}", bucket, key), b"/")
+}
+
+/// `x-amz-tagging` header, tags as URL query parameters.
+fn encode_tags(tags: &Tags) -> Option<String> {
+    (!tags.is_empty()).then(|| {
+        tags.iter()
+            .map(|(key, value)| {
+                format!(
+                    "{}={}",
+                    percent_encode(key, b""),
+                    percent_encode(value, b"")
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("&")
+    })
+}
+
+/// Encodes every byte but unreserved characters and those of `kept`.
+fn percent_encode(value: &str, kept: &[u8]) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ if kept.contains(&byte) => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect::<String>()
+}
@@ -0,0 +1,117 @@
+use clap::Parser;
+use std::path::PathBuf;
+
+use s3_utils::client::Provider;
+use s3_utils::config::static_credentials;
+use s3_utils::tags::tags_from_args;
+use s3_utils::{
+    tag_objects, Config, RemoteConfig, RemotePath, TagChanges, TransferOptions, TransferSummary,
+};
+
+use crate::console::{print_events, print_summary};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+pub struct Args {
+    pub tag_command: String,
+
+    /// Objects to tag, `[remote:]bucket[/prefix]`
+    pub location: String,
+
+    /// Add the tag `key=value`, or change its value, can be repeated
+    #[arg(long)]
+    pub tag: Vec<String>,
+
+    /// Remove the tag with this key, can be repeated
+    #[arg(long)]
+    pub remove_tag: Vec<String>,
+
+    /// Remove every other tag
+    #[arg(long)]
+    pub replace: bool,
+
+    /// Only tag the objects tagged `key=value`, can be repeated
+    #[arg(long)]
+    pub where_tag: Vec<String>,
+
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    #[arg(long)]
+    pub region: Option<String>,
+
+    #[arg(long)]
+    pub aws_access_key_id: Option<String>,
+
+    #[arg(long)]
+    pub aws_secret_access_key: Option<String>,
+
+    #[arg(long)]
+    pub endpoint: Option<String>,
+
+    #[arg(long, value_enum)]
+    pub provider: Option<Provider>,
+
+    #[arg(long)]
+    pub account_id: Option<String>,
+
+    #[arg(long)]
+    pub force_path_style: bool,
+
+    #[arg(long)]
+    pub use_http: bool,
+
+    #[arg(long)]
+    pub insecure: bool,
+
+    #[arg(long)]
+    pub ca_bundle: Option<PathBuf>,
+
+    #[arg(long)]
+    pub concurrency: Option<usize>,
+
+    #[arg(long)]
+    pub max_requests_per_second: Option<f64>,
+}
+
+/// `tag`: adds, changes or removes tags of the objects under a prefix.
+pub async fn tag() -> Result<TransferSummary, Box<dyn std::error::Error>> {
+    // Read command-line arguments
+    let args = Args::parse();
+    let config = Config::load(args.config.as_deref())?;
+    let location: RemotePath = args.location.parse()?;
+    let remote = RemoteConfig {
+        provider: args.provider,
+        endpoint: args.endpoint,
+        region: args.region,
+        account_id: args.account_id,
+        credentials: static_credentials(args.aws_access_key_id, args.aws_secret_access_key),
+        force_path_style: args.force_path_style.then_some(true),
+        use_http: args.use_http.then_some(true),
+        insecure: args.insecure.then_some(true),
+        ca_bundle: args.ca_bundle,
+        ..Default::default()
+    }
+    .or(config.remote_for(&location)?);
+
+    let changes = TagChanges {
+        set: tags_from_args(&args.tag, "--tag")?,
+        remove: args.remove_tag.into_iter().collect(),
+        replace: args.replace,
+    };
+    if changes.is_empty() {
+        return Err("Nothing to change, give --tag, --remove-tag or --replace".into());
+    }
+    let options = TransferOptions {
+        concurrency: args.concurrency,
+        max_requests_per_second: args.max_requests_per_second,
+        where_tags: tags_from_args(&args.where_tag, "--where-tag")?,
+        events: print_events(),
+        ..Default::default()
+    };
+
+    let summary = tag_objects(&location, &remote, &changes, &options).await?;
+    print_summary(&summary);
+
+    Ok(summary)
+}
@@ -0,0 +1,149 @@
+use futures_util::StreamExt;
+use std::collections::BTreeSet;
+use tokio::sync::mpsc;
+
+use crate::config::{RemoteConfig, RemotePath};
+use crate::listing::spawn_listing;
+use crate::store::{ObjectStore, S3Store, StoreError, StorePath};
+use crate::transfer::{Progress, TransferEvent, TransferOptions, TransferSummary};
+
+pub use crate::store::Tags;
+
+/// Parses repeated `key=value` arguments of `flag`.
+pub fn tags_from_args(values: &[String], flag: &str) -> Result<Tags, Box<dyn std::error::Error>> {
+    values
+        .iter()
+        .map(|value| match value.split_once('=') {
+            Some((key, value)) if !key.is_empty() => Ok((key.to_owned(), value.to_owned())),
+            _ => Err(format!("Invalid {} {}: expected key=value", flag, value).into()),
+        })
+        .collect()
+}
+
+/// Changes to the tags of objects.
+#[derive(Debug, Clone, Default)]
+pub struct TagChanges {
+    /// Tags added, or whose value is changed
+    pub set: Tags,
+    /// Keys of the tags removed
+    pub remove: BTreeSet<String>,
+    /// Drop the other tags, leaving only those of `set`
+    pub replace: bool,
+}
+
+impl TagChanges {
+    pub fn is_empty(&self) -> bool {
+        self.set.is_empty() && self.remove.is_empty() && !self.replace
+    }
+
+    fn apply(&self, tags: &Tags) -> Tags {
+        let mut changed = match self.replace {
+            true => Tags::new(),
+            false => tags.clone(),
+        };
+        changed.retain(|key, _| !self.remove.contains(key));
+        changed.extend(self.set.clone());
+        changed
+    }
+}
+
+/// Whether `tags` has every tag of `wanted`, with the same value.
+pub(crate) fn has_tags(tags: &Tags, wanted: &Tags) -> bool {
+    wanted
+        .iter()
+        .all(|(key, value)| tags.get(key) == Some(value))
+}
+
+/// Whether an object, or a version of it, has every tag of `wanted`.
+pub(crate) async fn is_tagged(
+    store: &dyn ObjectStore,
+    key: &str,
+    version_id: Option<&str>,
+    wanted: &Tags,
+) -> Result<bool, StoreError> {
+    if wanted.is_empty() {
+        return Ok(true);
+    }
+    Ok(has_tags(&store.get_tags(key, version_id).await?, wanted))
+}
+
+/// Changes the tags of the objects under `location`.
+pub async fn tag_objects(
+    location: &RemotePath,
+    remote: &RemoteConfig,
+    changes: &TagChanges,
+    options: &TransferOptions,
+) -> Result<TransferSummary, Box<dyn std::error::Error>> {
+    let concurrency = options.concurrency(remote.concurrency, 10);
+    let throttle = options.throttle(concurrency, remote.max_requests_per_second);
+    let store = S3Store::connect(remote, &location.bucket, throttle)?;
+
+    tag_from(
+        &StorePath::new(store, location.key_prefix()),
+        changes,
+        &TransferOptions {
+            concurrency: Some(concurrency),
+            ..options.clone()
+        },
+    )
+    .await
+}
+
+/// Changes the tags of the objects under `location` that have the
+/// `where_tags` of `options`, leaving alone those whose tags would not
+/// change.
+pub async fn tag_from(
+    location: &StorePath,
+    changes: &TagChanges,
+    options: &TransferOptions,
+) -> Result<TransferSummary, Box<dyn std::error::Error>> {
+    if changes.is_empty() {
+        return Err("No tags to change".into());
+    }
+    if options.manifest.is_some() {
+        return Err("Tags are changed for a prefix, not a manifest".into());
+    }
+    let concurrency = options.concurrency.unwrap_or(10);
+    let progress = Progress::new(options.events.clone());
+    // Replacing every tag does not need the current ones, unless filtering
+    let read_tags = !changes.replace || !options.where_tags.is_empty();
+
+    let (tx, rx) = mpsc::channel(concurrency);
+    spawn_listing(
+        location.store.clone(),
+        location.prefix.clone(),
+        progress.clone(),
+        tx,
+    );
+    tokio_stream::wrappers::ReceiverStream::new(rx)
+        .for_each_concurrent(concurrency, |info| {
+            let progress = progress.clone();
+            async move {
+                let key = info.key;
+                let tags = match read_tags {
+                    true => match location.store.get_tags(&key, None).await {
+                        Ok(tags) => Some(tags),
+                        Err(e) => return progress.fail(&key, e),
+                    },
+                    false => None,
+                };
+                if let Some(tags) = &tags {
+                    if !has_tags(tags, &options.where_tags) {
+                        return;
+                    }
+                }
+
+                let changed = changes.apply(tags.as_ref().unwrap_or(&Tags::new()));
+                if tags.as_ref() == Some(&changed) {
+                    return progress.emit(TransferEvent::UpToDate { key });
+                }
+                match location.store.set_tags(&key, &changed).await {
+                    Ok(_) => progress.emit(TransferEvent::Tagged { key }),
+                    Err(e) => progress.fail(&key, e),
+                }
+            }
+        })
+        .await;
+
+    Ok(progress.summary())
+}
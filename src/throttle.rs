@@ -0,0 +1,208 @@
+use rusoto_core::RusotoError;
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+use crate::transfer::{EventHandler, TransferEvent};
+
+const MAX_RETRIES: u32 = 8;
+const BASE_BACKOFF: Duration = Duration::from_millis(200);
+const MAX_BACKOFF: Duration = Duration::from_secs(20);
+// Throttling responses tend to arrive in bursts, only back off once per window
+const DECREASE_COOLDOWN: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+struct State {
+    limit: usize,
+    max_limit: usize,
+    in_flight: usize,
+    successes: usize,
+    last_decrease: Option<Instant>,
+    next_slot: Instant,
+}
+
+/// Shared request gate for all S3 calls of a command.
+///
+/// Caps the number of requests per second and adapts the number of requests
+/// in flight (AIMD): the limit is halved when the provider answers with
+/// `SlowDown`/503 and grows by one after a full window of successful requests.
+#[derive(Debug, Clone)]
+pub struct Throttle {
+    state: Arc<Mutex<State>>,
+    notify: Arc<Notify>,
+    interval: Option<Duration>,
+    events: EventHandler,
+}
+
+pub struct Permit {
+    throttle: Throttle,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.throttle.state.lock().unwrap().in_flight -= 1;
+        self.throttle.notify.notify_waiters();
+    }
+}
+
+impl Throttle {
+    pub fn new(concurrency: usize, max_requests_per_second: Option<f64>) -> Self {
+        let concurrency = concurrency.max(1);
+        let interval = max_requests_per_second
+            .filter(|rps| *rps > 0.0)
+            .map(|rps| Duration::from_secs_f64(1.0 / rps));
+
+        Throttle {
+            state: Arc::new(Mutex::new(State {
+                limit: concurrency,
+                max_limit: concurrency,
+                in_flight: 0,
+                successes: 0,
+                last_decrease: None,
+                next_slot: Instant::now(),
+            })),
+            notify: Arc::new(Notify::new()),
+            interval,
+            events: EventHandler::default(),
+        }
+    }
+
+    /// Requests currently allowed in flight.
+    pub fn concurrency(&self) -> usize {
+        self.state.lock().unwrap().limit
+    }
+
+    /// Reports the concurrency reductions to `events`.
+    pub fn with_events(mut self, events: EventHandler) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Waits for a free slot under the current concurrency limit and for the
+    /// next request slot allowed by the rate cap.
+    pub async fn acquire(&self) -> Permit {
+        loop {
+            let notified = self.notify.notified();
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.in_flight < state.limit {
+                    state.in_flight += 1;
+                    break;
+                }
+            }
+            notified.await;
+        }
+
+        let permit = Permit {
+            throttle: self.clone(),
+        };
+
+        if let Some(interval) = self.interval {
+            let slot = {
+                let mut state = self.state.lock().unwrap();
+                let slot = state.next_slot.max(Instant::now());
+                state.next_slot = slot + interval;
+                slot
+            };
+            tokio::time::sleep_until(slot).await;
+        }
+
+        permit
+    }
+
+    fn on_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.successes += 1;
+        if state.successes >= state.limit && state.limit < state.max_limit {
+            state.limit += 1;
+            state.successes = 0;
+            drop(state);
+            self.notify.notify_waiters();
+        }
+    }
+
+    fn on_throttle(&self) {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let cooling_down = state
+            .last_decrease
+            .map(|last| now.duration_since(last) < DECREASE_COOLDOWN)
+            .unwrap_or(false);
+
+        if !cooling_down {
+            state.limit = (state.limit / 2).max(1);
+            state.successes = 0;
+            state.last_decrease = Some(now);
+            let concurrency = state.limit;
+            drop(state);
+            self.events.emit(TransferEvent::Throttled { concurrency });
+        }
+    }
+
+    /// Runs an S3 request under the throttle, retrying it with exponential
+    /// backoff while the provider keeps answering with throttling errors.
+    pub async fn run<F, Fut, T, E>(&self, mut request: F) -> Result<T, RusotoError<E>>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, RusotoError<E>>>,
+        E: fmt::Display,
+    {
+        let mut attempt = 0;
+        loop {
+            let permit = self.acquire().await;
+            let result = request().await;
+            drop(permit);
+
+            match result {
+                Ok(output) => {
+                    self.on_success();
+                    return Ok(output);
+                }
+                Err(e) if is_throttling_error(&e) => {
+                    self.on_throttle();
+                    if attempt >= MAX_RETRIES {
+                        return Err(e);
+                    }
+                    attempt += 1;
+                    tokio::time::sleep(backoff(attempt)).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+fn backoff(attempt: u32) -> Duration {
+    BASE_BACKOFF
+        .saturating_mul(2u32.saturating_pow(attempt - 1))
+        .min(MAX_BACKOFF)
+}
+
+/// Whether the provider asked to slow down, be it with a bare 503 or 429, or
+/// with an error code rusoto parsed into a service error or left unknown.
+pub fn is_throttling_error<E: fmt::Display>(error: &RusotoError<E>) -> bool {
+    match error {
+        RusotoError::Unknown(response) => {
+            let status = response.status.as_u16();
+            status == 503
+                || status == 429
+                || is_throttling_code(&String::from_utf8_lossy(&response.body))
+        }
+        RusotoError::Service(e) => is_throttling_code(&e.to_string()),
+        _ => false,
+    }
+}
+
+fn is_throttling_code(message: &str) -> bool {
+    [
+        "SlowDown",
+        "Throttling",
+        "RequestLimitExceeded",
+        "TooManyRequests",
+    ]
+    .iter()
+    .any(|code| message.contains(code))
+}
@@ -0,0 +1,264 @@
+use chrono::{DateTime, Utc};
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::compress::CompressOptions;
+use crate::cse::ClientEncryption;
+use crate::filter::TimeFilter;
+use crate::keys::KeyRules;
+use crate::manifest::Manifest;
+use crate::sse::SseOptions;
+use crate::store::{LocalKeys, Tags, WalkOptions};
+use crate::throttle::Throttle;
+
+/// Settings of a transfer, on top of the `RemoteConfig` of each side.
+///
+/// `source_sse` and `sse` apply to the S3 stores created by
+/// `upload_directory`, `download_directory` and `copy_objects`; stores given
+/// to `transfer` carry their own.
+#[derive(Clone, Default)]
+pub struct TransferOptions {
+    /// Objects transferred at once, defaults to the remote's setting
+    pub concurrency: Option<usize>,
+    pub max_requests_per_second: Option<f64>,
+    /// Skip objects whose destination is up to date
+    pub sync: bool,
+    /// With `sync`, delete the destination objects whose source is gone
+    pub delete: bool,
+    /// Upload files under their name only, without their directories
+    pub flatten: bool,
+    /// Changes to the keys, after flattening them
+    pub keys: KeyRules,
+    /// Keys of the files of local directories
+    pub local_keys: LocalKeys,
+    /// Files of local directories to list
+    pub walk: WalkOptions,
+    /// SSE-C key of the source objects
+    pub source_sse: SseOptions,
+    /// Server-side encryption of the destination objects
+    pub sse: SseOptions,
+    pub encryption: Option<ClientEncryption>,
+    pub compress: Option<CompressOptions>,
+    /// Act on these objects instead of listing the source
+    pub manifest: Option<Manifest>,
+    /// Tags of the written objects, on top of those copied
+    pub tags: Tags,
+    /// Only act on the objects with every one of these tags
+    pub where_tags: Tags,
+    /// Only act on the objects last modified within this window
+    pub modified: TimeFilter,
+    pub events: EventHandler,
+}
+
+impl TransferOptions {
+    /// Concurrency given here, or else by the remote, or else `default`.
+    pub fn concurrency(&self, remote: Option<usize>, default: usize) -> usize {
+        self.concurrency.or(remote).unwrap_or(default)
+    }
+
+    /// Throttle shared by the S3 requests of a transfer.
+    pub fn throttle(
+        &self,
+        concurrency: usize,
+        remote_max_requests_per_second: Option<f64>,
+    ) -> Throttle {
+        let max_requests_per_second = self
+            .max_requests_per_second
+            .or(remote_max_requests_per_second);
+        Throttle::new(concurrency, max_requests_per_second).with_events(self.events.clone())
+    }
+}
+
+/// What happened to the objects of a transfer.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransferSummary {
+    /// Objects uploaded, downloaded, copied, deleted, tagged, changed or
+    /// restored
+    pub transferred: u64,
+    /// Objects already up to date or that cannot be transferred
+    pub skipped: u64,
+    pub failed: u64,
+    /// Bytes read from the source
+    pub bytes: u64,
+    /// Size of the compressed uploads before compression
+    pub original_bytes: u64,
+    /// Size of the compressed uploads once compressed
+    pub compressed_bytes: u64,
+}
+
+impl TransferSummary {
+    /// Adds the totals of another transfer, e.g. of a later pass.
+    pub(crate) fn add(&mut self, other: &TransferSummary) {
+        self.transferred += other.transferred;
+        self.skipped += other.skipped;
+        self.failed += other.failed;
+        self.bytes += other.bytes;
+        self.original_bytes += other.original_bytes;
+        self.compressed_bytes += other.compressed_bytes;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum TransferEvent {
+    Uploaded {
+        key: String,
+        bytes: u64,
+        /// Size of the file, when it was compressed on the way
+        original_bytes: Option<u64>,
+    },
+    Downloaded {
+        key: String,
+        path: PathBuf,
+        bytes: u64,
+    },
+    Copied {
+        key: String,
+        bytes: u64,
+    },
+    Deleted {
+        key: String,
+    },
+    PartUploaded {
+        key: String,
+        part_number: usize,
+    },
+    Tagged {
+        key: String,
+    },
+    StorageClassChanged {
+        key: String,
+        storage_class: String,
+    },
+    RestoreRequested {
+        key: String,
+    },
+    /// Asked for earlier and not done yet
+    RestoreInProgress {
+        key: String,
+    },
+    /// Readable until `expiry`
+    Restored {
+        key: String,
+        expiry: Option<DateTime<Utc>>,
+    },
+    /// Left alone by a sync
+    UpToDate {
+        key: String,
+    },
+    Skipped {
+        key: String,
+        reason: String,
+    },
+    Failed {
+        key: String,
+        error: String,
+    },
+    /// Part of the source could not be listed
+    ListingFailed {
+        prefix: String,
+        error: String,
+    },
+    /// The provider asked to slow down and concurrency was reduced
+    Throttled {
+        concurrency: usize,
+    },
+}
+
+type Callback = dyn Fn(&TransferEvent) + Send + Sync;
+
+/// Callback receiving the progress of a transfer, possibly from several
+/// tasks at once.
+#[derive(Clone, Default)]
+pub struct EventHandler {
+    callback: Option<Arc<Callback>>,
+}
+
+impl EventHandler {
+    pub fn new<F>(callback: F) -> Self
+    where
+        F: Fn(&TransferEvent) + Send + Sync + 'static,
+    {
+        EventHandler {
+            callback: Some(Arc::new(callback)),
+        }
+    }
+
+    pub(crate) fn emit(&self, event: TransferEvent) {
+        if let Some(callback) = &self.callback {
+            callback(&event);
+        }
+    }
+}
+
+impl fmt::Debug for EventHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventHandler")
+            .field("callback", &self.callback.is_some())
+            .finish()
+    }
+}
+
+/// Forwards the events of a transfer and tallies them into its summary.
+#[derive(Debug, Clone)]
+pub(crate) struct Progress {
+    events: EventHandler,
+    summary: Arc<Mutex<TransferSummary>>,
+}
+
+impl Progress {
+    pub fn new(events: EventHandler) -> Self {
+        Progress {
+            events,
+            summary: Default::default(),
+        }
+    }
+
+    pub fn emit(&self, event: TransferEvent) {
+        {
+            let mut summary = self.summary.lock().unwrap();
+            match &event {
+                TransferEvent::Uploaded {
+                    bytes,
+                    original_bytes: Some(original_bytes),
+                    ..
+                } => {
+                    summary.transferred += 1;
+                    summary.bytes += original_bytes;
+                    summary.original_bytes += original_bytes;
+                    summary.compressed_bytes += bytes;
+                }
+                TransferEvent::Uploaded { bytes, .. }
+                | TransferEvent::Downloaded { bytes, .. }
+                | TransferEvent::Copied { bytes, .. } => {
+                    summary.transferred += 1;
+                    summary.bytes += bytes;
+                }
+                TransferEvent::Deleted { .. }
+                | TransferEvent::Tagged { .. }
+                | TransferEvent::StorageClassChanged { .. }
+                | TransferEvent::RestoreRequested { .. } => summary.transferred += 1,
+                TransferEvent::UpToDate { .. }
+                | TransferEvent::Skipped { .. }
+                | TransferEvent::RestoreInProgress { .. }
+                | TransferEvent::Restored { .. } => summary.skipped += 1,
+                TransferEvent::Failed { .. } | TransferEvent::ListingFailed { .. } => {
+                    summary.failed += 1
+                }
+                TransferEvent::PartUploaded { .. } | TransferEvent::Throttled { .. } => {}
+            }
+        }
+        self.events.emit(event);
+    }
+
+    pub fn fail(&self, key: &str, error: impl fmt::Display) {
+        self.emit(TransferEvent::Failed {
+            key: key.to_owned(),
+            error: error.to_string(),
+        });
+    }
+
+    pub fn summary(&self) -> TransferSummary {
+        self.summary.lock().unwrap().clone()
+    }
+}
@@ -0,0 +1,113 @@
+use clap::Parser;
+use std::path::PathBuf;
+
+use s3_utils::client::Provider;
+use s3_utils::config::static_credentials;
+use s3_utils::sse::{ServerSideEncryption, SseOptions};
+use s3_utils::{
+    transition_objects, Config, RemoteConfig, RemotePath, TransferOptions, TransferSummary,
+};
+
+use crate::console::{print_events, print_summary};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+pub struct Args {
+    pub transition: String,
+
+    /// Objects to change, `[remote:]bucket[/prefix]`
+    pub location: String,
+
+    /// New storage class, e.g. `STANDARD_IA`, `GLACIER_IR` or `DEEP_ARCHIVE`
+    #[arg(long)]
+    pub storage_class: String,
+
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    #[arg(long)]
+    pub region: Option<String>,
+
+    #[arg(long)]
+    pub aws_access_key_id: Option<String>,
+
+    #[arg(long)]
+    pub aws_secret_access_key: Option<String>,
+
+    #[arg(long)]
+    pub endpoint: Option<String>,
+
+    #[arg(long, value_enum)]
+    pub provider: Option<Provider>,
+
+    #[arg(long)]
+    pub account_id: Option<String>,
+
+    #[arg(long)]
+    pub force_path_style: bool,
+
+    #[arg(long)]
+    pub use_http: bool,
+
+    #[arg(long)]
+    pub insecure: bool,
+
+    #[arg(long)]
+    pub ca_bundle: Option<PathBuf>,
+
+    #[arg(long)]
+    pub concurrency: Option<usize>,
+
+    #[arg(long)]
+    pub max_requests_per_second: Option<f64>,
+
+    /// Encryption of the rewritten objects, the bucket default otherwise
+    #[arg(long, value_enum)]
+    pub sse: Option<ServerSideEncryption>,
+
+    #[arg(long)]
+    pub sse_kms_key_id: Option<String>,
+
+    /// Base64 encoded 256-bit SSE-C key of the objects
+    #[arg(long)]
+    pub sse_customer_key: Option<String>,
+}
+
+/// `transition`: changes the storage class of the objects under a prefix
+/// by copying them onto themselves.
+pub async fn transition() -> Result<TransferSummary, Box<dyn std::error::Error>> {
+    // Read command-line arguments
+    let args = Args::parse();
+    let config = Config::load(args.config.as_deref())?;
+    let location: RemotePath = args.location.parse()?;
+    let remote = RemoteConfig {
+        provider: args.provider,
+        endpoint: args.endpoint,
+        region: args.region,
+        account_id: args.account_id,
+        credentials: static_credentials(args.aws_access_key_id, args.aws_secret_access_key),
+        force_path_style: args.force_path_style.then_some(true),
+        use_http: args.use_http.then_some(true),
+        insecure: args.insecure.then_some(true),
+        ca_bundle: args.ca_bundle,
+        ..Default::default()
+    }
+    .or(config.remote_for(&location)?);
+
+    let options = TransferOptions {
+        concurrency: args.concurrency,
+        max_requests_per_second: args.max_requests_per_second,
+        sse: SseOptions::new(
+            args.sse,
+            args.sse_kms_key_id,
+            args.sse_customer_key.as_deref(),
+        )?,
+        events: print_events(),
+        ..Default::default()
+    };
+
+    let summary = transition_objects(&location, &remote, &args.storage_class, &options).await?;
+    print_summary(&summary);
+
+    Ok(summary)
+}
@@ -0,0 +1,35 @@
+use std::path::Path;
+
+use crate::config::{RemoteConfig, RemotePath};
+use crate::copy::transfer_between;
+use crate::store::{LocalStore, S3Store, StorePath};
+use crate::transfer::{TransferOptions, TransferSummary};
+
+/// Uploads the files of `directory` under `target`.
+pub async fn upload_directory(
+    directory: impl AsRef<Path>,
+    target: &RemotePath,
+    remote: &RemoteConfig,
+    options: &TransferOptions,
+) -> Result<TransferSummary, Box<dyn std::error::Error>> {
+    let concurrency = options.concurrency(remote.concurrency, 50);
+    let throttle = options.throttle(concurrency, remote.max_requests_per_second);
+    let store = S3Store::connect(remote, &target.bucket, throttle)?
+        .with_sse(options.sse.clone())
+        .with_events(options.events.clone());
+
+    transfer_between(
+        &StorePath::new(
+            LocalStore::new(directory.as_ref())
+                .with_keys(options.local_keys)
+                .with_walk(options.walk),
+            "",
+        ),
+        &StorePath::new(store, target.key_prefix()),
+        &TransferOptions {
+            concurrency: Some(concurrency),
+            ..options.clone()
+        },
+    )
+    .await
+}
@@ -0,0 +1,145 @@
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use std::collections::BTreeMap;
+
+use crate::store::{ObjectVersion, StorePath};
+use crate::transfer::{EventHandler, Progress, TransferEvent};
+
+/// Settings of a `usage` summary.
+#[derive(Debug, Clone, Default)]
+pub struct UsageOptions {
+    /// Levels of prefixes to total, 0 for the location only
+    pub depth: usize,
+    /// Count noncurrent versions too, which are billed as well
+    pub versions: bool,
+    pub events: EventHandler,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UsageTotals {
+    pub objects: u64,
+    pub bytes: u64,
+}
+
+impl UsageTotals {
+    fn add(&mut self, bytes: u64) {
+        self.objects += 1;
+        self.bytes += bytes;
+    }
+}
+
+/// Age of an object, from its last modification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Age {
+    UpTo30Days,
+    UpTo90Days,
+    UpTo180Days,
+    UpTo1Year,
+    Over1Year,
+    Unknown,
+}
+
+impl Age {
+    pub fn of(modified: Option<DateTime<Utc>>, now: DateTime<Utc>) -> Age {
+        let Some(modified) = modified else {
+            return Age::Unknown;
+        };
+        match (now - modified).num_days() {
+            ..=30 => Age::UpTo30Days,
+            31..=90 => Age::UpTo90Days,
+            91..=180 => Age::UpTo180Days,
+            181..=365 => Age::UpTo1Year,
+            _ => Age::Over1Year,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Age::UpTo30Days => "0-30 days",
+            Age::UpTo90Days => "31-90 days",
+            Age::UpTo180Days => "91-180 days",
+            Age::UpTo1Year => "181-365 days",
+            Age::Over1Year => "over 1 year",
+            Age::Unknown => "unknown",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UsageReport {
+    pub total: UsageTotals,
+    /// Totals under each prefix relative to the location, ending with `/`,
+    /// down to the requested depth
+    pub prefixes: BTreeMap<String, UsageTotals>,
+    pub storage_classes: BTreeMap<String, UsageTotals>,
+    pub ages: BTreeMap<Age, UsageTotals>,
+    /// Listing errors, after which the totals are incomplete
+    pub errors: u64,
+}
+
+/// Lists the objects under `location` and totals their count and size by
+/// prefix, storage class and age.
+pub async fn usage(
+    location: &StorePath,
+    options: &UsageOptions,
+) -> Result<UsageReport, Box<dyn std::error::Error>> {
+    let progress = Progress::new(options.events.clone());
+    let now = Utc::now();
+    let mut entries = match options.versions {
+        true => location.store.list_versions(&location.prefix),
+        false => location
+            .store
+            .list(&location.prefix)
+            .map(|info| {
+                info.map(|info| ObjectVersion {
+                    info,
+                    is_latest: true,
+                    ..Default::default()
+                })
+            })
+            .boxed(),
+    };
+
+    let mut report = UsageReport::default();
+    while let Some(entry) = entries.next().await {
+        let version = match entry {
+            Ok(version) => version,
+            Err(e) => {
+                progress.emit(TransferEvent::ListingFailed {
+                    prefix: location.prefix.clone(),
+                    error: e.to_string(),
+                });
+                report.errors += 1;
+                continue;
+            }
+        };
+        if version.delete_marker {
+            continue;
+        }
+        let info = version.info;
+
+        report.total.add(info.size);
+        let relative_key = location.relative_key(&info.key);
+        for (end, _) in relative_key.match_indices('/').take(options.depth) {
+            let prefix = &relative_key[..=end];
+            report
+                .prefixes
+                .entry(prefix.to_owned())
+                .or_default()
+                .add(info.size);
+        }
+        let storage_class = info.storage_class.as_deref().unwrap_or("STANDARD");
+        report
+            .storage_classes
+            .entry(storage_class.to_owned())
+            .or_default()
+            .add(info.size);
+        report
+            .ages
+            .entry(Age::of(info.modified, now))
+            .or_default()
+            .add(info.size);
+    }
+
+    Ok(report)
+}
@@ -0,0 +1,163 @@
+use notify::event::{AccessKind, AccessMode};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use crate::config::{RemoteConfig, RemotePath};
+use crate::copy::transfer_between;
+use crate::store::{LocalStore, ObjectStore, S3Store, StorePath};
+use crate::transfer::{TransferOptions, TransferSummary};
+
+/// Settings of `watch_directory`, on top of the `TransferOptions`.
+#[derive(Debug, Clone)]
+pub struct WatchOptions {
+    /// Time without changes to wait for before syncing a burst of them
+    pub debounce: Duration,
+    /// Delete the objects of removed files
+    pub delete: bool,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        WatchOptions {
+            debounce: Duration::from_secs(1),
+            delete: false,
+        }
+    }
+}
+
+/// Syncs `directory` to `target`, then keeps syncing what changes in it
+/// until interrupted with Ctrl-C, once the sync in progress is done, and
+/// returns the totals of every sync.
+///
+/// Changes are only noticed, not trusted: each burst of them syncs the
+/// directories they happened in, so that nothing is lost to a missed or
+/// reordered notification.
+pub async fn watch_directory(
+    directory: impl AsRef<Path>,
+    target: &RemotePath,
+    remote: &RemoteConfig,
+    options: &TransferOptions,
+    watch: &WatchOptions,
+) -> Result<TransferSummary, Box<dyn std::error::Error>> {
+    let root = directory
+        .as_ref()
+        .canonicalize()
+        .map_err(|e| format!("Not a directory: {}: {}", directory.as_ref().display(), e))?;
+    let concurrency = options.concurrency(remote.concurrency, 50);
+    let throttle = options.throttle(concurrency, remote.max_requests_per_second);
+    let store: Arc<dyn ObjectStore> = Arc::new(
+        S3Store::connect(remote, &target.bucket, throttle)?
+            .with_sse(options.sse.clone())
+            .with_events(options.events.clone()),
+    );
+    let source: Arc<dyn ObjectStore> = Arc::new(
+        LocalStore::new(&root)
+            .with_keys(options.local_keys)
+            .with_walk(options.walk),
+    );
+    let options = TransferOptions {
+        concurrency: Some(concurrency),
+        sync: true,
+        delete: watch.delete,
+        ..options.clone()
+    };
+
+    // Watch before the first sync, so that no change goes unnoticed
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        if let Ok(event) = event {
+            if is_change(&event.kind) {
+                let _ = tx.send(event.paths);
+            }
+        }
+    })?;
+    watcher.watch(&root, RecursiveMode::Recursive)?;
+
+    // Narrowing a sync to a directory keeps the keys as they are
+    let scoped =
+        !options.flatten && options.keys.is_empty() && options.local_keys == Default::default();
+    let sync = |prefix: String| {
+        let target = StorePath {
+            store: store.clone(),
+            prefix: format!("{}{}", target.key_prefix(), prefix),
+        };
+        let source = StorePath {
+            store: source.clone(),
+            prefix,
+        };
+        let options = &options;
+        async move { transfer_between(&source, &target, options).await }
+    };
+
+    // Listen from now on, so that an interruption during a sync is kept
+    let mut interrupted = tokio::spawn(tokio::signal::ctrl_c());
+    let mut total = sync(String::new()).await?;
+    loop {
+        let mut paths = tokio::select! {
+            _ = &mut interrupted => break,
+            paths = rx.recv() => match paths {
+                Some(paths) => paths,
+                None => break,
+            },
+        };
+        // Wait for the burst to settle
+        while let Ok(Some(more)) = tokio::time::timeout(watch.debounce, rx.recv()).await {
+            paths.extend(more);
+        }
+
+        let prefix = match scoped {
+            true => changed_prefix(&root, &paths),
+            false => String::new(),
+        };
+        let summary = sync(prefix).await?;
+        total.add(&summary);
+    }
+
+    Ok(total)
+}
+
+/// Creations, modifications, renames and removals, not mere reads.
+fn is_change(kind: &EventKind) -> bool {
+    match kind {
+        EventKind::Access(AccessKind::Close(AccessMode::Write)) => true,
+        EventKind::Access(_) | EventKind::Other => false,
+        _ => true,
+    }
+}
+
+/// Deepest directory holding every changed path, as a key prefix relative
+/// to `root`: empty for the root itself, or ending with `/`.
+fn changed_prefix(root: &Path, paths: &[PathBuf]) -> String {
+    let mut common: Option<Vec<String>> = None;
+    for path in paths {
+        // The parent, as a removed directory is gone from the source
+        let directory = path.parent().unwrap_or(path);
+        let Ok(relative) = directory.strip_prefix(root) else {
+            return String::new();
+        };
+        let mut names = Vec::new();
+        for component in relative.components() {
+            match component.as_os_str().to_str() {
+                Some(name) => names.push(name.to_owned()),
+                None => return String::new(),
+            }
+        }
+        common = Some(match common {
+            None => names,
+            Some(common) => common
+                .into_iter()
+                .zip(names)
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| a)
+                .collect(),
+        });
+    }
+
+    match common {
+        Some(names) if !names.is_empty() => format!("{}/", names.join("/")),
+        _ => String::new(),
+    }
+}
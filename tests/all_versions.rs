@@ -0,0 +1,171 @@
+mod common;
+
+use bytes::Bytes;
+use common::TestEnv;
+
+fn body(text: &str) -> Option<Bytes> {
+    Some(Bytes::copy_from_slice(text.as_bytes()))
+}
+
+#[test]
+fn replays_every_version_and_delete_marker() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("origin");
+    env.s3.enable_versioning("origin");
+    env.s3.put_object("origin", "docs/a.txt", "a, first");
+    env.s3.put_object("origin", "docs/a.txt", "a, second");
+    env.s3.delete_object("origin", "docs/a.txt");
+    env.s3.put_object("origin", "docs/b.txt", "b");
+    env.s3.create_bucket("replica");
+    env.s3.enable_versioning("replica");
+
+    let output = env.run(&[
+        "b2b",
+        "mock:origin/docs",
+        "mock:replica/archive",
+        "--all-versions",
+        "--version-mapping",
+        "mapping.jsonl",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout.contains("Done: 4 transferred"), "{}", stdout);
+    assert_eq!(env.s3.keys("replica"), ["archive/b.txt"]);
+    assert_eq!(
+        env.s3.version_bodies("replica", "archive/a.txt"),
+        [body("a, first"), body("a, second"), None]
+    );
+
+    let mapping = std::fs::read_to_string(env.path("mapping.jsonl")).unwrap();
+    let entries = mapping
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(entries.len(), 4);
+    let mapped = |key: &str| {
+        entries
+            .iter()
+            .filter(|entry| entry["key"] == key)
+            .map(|entry| {
+                (
+                    entry["version_id"].as_str().unwrap().to_owned(),
+                    entry["target_version_id"].as_str().unwrap().to_owned(),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    let expected = env
+        .s3
+        .version_ids("origin", "docs/a.txt")
+        .into_iter()
+        .zip(env.s3.version_ids("replica", "archive/a.txt"))
+        .collect::<Vec<_>>();
+    assert_eq!(mapped("docs/a.txt"), expected);
+    assert!(entries
+        .iter()
+        .any(|entry| entry["key"] == "docs/a.txt" && entry["delete_marker"] == true));
+}
+
+#[test]
+fn replays_histories_split_across_pages() {
+    let env = TestEnv::with_page_size(2);
+    env.s3.create_bucket("origin");
+    env.s3.enable_versioning("origin");
+    for text in ["a1", "a2", "a3"] {
+        env.s3.put_object("origin", "a.txt", text);
+    }
+    env.s3.put_object("origin", "b.txt", "b1");
+    env.s3.delete_object("origin", "a.txt");
+    env.s3.put_object("origin", "b.txt", "b2");
+    env.s3.put_object("origin", "c.txt", "c1");
+    env.s3.create_bucket("replica");
+    env.s3.enable_versioning("replica");
+
+    let output = env.run(&[
+        "b2b",
+        "mock:origin",
+        "mock:replica",
+        "--all-versions",
+        "--version-mapping",
+        "mapping.jsonl",
+    ]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        env.s3.version_bodies("replica", "a.txt"),
+        [body("a1"), body("a2"), body("a3"), None]
+    );
+    assert_eq!(
+        env.s3.version_bodies("replica", "b.txt"),
+        [body("b1"), body("b2")]
+    );
+    assert_eq!(env.s3.version_bodies("replica", "c.txt"), [body("c1")]);
+}
+
+#[test]
+fn resumes_from_the_version_mapping() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("origin");
+    env.s3.enable_versioning("origin");
+    env.s3.put_object("origin", "a.txt", "a, first");
+    env.s3.put_object("origin", "a.txt", "a, second");
+    env.s3.create_bucket("replica");
+    env.s3.enable_versioning("replica");
+    let args = [
+        "b2b",
+        "mock:origin",
+        "mock:replica",
+        "--all-versions",
+        "--version-mapping",
+        "mapping.jsonl",
+    ];
+    assert_eq!(env.run(&args).status.code(), Some(0));
+
+    env.s3.put_object("origin", "a.txt", "a, third");
+    let output = env.run(&args);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout.contains("Done: 1 transferred"), "{}", stdout);
+    assert!(stdout.contains("2 skipped"), "{}", stdout);
+    assert_eq!(
+        env.s3.version_bodies("replica", "a.txt"),
+        [body("a, first"), body("a, second"), body("a, third")]
+    );
+}
+
+#[test]
+fn refuses_a_target_without_versioning() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("origin");
+    env.s3.enable_versioning("origin");
+    env.s3.put_object("origin", "a.txt", "a");
+    env.s3.create_bucket("replica");
+
+    let output = env.run(&[
+        "b2b",
+        "mock:origin",
+        "mock:replica",
+        "--all-versions",
+        "--version-mapping",
+        "mapping.jsonl",
+    ]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(env.s3.keys("replica").is_empty());
+
+    // Unless it is copied from the origin first
+    let output = env.run(&[
+        "b2b",
+        "mock:origin",
+        "mock:replica",
+        "--all-versions",
+        "--version-mapping",
+        "mapping.jsonl",
+        "--copy-bucket-config",
+    ]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(env.s3.keys("replica"), ["a.txt"]);
+}
@@ -0,0 +1,193 @@
+mod common;
+
+use common::{eventually, TestEnv};
+use std::cell::RefCell;
+
+#[test]
+fn changes_the_storage_class_in_place() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("assets");
+    env.s3.put_object("assets", "logs/a.log", "a");
+    env.s3.put_object("assets", "logs/b.log", "b");
+    env.s3
+        .set_storage_class("assets", "logs/b.log", "STANDARD_IA");
+    env.s3.put_object("assets", "other.txt", "other");
+
+    let output = env.run(&[
+        "transition",
+        "mock:assets/logs",
+        "--storage-class",
+        "STANDARD_IA",
+    ]);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(
+        stdout.contains("Done: 1 transferred (0 bytes), 1 skipped"),
+        "{}",
+        stdout
+    );
+    assert_eq!(
+        env.s3.storage_class("assets", "logs/a.log").as_deref(),
+        Some("STANDARD_IA")
+    );
+    assert_eq!(env.s3.object("assets", "logs/a.log").unwrap(), "a");
+    assert_eq!(
+        env.s3.storage_class("assets", "other.txt").as_deref(),
+        Some("STANDARD")
+    );
+}
+
+#[test]
+fn keeps_the_kms_key_of_the_objects_in_place() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("assets");
+    env.s3.put_kms_object("assets", "secret.bin", "secret");
+    let kms_key_id = env
+        .s3
+        .header(
+            "assets",
+            "secret.bin",
+            "x-amz-server-side-encryption-aws-kms-key-id",
+        )
+        .unwrap();
+
+    let output = env.run(&["transition", "mock:assets", "--storage-class", "GLACIER"]);
+
+    assert_eq!(
+        output.status.code(),
+        Some(0),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let copy_requests = env.s3.copy_requests();
+    assert_eq!(copy_requests.len(), 1);
+    assert_eq!(copy_requests[0]["x-amz-server-side-encryption"], "aws:kms");
+    assert_eq!(
+        copy_requests[0]["x-amz-server-side-encryption-aws-kms-key-id"],
+        kms_key_id
+    );
+    assert_eq!(
+        env.s3
+            .header(
+                "assets",
+                "secret.bin",
+                "x-amz-server-side-encryption-aws-kms-key-id"
+            )
+            .as_deref(),
+        Some(kms_key_id.as_str())
+    );
+}
+
+#[test]
+fn archived_objects_must_be_restored_before_being_copied() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("origin");
+    env.s3.create_bucket("target");
+    env.s3.put_object("origin", "cold.bin", "cold");
+    env.s3.set_storage_class("origin", "cold.bin", "GLACIER");
+    env.s3.put_object("origin", "warm.bin", "warm");
+
+    let output = env.run(&["b2b", "mock:origin", "mock:target"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(
+        stdout.contains("archived in GLACIER, restore it first"),
+        "{}",
+        stdout
+    );
+    assert_eq!(env.s3.keys("target"), ["warm.bin"]);
+
+    let output = env.run(&["restore_archive", "mock:origin", "--days", "2"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout.contains("Restore requested: cold.bin"), "{}", stdout);
+    assert!(
+        stdout.contains("Done: 1 requested, 1 not archived"),
+        "{}",
+        stdout
+    );
+    assert!(env.s3.restore_requested("origin", "cold.bin"));
+
+    let output = env.run(&["restore_archive", "mock:origin"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(
+        stdout.contains("Restore in progress: cold.bin"),
+        "{}",
+        stdout
+    );
+
+    env.s3.complete_restores("origin");
+    let output = env.run(&["restore_archive", "mock:origin"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Restored: cold.bin (until 2035-12-21"),
+        "{}",
+        stdout
+    );
+
+    let output = env.run(&["b2b", "mock:origin", "mock:target"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(env.s3.object("target", "cold.bin").unwrap(), "cold");
+}
+
+#[test]
+fn waits_for_restores_to_complete() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("origin");
+    env.s3.put_object("origin", "cold.bin", "cold");
+    env.s3
+        .set_storage_class("origin", "cold.bin", "DEEP_ARCHIVE");
+
+    let child = RefCell::new(env.spawn(&[
+        "restore_archive",
+        "mock:origin",
+        "--tier",
+        "bulk",
+        "--wait",
+        "--interval",
+        "1",
+    ]));
+    assert!(eventually(|| env
+        .s3
+        .restore_requested("origin", "cold.bin")));
+    // Still waiting
+    std::thread::sleep(std::time::Duration::from_millis(1500));
+    assert!(child.borrow_mut().try_wait().unwrap().is_none());
+
+    env.s3.complete_restores("origin");
+    let exited = eventually(|| child.borrow_mut().try_wait().unwrap().is_some());
+
+    if !exited {
+        child.borrow_mut().kill().unwrap();
+    }
+    assert!(exited);
+    assert_eq!(child.borrow_mut().wait().unwrap().code(), Some(0));
+}
+
+#[test]
+fn archived_objects_cannot_be_transitioned_until_restored() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("assets");
+    env.s3.put_object("assets", "cold.bin", "cold");
+    env.s3.set_storage_class("assets", "cold.bin", "GLACIER");
+
+    let output = env.run(&["transition", "mock:assets", "--storage-class", "STANDARD"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stdout.contains("restore it first"), "{}", stdout);
+
+    assert_eq!(
+        env.run(&["restore_archive", "mock:assets"]).status.code(),
+        Some(0)
+    );
+    env.s3.complete_restores("assets");
+
+    let output = env.run(&["transition", "mock:assets", "--storage-class", "STANDARD"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        env.s3.storage_class("assets", "cold.bin").as_deref(),
+        Some("STANDARD")
+    );
+}
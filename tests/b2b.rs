@@ -0,0 +1,76 @@
+mod common;
+
+use common::{test_body, TestEnv};
+
+#[test]
+fn copies_across_listing_pages() {
+    let env = TestEnv::with_page_size(4);
+    env.s3.create_bucket("origin");
+    env.s3.create_bucket("target");
+    let keys = (0..23)
+        .map(|i| format!("dir{}/object-{:02}.txt", i % 3, i))
+        .collect::<Vec<_>>();
+    for key in &keys {
+        env.s3.put_object("origin", key, key.clone());
+    }
+
+    let output = env.run(&["b2b", "mock:origin", "mock:target"]);
+
+    assert_eq!(output.status.code(), Some(0));
+    let mut expected = keys.clone();
+    expected.sort();
+    assert_eq!(env.s3.keys("target"), expected);
+    for key in &keys {
+        assert_eq!(env.s3.object("target", key).unwrap(), key.as_str());
+    }
+}
+
+#[test]
+fn copies_a_prefix_to_another_prefix() {
+    let env = TestEnv::with_page_size(3);
+    env.s3.create_bucket("origin");
+    env.s3.create_bucket("target");
+    for i in 0..10 {
+        env.s3
+            .put_object("origin", &format!("logs/{}.log", i), test_body(100 + i));
+        env.s3
+            .put_object("origin", &format!("other/{}.txt", i), "other");
+    }
+
+    let output = env.run(&["b2b", "mock:origin/logs", "mock:target/archive/logs"]);
+
+    assert_eq!(output.status.code(), Some(0));
+    let keys = env.s3.keys("target");
+    assert_eq!(keys.len(), 10);
+    assert!(keys.iter().all(|key| key.starts_with("archive/logs/")));
+    assert_eq!(
+        env.s3.object("target", "archive/logs/7.log").unwrap(),
+        test_body(107)
+    );
+}
+
+#[test]
+fn copies_large_objects_in_parts() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("origin");
+    env.s3.create_bucket("target");
+    let body = test_body(25 * 1024 * 1024);
+    env.s3.put_object("origin", "large.bin", body.clone());
+
+    let output = env.run(&["b2b", "mock:origin", "mock:target"]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(env.s3.completed_multipart_uploads(), 1);
+    assert!(env.s3.object("target", "large.bin").unwrap() == body);
+}
+
+#[test]
+fn reports_a_missing_origin_bucket_in_exit_code() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("target");
+
+    let output = env.run(&["b2b", "mock:missing", "mock:target"]);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(env.s3.keys("target").is_empty());
+}
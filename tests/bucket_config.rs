@@ -0,0 +1,91 @@
+mod common;
+
+use common::TestEnv;
+
+const CORS: &str = "<CORSConfiguration><CORSRule><AllowedMethod>GET</AllowedMethod><AllowedOrigin>https://example.com</AllowedOrigin></CORSRule></CORSConfiguration>";
+const LIFECYCLE: &str = "<LifecycleConfiguration><Rule><ID>expire-tmp</ID><Filter><Prefix>tmp/</Prefix></Filter><Status>Enabled</Status><Expiration><Days>7</Days></Expiration></Rule></LifecycleConfiguration>";
+const ENCRYPTION: &str = "<ServerSideEncryptionConfiguration><Rule><ApplyServerSideEncryptionByDefault><SSEAlgorithm>AES256</SSEAlgorithm></ApplyServerSideEncryptionByDefault></Rule></ServerSideEncryptionConfiguration>";
+const TAGGING: &str =
+    "<Tagging><TagSet><Tag><Key>team</Key><Value>web</Value></Tag></TagSet></Tagging>";
+const WEBSITE: &str = "<WebsiteConfiguration><IndexDocument><Suffix>index.html</Suffix></IndexDocument></WebsiteConfiguration>";
+const POLICY: &str = r#"{"Version":"2012-10-17","Statement":[{"Effect":"Allow","Principal":"*","Action":"s3:GetObject","Resource":["arn:aws:s3:::origin/*","arn:aws:s3:::origin-logs/*"]},{"Effect":"Allow","Principal":"*","Action":"s3:ListBucket","Resource":"arn:aws:s3:::origin"}]}"#;
+
+#[test]
+fn creates_the_target_bucket_and_copies_its_settings() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("origin");
+    env.s3.enable_versioning("origin");
+    env.s3.put_object("origin", "index.html", "index");
+    for (setting, body) in [
+        ("cors", CORS),
+        ("lifecycle", LIFECYCLE),
+        ("encryption", ENCRYPTION),
+        ("tagging", TAGGING),
+        ("website", WEBSITE),
+        ("policy", POLICY),
+    ] {
+        env.s3.set_bucket_setting("origin", setting, body);
+    }
+
+    let output = env.run(&["b2b", "mock:origin", "mock:replica", "--copy-bucket-config"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout.contains("Bucket created: replica"), "{}", stdout);
+    assert_eq!(env.s3.keys("replica"), ["index.html"]);
+    assert!(env.s3.is_versioned("replica"));
+    let setting = |name: &str| env.s3.bucket_setting("replica", name).unwrap_or_default();
+    assert!(setting("cors").contains("<AllowedOrigin>https://example.com</AllowedOrigin>"));
+    assert!(setting("lifecycle").contains("<Days>7</Days>"));
+    assert!(setting("encryption").contains("<SSEAlgorithm>AES256</SSEAlgorithm>"));
+    assert!(setting("tagging").contains("<Key>team</Key><Value>web</Value>"));
+    assert!(setting("website").contains("<Suffix>index.html</Suffix>"));
+    assert_eq!(
+        setting("policy"),
+        POLICY
+            .replace(":::origin/*", ":::replica/*")
+            .replace(":::origin\"", ":::replica\"")
+    );
+}
+
+#[test]
+fn leaves_out_settings_the_origin_does_not_have() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("origin");
+    env.s3.create_bucket("replica");
+    env.s3.put_object("origin", "a.txt", "a");
+    env.s3.set_bucket_setting("origin", "cors", CORS);
+
+    let output = env.run(&["b2b", "mock:origin", "mock:replica", "--copy-bucket-config"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(!stdout.contains("Bucket created"), "{}", stdout);
+    assert!(stdout.contains("Bucket setting copied: CORS"), "{}", stdout);
+    assert!(!env.s3.is_versioned("replica"));
+    assert!(env.s3.bucket_setting("replica", "policy").is_none());
+    assert!(env.s3.bucket_setting("replica", "website").is_none());
+}
+
+#[test]
+fn reports_settings_the_target_does_not_support() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("origin");
+    env.s3.create_bucket("replica");
+    env.s3.put_object("origin", "a.txt", "a");
+    env.s3.set_bucket_setting("origin", "website", WEBSITE);
+    env.s3.set_bucket_setting("origin", "tagging", TAGGING);
+    env.s3.reject_bucket_setting("replica", "website");
+
+    let output = env.run(&["b2b", "mock:origin", "mock:replica", "--copy-bucket-config"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(
+        stdout.contains("Skipping bucket setting website: not supported by the target provider"),
+        "{}",
+        stdout
+    );
+    assert!(env.s3.bucket_setting("replica", "tagging").is_some());
+    assert_eq!(env.s3.keys("replica"), ["a.txt"]);
+}
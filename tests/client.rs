@@ -0,0 +1,155 @@
+use rusoto_core::signature::SignedRequest;
+use rusoto_core::Region;
+
+use s3_utils::client::{to_virtual_host, ClientOptions, CredentialsSource, Provider};
+use s3_utils::RemoteConfig;
+
+fn options(provider: Provider, region: &str) -> ClientOptions {
+    ClientOptions {
+        region: region.to_owned(),
+        provider,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn fills_in_the_endpoint_templates_of_the_providers() {
+    let endpoint = |provider, region| options(provider, region).endpoint().unwrap();
+
+    assert_eq!(
+        endpoint(Provider::Aws, "eu-west-1"),
+        "https://s3.eu-west-1.amazonaws.com"
+    );
+    assert_eq!(
+        endpoint(Provider::Minio, "us-east-1"),
+        "http://localhost:9000"
+    );
+    assert_eq!(
+        endpoint(Provider::B2, "us-west-004"),
+        "https://s3.us-west-004.backblazeb2.com"
+    );
+    assert_eq!(
+        endpoint(Provider::Wasabi, "eu-central-1"),
+        "https://s3.eu-central-1.wasabisys.com"
+    );
+    assert_eq!(
+        endpoint(Provider::Digitalocean, "fra1"),
+        "https://fra1.digitaloceanspaces.com"
+    );
+    assert_eq!(
+        endpoint(Provider::Gcs, "auto"),
+        "https://storage.googleapis.com"
+    );
+}
+
+#[test]
+fn requires_an_account_id_for_r2() {
+    let mut r2 = options(Provider::R2, "auto");
+    assert!(r2.endpoint().is_err());
+
+    r2.account_id = Some("abc123".to_owned());
+    assert_eq!(
+        r2.endpoint().unwrap(),
+        "https://abc123.r2.cloudflarestorage.com"
+    );
+}
+
+#[test]
+fn adds_a_scheme_to_explicit_endpoints_without_one() {
+    let mut custom = options(Provider::Aws, "us-east-1");
+    custom.endpoint = Some("storage.example.com:9000".to_owned());
+    assert_eq!(
+        custom.endpoint().unwrap(),
+        "https://storage.example.com:9000"
+    );
+
+    custom.use_http = true;
+    assert_eq!(
+        custom.endpoint().unwrap(),
+        "http://storage.example.com:9000"
+    );
+
+    custom.endpoint = Some("https://storage.example.com".to_owned());
+    assert_eq!(custom.endpoint().unwrap(), "https://storage.example.com");
+}
+
+#[test]
+fn addresses_aws_virtual_hosted_style_unless_forced_to_path_style() {
+    let aws = options(Provider::Aws, "eu-west-1");
+    assert!(aws.virtual_hosted());
+
+    let forced = ClientOptions {
+        force_path_style: true,
+        ..aws.clone()
+    };
+    assert!(!forced.virtual_hosted());
+
+    let custom = ClientOptions {
+        endpoint: Some("https://s3.example.com".to_owned()),
+        ..aws
+    };
+    assert!(!custom.virtual_hosted());
+
+    for provider in [Provider::Minio, Provider::R2, Provider::B2, Provider::Gcs] {
+        assert!(
+            !options(provider, "auto").virtual_hosted(),
+            "{:?}",
+            provider
+        );
+    }
+}
+
+fn request(path: &str) -> SignedRequest {
+    let region = Region::Custom {
+        name: "eu-west-1".to_owned(),
+        endpoint: "https://s3.eu-west-1.amazonaws.com".to_owned(),
+    };
+    SignedRequest::new("GET", "s3", &region, path)
+}
+
+#[test]
+fn moves_the_bucket_into_the_hostname() {
+    let mut object = request("/my-bucket/photos/cat.jpg");
+    to_virtual_host(&mut object);
+    assert_eq!(object.hostname(), "my-bucket.s3.eu-west-1.amazonaws.com");
+    assert_eq!(object.path, "/photos/cat.jpg");
+
+    let mut bucket = request("/my-bucket");
+    to_virtual_host(&mut bucket);
+    assert_eq!(bucket.hostname(), "my-bucket.s3.eu-west-1.amazonaws.com");
+    assert_eq!(bucket.path, "/");
+}
+
+#[test]
+fn keeps_dotted_buckets_and_service_requests_path_style() {
+    let mut dotted = request("/logs.example.com/2024/01.log");
+    to_virtual_host(&mut dotted);
+    assert_eq!(dotted.hostname(), "s3.eu-west-1.amazonaws.com");
+    assert_eq!(dotted.path, "/logs.example.com/2024/01.log");
+
+    let mut service = request("/");
+    to_virtual_host(&mut service);
+    assert_eq!(service.hostname(), "s3.eu-west-1.amazonaws.com");
+    assert_eq!(service.path, "/");
+}
+
+#[test]
+fn leaves_static_keys_out_of_debug_output() {
+    let remote = RemoteConfig {
+        region: Some("eu-west-1".to_owned()),
+        credentials: Some(CredentialsSource::Static {
+            access_key_id: "AKIAEXAMPLEKEY".to_owned(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG".to_owned(),
+        }),
+        ..Default::default()
+    };
+
+    for debug in [
+        format!("{:?}", remote),
+        format!("{:?}", remote.client_options().unwrap()),
+    ] {
+        assert!(debug.contains("Static"), "{}", debug);
+        assert!(!debug.contains("AKIAEXAMPLEKEY"), "{}", debug);
+        assert!(!debug.contains("wJalrXUtnFEMI"), "{}", debug);
+    }
+}
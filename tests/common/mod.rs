@@ -0,0 +1,1250 @@
+//! An in-process stand-in for an S3-compatible provider, and helpers to run
+//! the commands against it.
+#![allow(dead_code)]
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use std::fmt::Write as _;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+/// Headers stored with an object and sent back when it is read, but for
+/// the tags, whose number only is
+const KEPT_HEADERS: [&str; 5] = [
+    "content-encoding",
+    "x-amz-server-side-encryption",
+    "x-amz-server-side-encryption-aws-kms-key-id",
+    "x-amz-storage-class",
+    "x-amz-tagging",
+];
+
+/// Bucket settings stored as sent, and the error code of a bucket without
+const BUCKET_SETTINGS: [(&str, &str); 6] = [
+    ("cors", "NoSuchCORSConfiguration"),
+    ("lifecycle", "NoSuchLifecycleConfiguration"),
+    ("policy", "NoSuchBucketPolicy"),
+    (
+        "encryption",
+        "ServerSideEncryptionConfigurationNotFoundError",
+    ),
+    ("tagging", "NoSuchTagSet"),
+    ("website", "NoSuchWebsiteConfiguration"),
+];
+
+#[derive(Clone)]
+struct Version {
+    id: String,
+    /// `None` for delete markers
+    body: Option<Bytes>,
+    headers: Vec<(String, String)>,
+    modified: DateTime<Utc>,
+}
+
+impl Version {
+    fn storage_class(&self) -> &str {
+        self.headers
+            .iter()
+            .find(|(name, _)| name == "x-amz-storage-class")
+            .map_or("STANDARD", |(_, value)| value)
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn tags(&self) -> BTreeMap<String, String> {
+        self.header("x-amz-tagging")
+            .map(parse_query)
+            .unwrap_or_default()
+            .into_iter()
+            .collect()
+    }
+
+    fn set_tags(&mut self, tags: &BTreeMap<String, String>) {
+        self.headers.retain(|(name, _)| name != "x-amz-tagging");
+        let tagging = tags
+            .iter()
+            .map(|(key, value)| format!("{}={}", percent_encode(key), percent_encode(value)))
+            .collect::<Vec<_>>()
+            .join("&");
+        self.headers.push(("x-amz-tagging".to_owned(), tagging));
+    }
+
+    /// In an archive storage class and not restored, so not readable.
+    fn archived(&self) -> bool {
+        matches!(self.storage_class(), "GLACIER" | "DEEP_ARCHIVE")
+            && !self
+                .header("x-amz-restore")
+                .is_some_and(|restore| restore.contains("ongoing-request=\"false\""))
+    }
+
+    /// MD5 of the body, but for SSE-KMS, whose ETags are not.
+    fn e_tag(&self) -> String {
+        let body = self.body.as_deref().unwrap_or_default();
+        match self.header("x-amz-server-side-encryption") {
+            Some("aws:kms") => {
+                let data_key = self.header("x-amz-server-side-encryption-aws-kms-key-id");
+                format!("\"{:x}\"", md5::compute(data_key.unwrap_or_default()))
+            }
+            _ => format!("\"{:x}\"", md5::compute(body)),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Bucket {
+    versioning: bool,
+    /// `Suspended` once versioning was turned off
+    versioning_status: Option<String>,
+    /// Bodies of the settings, by query parameter
+    settings: BTreeMap<String, String>,
+    /// Settings answered with `NotImplemented`, like some providers do
+    unsupported: Vec<String>,
+    /// Versions of each key, oldest first
+    objects: BTreeMap<String, Vec<Version>>,
+}
+
+impl Bucket {
+    fn latest(&self, key: &str) -> Option<&Version> {
+        self.objects
+            .get(key)
+            .and_then(|versions| versions.last())
+            .filter(|version| version.body.is_some())
+    }
+
+    /// The version of `key` with `version_id`, or the current one.
+    fn version(&self, key: &str, version_id: Option<&String>) -> Option<&Version> {
+        match version_id {
+            Some(version_id) => self
+                .objects
+                .get(key)?
+                .iter()
+                .find(|version| &version.id == version_id)
+                .filter(|version| version.body.is_some()),
+            None => self.latest(key),
+        }
+    }
+}
+
+struct Upload {
+    headers: Vec<(String, String)>,
+    parts: BTreeMap<u32, Bytes>,
+}
+
+struct State {
+    buckets: BTreeMap<String, Bucket>,
+    uploads: HashMap<String, Upload>,
+    next_id: u64,
+    page_size: usize,
+    omit_next_markers: bool,
+    completed_multipart_uploads: usize,
+    /// Headers of the copy requests that wrote an object
+    copy_requests: Vec<HashMap<String, String>>,
+    /// Requests still to answer with `SlowDown`
+    slow_downs: usize,
+}
+
+impl State {
+    fn next_id(&mut self) -> String {
+        self.next_id += 1;
+        format!("{:08}", self.next_id)
+    }
+
+    /// Writes a version and returns its id, in versioned buckets.
+    fn put(
+        &mut self,
+        bucket: &str,
+        key: &str,
+        body: Bytes,
+        headers: Vec<(String, String)>,
+    ) -> Option<String> {
+        let id = self.next_id();
+        let bucket = self.buckets.get_mut(bucket).unwrap();
+        let versions = bucket.objects.entry(key.to_owned()).or_default();
+        if !bucket.versioning {
+            versions.clear();
+        }
+        versions.push(Version {
+            id: match bucket.versioning {
+                true => id.clone(),
+                false => "null".to_owned(),
+            },
+            body: Some(body),
+            headers,
+            modified: Utc::now(),
+        });
+        bucket.versioning.then_some(id)
+    }
+
+    /// Deletes an object and returns the id of the delete marker left.
+    fn delete(&mut self, bucket: &str, key: &str) -> Option<String> {
+        let id = self.next_id();
+        let bucket = self.buckets.get_mut(bucket).unwrap();
+        if !bucket.versioning {
+            bucket.objects.remove(key);
+            return None;
+        }
+        bucket.latest(key)?;
+        bucket
+            .objects
+            .entry(key.to_owned())
+            .or_default()
+            .push(Version {
+                id: id.clone(),
+                body: None,
+                headers: Vec::new(),
+                modified: Utc::now(),
+            });
+        Some(id)
+    }
+}
+
+/// A mock S3 server on a random local port, serving path-style requests
+/// from memory. Request signatures are not checked.
+#[derive(Clone)]
+pub struct MockS3 {
+    port: u16,
+    state: Arc<Mutex<State>>,
+}
+
+impl MockS3 {
+    /// Starts a server whose listings return at most `page_size` entries
+    /// per page, like the 1000 of S3.
+    pub fn start(page_size: usize) -> Self {
+        let state = Arc::new(Mutex::new(State {
+            buckets: BTreeMap::new(),
+            uploads: HashMap::new(),
+            next_id: 0,
+            page_size,
+            omit_next_markers: false,
+            completed_multipart_uploads: 0,
+            copy_requests: Vec::new(),
+            slow_downs: 0,
+        }));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server_state = state.clone();
+        std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            runtime.block_on(async move {
+                let make_service = make_service_fn(move |_| {
+                    let state = server_state.clone();
+                    async move {
+                        Ok::<_, Infallible>(service_fn(move |request| {
+                            handle(state.clone(), request)
+                        }))
+                    }
+                });
+                Server::from_tcp(listener)
+                    .unwrap()
+                    .serve(make_service)
+                    .await
+                    .unwrap();
+            });
+        });
+
+        MockS3 { port, state }
+    }
+
+    pub fn endpoint(&self) -> String {
+        format!("http://127.0.0.1:{}", self.port)
+    }
+
+    pub fn create_bucket(&self, bucket: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.buckets.entry(bucket.to_owned()).or_default();
+    }
+
+    pub fn enable_versioning(&self, bucket: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.buckets.get_mut(bucket).unwrap().versioning = true;
+    }
+
+    /// Turns versioning off: new objects replace the null version.
+    pub fn suspend_versioning(&self, bucket: &str) {
+        let mut state = self.state.lock().unwrap();
+        let bucket = state.buckets.get_mut(bucket).unwrap();
+        bucket.versioning = false;
+        bucket.versioning_status = Some("Suspended".to_owned());
+    }
+
+    pub fn bucket_exists(&self, bucket: &str) -> bool {
+        self.state.lock().unwrap().buckets.contains_key(bucket)
+    }
+
+    pub fn is_versioned(&self, bucket: &str) -> bool {
+        self.state.lock().unwrap().buckets[bucket].versioning
+    }
+
+    /// Sets a bucket setting, e.g. `cors`, to `body` as a client would send.
+    pub fn set_bucket_setting(&self, bucket: &str, setting: &str, body: &str) {
+        let mut state = self.state.lock().unwrap();
+        let settings = &mut state.buckets.get_mut(bucket).unwrap().settings;
+        settings.insert(setting.to_owned(), body.to_owned());
+    }
+
+    pub fn bucket_setting(&self, bucket: &str, setting: &str) -> Option<String> {
+        let state = self.state.lock().unwrap();
+        state.buckets[bucket].settings.get(setting).cloned()
+    }
+
+    /// Answers requests about a bucket setting with `NotImplemented`.
+    pub fn reject_bucket_setting(&self, bucket: &str, setting: &str) {
+        let mut state = self.state.lock().unwrap();
+        let unsupported = &mut state.buckets.get_mut(bucket).unwrap().unsupported;
+        unsupported.push(setting.to_owned());
+    }
+
+    pub fn put_object(&self, bucket: &str, key: &str, body: impl Into<Bytes>) {
+        let mut state = self.state.lock().unwrap();
+        state.put(bucket, key, body.into(), Vec::new());
+    }
+
+    /// Puts an object encrypted with SSE-KMS.
+    pub fn put_kms_object(&self, bucket: &str, key: &str, body: impl Into<Bytes>) {
+        let mut state = self.state.lock().unwrap();
+        // A key of its own gives the object an ETag of its own
+        let headers = vec![
+            (
+                "x-amz-server-side-encryption".to_owned(),
+                "aws:kms".to_owned(),
+            ),
+            (
+                "x-amz-server-side-encryption-aws-kms-key-id".to_owned(),
+                state.next_id(),
+            ),
+        ];
+        state.put(bucket, key, body.into(), headers);
+    }
+
+    /// Deletes like a client would: versioned buckets get a delete marker.
+    pub fn delete_object(&self, bucket: &str, key: &str) {
+        self.state.lock().unwrap().delete(bucket, key);
+    }
+
+    /// Keys of the current objects of `bucket`, in order.
+    pub fn keys(&self, bucket: &str) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+        let bucket = &state.buckets[bucket];
+        bucket
+            .objects
+            .keys()
+            .filter(|key| bucket.latest(key).is_some())
+            .cloned()
+            .collect()
+    }
+
+    pub fn object(&self, bucket: &str, key: &str) -> Option<Bytes> {
+        let state = self.state.lock().unwrap();
+        state.buckets[bucket]
+            .latest(key)
+            .and_then(|version| version.body.clone())
+    }
+
+    /// Ids of the versions and delete markers of `key`, oldest first.
+    pub fn version_ids(&self, bucket: &str, key: &str) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+        state.buckets[bucket].objects[key]
+            .iter()
+            .map(|version| version.id.clone())
+            .collect()
+    }
+
+    /// Bodies of the versions of `key`, oldest first, `None` for delete
+    /// markers.
+    pub fn version_bodies(&self, bucket: &str, key: &str) -> Vec<Option<Bytes>> {
+        let state = self.state.lock().unwrap();
+        state.buckets[bucket].objects[key]
+            .iter()
+            .map(|version| version.body.clone())
+            .collect()
+    }
+
+    /// Versions and delete markers of `bucket`, current or not.
+    pub fn version_count(&self, bucket: &str) -> usize {
+        let state = self.state.lock().unwrap();
+        state.buckets[bucket].objects.values().map(Vec::len).sum()
+    }
+
+    pub fn storage_class(&self, bucket: &str, key: &str) -> Option<String> {
+        let state = self.state.lock().unwrap();
+        state.buckets[bucket]
+            .latest(key)
+            .map(|version| version.storage_class().to_owned())
+    }
+
+    /// Changes the storage class of the current version of `key`.
+    pub fn set_storage_class(&self, bucket: &str, key: &str, storage_class: &str) {
+        let mut state = self.state.lock().unwrap();
+        let version = state
+            .buckets
+            .get_mut(bucket)
+            .unwrap()
+            .objects
+            .get_mut(key)
+            .unwrap();
+        let version = version.last_mut().unwrap();
+        version
+            .headers
+            .retain(|(name, _)| name != "x-amz-storage-class");
+        version
+            .headers
+            .push(("x-amz-storage-class".to_owned(), storage_class.to_owned()));
+    }
+
+    /// Completes the restores in progress, as the provider would hours later.
+    pub fn complete_restores(&self, bucket: &str) {
+        let mut state = self.state.lock().unwrap();
+        for versions in state.buckets.get_mut(bucket).unwrap().objects.values_mut() {
+            for version in versions {
+                for (name, value) in &mut version.headers {
+                    if name == "x-amz-restore" {
+                        *value = "ongoing-request=\"false\", expiry-date=\"Fri, 21 Dec 2035 00:00:00 GMT\"".to_owned();
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn tags(&self, bucket: &str, key: &str) -> BTreeMap<String, String> {
+        let state = self.state.lock().unwrap();
+        state.buckets[bucket].latest(key).unwrap().tags()
+    }
+
+    /// Replaces the tags of the current version of `key`.
+    pub fn set_tags(&self, bucket: &str, key: &str, tags: &[(&str, &str)]) {
+        let mut state = self.state.lock().unwrap();
+        let versions = state
+            .buckets
+            .get_mut(bucket)
+            .unwrap()
+            .objects
+            .get_mut(key)
+            .unwrap();
+        let tags = tags
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        versions.last_mut().unwrap().set_tags(&tags);
+    }
+
+    /// Whether a restore of the current version of `key` was asked for.
+    pub fn restore_requested(&self, bucket: &str, key: &str) -> bool {
+        let state = self.state.lock().unwrap();
+        state.buckets[bucket]
+            .latest(key)
+            .is_some_and(|version| version.header("x-amz-restore").is_some())
+    }
+
+    /// Flips the last byte of the current version of `key`, keeping its
+    /// headers, as a corruption in transit would.
+    pub fn corrupt_object(&self, bucket: &str, key: &str) {
+        let mut state = self.state.lock().unwrap();
+        let version = state
+            .buckets
+            .get_mut(bucket)
+            .unwrap()
+            .objects
+            .get_mut(key)
+            .unwrap()
+            .last_mut()
+            .unwrap();
+        let mut body = version.body.as_ref().unwrap().to_vec();
+        *body.last_mut().unwrap() ^= 0xff;
+        version.body = Some(Bytes::from(body));
+    }
+
+    /// Backdates the current version of `key` by `days`.
+    pub fn set_age(&self, bucket: &str, key: &str, days: i64) {
+        let mut state = self.state.lock().unwrap();
+        let versions = state
+            .buckets
+            .get_mut(bucket)
+            .unwrap()
+            .objects
+            .get_mut(key)
+            .unwrap();
+        versions.last_mut().unwrap().modified = Utc::now() - chrono::Duration::days(days);
+    }
+
+    /// Backdates the versions and delete markers of `key`, oldest first, by
+    /// as many `days` each.
+    pub fn set_version_ages(&self, bucket: &str, key: &str, days: &[i64]) {
+        let mut state = self.state.lock().unwrap();
+        let versions = state
+            .buckets
+            .get_mut(bucket)
+            .unwrap()
+            .objects
+            .get_mut(key)
+            .unwrap();
+        assert_eq!(versions.len(), days.len());
+        for (version, days) in versions.iter_mut().zip(days) {
+            version.modified = Utc::now() - chrono::Duration::days(*days);
+        }
+    }
+
+    /// Leaves the continuation token and markers out of truncated listings,
+    /// as some providers wrongly do.
+    pub fn omit_next_markers(&self) {
+        self.state.lock().unwrap().omit_next_markers = true;
+    }
+
+    pub fn completed_multipart_uploads(&self) -> usize {
+        self.state.lock().unwrap().completed_multipart_uploads
+    }
+
+    /// Answers the next `count` requests with `SlowDown`, as a provider
+    /// over its request rate would.
+    pub fn slow_down(&self, count: usize) {
+        self.state.lock().unwrap().slow_downs = count;
+    }
+
+    /// Objects written by copying others in the mock.
+    pub fn copied_objects(&self) -> usize {
+        self.state.lock().unwrap().copy_requests.len()
+    }
+
+    /// Headers of the copy requests that wrote an object, in order.
+    pub fn copy_requests(&self) -> Vec<HashMap<String, String>> {
+        self.state.lock().unwrap().copy_requests.clone()
+    }
+
+    /// Header `name` of the current version of `key`.
+    pub fn header(&self, bucket: &str, key: &str, name: &str) -> Option<String> {
+        let state = self.state.lock().unwrap();
+        state.buckets[bucket].latest(key).and_then(|version| {
+            version
+                .headers
+                .iter()
+                .find(|(header, _)| header == name)
+                .map(|(_, value)| value.clone())
+        })
+    }
+}
+
+/// A mock server, a scratch directory and a configuration file whose
+/// `mock` remote points to the server.
+pub struct TestEnv {
+    pub s3: MockS3,
+    pub dir: TempDir,
+    config: PathBuf,
+}
+
+impl TestEnv {
+    pub fn new() -> Self {
+        TestEnv::with_page_size(1000)
+    }
+
+    pub fn with_page_size(page_size: usize) -> Self {
+        let s3 = MockS3::start(page_size);
+        let dir = tempfile::tempdir().unwrap();
+        let config = dir.path().join("config.toml");
+        std::fs::write(
+            &config,
+            format!(
+                r#"[remotes.mock]
+provider = "minio"
+endpoint = "{}"
+region = "us-east-1"
+credentials = {{ source = "static", access_key_id = "test", secret_access_key = "test" }}
+"#,
+                s3.endpoint()
+            ),
+        )
+        .unwrap();
+        TestEnv { s3, dir, config }
+    }
+
+    /// Adds a setting, e.g. `concurrency = 8`, to the `mock` remote.
+    pub fn configure_remote(&self, setting: &str) {
+        let mut config = std::fs::read_to_string(&self.config).unwrap();
+        config.push_str(setting);
+        config.push('\n');
+        std::fs::write(&self.config, config).unwrap();
+    }
+
+    /// Path under the scratch directory.
+    pub fn path(&self, relative: &str) -> PathBuf {
+        self.dir.path().join(relative)
+    }
+
+    /// Writes a file under the scratch directory, creating its parents.
+    pub fn write_file(&self, relative: &str, body: &[u8]) -> PathBuf {
+        let path = self.path(relative);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, body).unwrap();
+        path
+    }
+
+    fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_s3-utils"));
+        command
+            .args(args)
+            .arg("--config")
+            .arg(&self.config)
+            .env_remove("S3_UTILS_CSE_PASSPHRASE")
+            .current_dir(self.dir.path());
+        command
+    }
+
+    /// Runs `s3-utils` with `args` and the configuration file.
+    pub fn run(&self, args: &[&str]) -> Output {
+        let output = self.command(args).output().unwrap();
+        print!("{}", String::from_utf8_lossy(&output.stdout));
+        eprint!("{}", String::from_utf8_lossy(&output.stderr));
+        output
+    }
+
+    /// Starts `s3-utils` in the background, for commands that keep running.
+    pub fn spawn(&self, args: &[&str]) -> Child {
+        self.command(args).spawn().unwrap()
+    }
+}
+
+/// `len` bytes that differ from one part to the next.
+pub fn test_body(len: usize) -> Vec<u8> {
+    (0..len)
+        .map(|i| (i % 251) as u8 ^ (i >> 20) as u8)
+        .collect()
+}
+
+pub fn local_files(root: &Path) -> Vec<String> {
+    let mut files = walkdir::WalkDir::new(root)
+        .into_iter()
+        .map(Result::unwrap)
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| {
+            let relative = entry.path().strip_prefix(root).unwrap();
+            relative.to_string_lossy().replace('\\', "/")
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+    files
+}
+
+async fn handle(
+    state: Arc<Mutex<State>>,
+    request: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let method = request.method().clone();
+    let path = percent_decode(request.uri().path());
+    let query = request.uri().query().map(parse_query).unwrap_or_default();
+    let headers = request
+        .headers()
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.as_str().to_owned(),
+                value.to_str().unwrap_or_default().to_owned(),
+            )
+        })
+        .collect::<HashMap<_, _>>();
+    let body = hyper::body::to_bytes(request.into_body())
+        .await
+        .unwrap_or_default();
+
+    let (bucket, key) = match path.trim_start_matches('/').split_once('/') {
+        Some((bucket, key)) => (bucket.to_owned(), key.to_owned()),
+        None => (path.trim_start_matches('/').to_owned(), String::new()),
+    };
+
+    let mut state = state.lock().unwrap();
+    if state.slow_downs > 0 {
+        state.slow_downs -= 1;
+        return Ok(error(StatusCode::SERVICE_UNAVAILABLE, "SlowDown"));
+    }
+    if method == Method::PUT && key.is_empty() && query.is_empty() {
+        state.buckets.entry(bucket).or_default();
+        return Ok(response(StatusCode::OK, ""));
+    }
+    if !state.buckets.contains_key(&bucket) {
+        return Ok(error(StatusCode::NOT_FOUND, "NoSuchBucket"));
+    }
+    let setting = BUCKET_SETTINGS
+        .iter()
+        .find(|(setting, _)| key.is_empty() && query.contains_key(*setting));
+    if key.is_empty()
+        && state.buckets[&bucket]
+            .unsupported
+            .iter()
+            .any(|setting| query.contains_key(setting))
+    {
+        return Ok(error(StatusCode::NOT_IMPLEMENTED, "NotImplemented"));
+    }
+
+    let response = match (method, key.is_empty()) {
+        (Method::HEAD, true) => response(StatusCode::OK, ""),
+        (Method::PUT, true) if query.contains_key("versioning") => {
+            let body = String::from_utf8_lossy(&body);
+            let bucket = state.buckets.get_mut(&bucket).unwrap();
+            bucket.versioning = body.contains("<Status>Enabled</Status>");
+            bucket.versioning_status = match bucket.versioning {
+                true => None,
+                false => Some("Suspended".to_owned()),
+            };
+            response(StatusCode::OK, "")
+        }
+        (Method::GET, true) if query.contains_key("versioning") => {
+            let bucket = &state.buckets[&bucket];
+            let status = match bucket.versioning {
+                true => Some("Enabled"),
+                false => bucket.versioning_status.as_deref(),
+            };
+            let status = status
+                .map(|status| format!("<Status>{}</Status>", status))
+                .unwrap_or_default();
+            response(
+                StatusCode::OK,
+                format!(
+                    "<VersioningConfiguration>{}</VersioningConfiguration>",
+                    status
+                ),
+            )
+        }
+        (Method::PUT, true) if setting.is_some() => {
+            let (setting, _) = setting.unwrap();
+            let settings = &mut state.buckets.get_mut(&bucket).unwrap().settings;
+            settings.insert(
+                setting.to_string(),
+                String::from_utf8_lossy(&body).into_owned(),
+            );
+            response(StatusCode::OK, "")
+        }
+        (Method::GET, true) if setting.is_some() => {
+            let (setting, missing) = setting.unwrap();
+            match state.buckets[&bucket].settings.get(*setting) {
+                Some(body) => response(StatusCode::OK, body.clone()),
+                None => error(StatusCode::NOT_FOUND, missing),
+            }
+        }
+        (Method::GET, true) if query.contains_key("versions") => {
+            response(StatusCode::OK, list_versions(&state, &bucket, &query))
+        }
+        (Method::GET, true) => response(StatusCode::OK, list_objects(&state, &bucket, &query)),
+        (Method::DELETE, true) => {
+            if state.buckets[&bucket].objects.is_empty() {
+                state.buckets.remove(&bucket);
+                response(StatusCode::NO_CONTENT, "")
+            } else {
+                error(StatusCode::CONFLICT, "BucketNotEmpty")
+            }
+        }
+        (Method::PUT, false) if query.contains_key("tagging") => {
+            let objects = &mut state.buckets.get_mut(&bucket).unwrap().objects;
+            let version = objects.get_mut(&key).and_then(|versions| {
+                versions
+                    .iter_mut()
+                    .rev()
+                    .find(|version| query.get("versionId").is_none_or(|id| &version.id == id))
+            });
+            match version {
+                Some(version) if version.body.is_some() => {
+                    version.set_tags(&parse_tag_set(&String::from_utf8_lossy(&body)));
+                    response(StatusCode::OK, "")
+                }
+                _ => error(StatusCode::NOT_FOUND, "NoSuchKey"),
+            }
+        }
+        (Method::PUT, false) if query.contains_key("partNumber") => {
+            let part_number = query["partNumber"].parse().unwrap();
+            match state.uploads.get_mut(&query["uploadId"]) {
+                Some(upload) => {
+                    let e_tag = format!("\"{:x}\"", md5::compute(&body));
+                    upload.parts.insert(part_number, body);
+                    with_header(response(StatusCode::OK, ""), "etag", &e_tag)
+                }
+                None => error(StatusCode::NOT_FOUND, "NoSuchUpload"),
+            }
+        }
+        (Method::PUT, false) if headers.contains_key("x-amz-copy-source") => {
+            let copy_source = &headers["x-amz-copy-source"];
+            let (source, version_id) = match copy_source.split_once("?versionId=") {
+                Some((source, version_id)) => (source, Some(percent_decode(version_id))),
+                None => (copy_source.as_str(), None),
+            };
+            let source = percent_decode(source);
+            let (source_bucket, source_key) =
+                source.trim_start_matches('/').split_once('/').unwrap();
+            let source = state
+                .buckets
+                .get(source_bucket)
+                .and_then(|bucket| bucket.version(source_key, version_id.as_ref()))
+                .cloned();
+            match source {
+                Some(source) if source.archived() => {
+                    error(StatusCode::FORBIDDEN, "InvalidObjectState")
+                }
+                Some(source) => {
+                    let e_tag = source.e_tag();
+                    let mut new_headers = source.headers;
+                    // The restored copy is not part of the new object
+                    new_headers.retain(|(name, _)| name != "x-amz-restore");
+                    // Like S3, the copy is encrypted as asked, not as its source
+                    new_headers
+                        .retain(|(name, _)| !name.starts_with("x-amz-server-side-encryption"));
+                    new_headers.extend(
+                        kept_headers(&headers)
+                            .into_iter()
+                            .filter(|(name, _)| name.starts_with("x-amz-server-side-encryption")),
+                    );
+                    if let Some(storage_class) = headers.get("x-amz-storage-class") {
+                        new_headers.retain(|(name, _)| name != "x-amz-storage-class");
+                        new_headers.push(("x-amz-storage-class".to_owned(), storage_class.clone()));
+                    }
+                    let version_id = state.put(&bucket, &key, source.body.unwrap(), new_headers);
+                    state.copy_requests.push(headers.clone());
+                    with_version_id(
+                        response(
+                            StatusCode::OK,
+                            format!(
+                                "<CopyObjectResult><ETag>{}</ETag></CopyObjectResult>",
+                                e_tag
+                            ),
+                        ),
+                        version_id,
+                    )
+                }
+                None => error(StatusCode::NOT_FOUND, "NoSuchKey"),
+            }
+        }
+        (Method::PUT, false) => {
+            let e_tag = format!("\"{:x}\"", md5::compute(&body));
+            let version_id = state.put(&bucket, &key, body, kept_headers(&headers));
+            with_version_id(
+                with_header(response(StatusCode::OK, ""), "etag", &e_tag),
+                version_id,
+            )
+        }
+        (Method::POST, false) if query.contains_key("restore") => {
+            let objects = &mut state.buckets.get_mut(&bucket).unwrap().objects;
+            match objects
+                .get_mut(&key)
+                .and_then(|versions| versions.last_mut())
+            {
+                Some(version) if version.body.is_none() => {
+                    error(StatusCode::NOT_FOUND, "NoSuchKey")
+                }
+                Some(version) if !matches!(version.storage_class(), "GLACIER" | "DEEP_ARCHIVE") => {
+                    error(StatusCode::FORBIDDEN, "InvalidObjectState")
+                }
+                Some(version) => match version.header("x-amz-restore") {
+                    Some(restore) if restore.contains("ongoing-request=\"true\"") => {
+                        error(StatusCode::CONFLICT, "RestoreAlreadyInProgress")
+                    }
+                    Some(_) => response(StatusCode::OK, ""),
+                    None => {
+                        version.headers.push((
+                            "x-amz-restore".to_owned(),
+                            "ongoing-request=\"true\"".to_owned(),
+                        ));
+                        response(StatusCode::ACCEPTED, "")
+                    }
+                },
+                None => error(StatusCode::NOT_FOUND, "NoSuchKey"),
+            }
+        }
+        (Method::POST, false) if query.contains_key("uploads") => {
+            let upload_id = state.next_id();
+            state.uploads.insert(
+                upload_id.clone(),
+                Upload {
+                    headers: kept_headers(&headers),
+                    parts: BTreeMap::new(),
+                },
+            );
+            response(
+                StatusCode::OK,
+                format!(
+                    "<InitiateMultipartUploadResult><Bucket>{}</Bucket><Key>{}</Key><UploadId>{}</UploadId></InitiateMultipartUploadResult>",
+                    escape(&bucket),
+                    escape(&key),
+                    upload_id
+                ),
+            )
+        }
+        (Method::POST, false) if query.contains_key("uploadId") => {
+            match state.uploads.remove(&query["uploadId"]) {
+                Some(upload) => {
+                    let body = upload.parts.into_values().collect::<Vec<_>>().concat();
+                    let version_id = state.put(&bucket, &key, body.into(), upload.headers);
+                    state.completed_multipart_uploads += 1;
+                    with_version_id(
+                        response(
+                            StatusCode::OK,
+                            "<CompleteMultipartUploadResult><ETag>\"multipart\"</ETag></CompleteMultipartUploadResult>",
+                        ),
+                        version_id,
+                    )
+                }
+                None => error(StatusCode::NOT_FOUND, "NoSuchUpload"),
+            }
+        }
+        (Method::DELETE, false) if query.contains_key("uploadId") => {
+            state.uploads.remove(&query["uploadId"]);
+            response(StatusCode::NO_CONTENT, "")
+        }
+        (Method::DELETE, false) => {
+            let marker_id = match query.get("versionId") {
+                Some(version_id) => {
+                    let objects = &mut state.buckets.get_mut(&bucket).unwrap().objects;
+                    if let Some(versions) = objects.get_mut(&key) {
+                        versions.retain(|version| &version.id != version_id);
+                        if versions.is_empty() {
+                            objects.remove(&key);
+                        }
+                    }
+                    None
+                }
+                None => state.delete(&bucket, &key),
+            };
+            with_version_id(response(StatusCode::NO_CONTENT, ""), marker_id)
+        }
+        (Method::GET, false) if query.contains_key("tagging") => {
+            match state.buckets[&bucket].version(&key, query.get("versionId")) {
+                Some(version) => {
+                    let mut xml = "<Tagging><TagSet>".to_owned();
+                    for (key, value) in version.tags() {
+                        write!(
+                            xml,
+                            "<Tag><Key>{}</Key><Value>{}</Value></Tag>",
+                            escape(&key),
+                            escape(&value)
+                        )
+                        .unwrap();
+                    }
+                    response(StatusCode::OK, xml + "</TagSet></Tagging>")
+                }
+                None => error(StatusCode::NOT_FOUND, "NoSuchKey"),
+            }
+        }
+        (method @ (Method::GET | Method::HEAD), false) => {
+            match state.buckets[&bucket].version(&key, query.get("versionId")) {
+                Some(version) if version.archived() && method == Method::GET => {
+                    error(StatusCode::FORBIDDEN, "InvalidObjectState")
+                }
+                Some(version) => object_response(version, &headers, method == Method::HEAD),
+                None => error(StatusCode::NOT_FOUND, "NoSuchKey"),
+            }
+        }
+        _ => error(StatusCode::BAD_REQUEST, "InvalidRequest"),
+    };
+    Ok(response)
+}
+
+fn list_objects(state: &State, bucket: &str, query: &HashMap<String, String>) -> String {
+    let bucket = &state.buckets[bucket];
+    let prefix = query.get("prefix").map(String::as_str).unwrap_or_default();
+    let after = query
+        .get("continuation-token")
+        .map(String::as_str)
+        .unwrap_or_default();
+
+    let keys = bucket
+        .objects
+        .keys()
+        .filter(|key| key.starts_with(prefix) && key.as_str() > after)
+        .filter(|key| bucket.latest(key).is_some())
+        .collect::<Vec<_>>();
+    let page = &keys[..keys.len().min(state.page_size)];
+    let truncated = keys.len() > page.len();
+
+    let mut xml = format!(
+        "<ListBucketResult><IsTruncated>{}</IsTruncated><KeyCount>{}</KeyCount>",
+        truncated,
+        page.len()
+    );
+    for key in page {
+        let version = bucket.latest(key).unwrap();
+        write!(
+            xml,
+            "<Contents><Key>{}</Key><Size>{}</Size><LastModified>{}</LastModified><ETag>{}</ETag><StorageClass>{}</StorageClass></Contents>",
+            escape(key),
+            version.body.as_ref().unwrap().len(),
+            version.modified.format("%Y-%m-%dT%H:%M:%S%.3fZ"),
+            escape(&version.e_tag()),
+            version.storage_class()
+        )
+        .unwrap();
+    }
+    if let (true, Some(last), false) = (truncated, page.last(), state.omit_next_markers) {
+        write!(
+            xml,
+            "<NextContinuationToken>{}</NextContinuationToken>",
+            escape(last)
+        )
+        .unwrap();
+    }
+    xml + "</ListBucketResult>"
+}
+
+fn list_versions(state: &State, bucket: &str, query: &HashMap<String, String>) -> String {
+    let bucket = &state.buckets[bucket];
+    let prefix = query.get("prefix").map(String::as_str).unwrap_or_default();
+
+    // Newest version first, as S3 does
+    let entries = bucket
+        .objects
+        .iter()
+        .filter(|(key, _)| key.starts_with(prefix))
+        .flat_map(|(key, versions)| {
+            let latest = versions.len() - 1;
+            versions
+                .iter()
+                .enumerate()
+                .rev()
+                .map(move |(index, version)| (key, version, index == latest))
+        })
+        .collect::<Vec<_>>();
+    let start = match (query.get("key-marker"), query.get("version-id-marker")) {
+        (Some(key_marker), Some(version_id_marker)) => entries
+            .iter()
+            .position(|(key, version, _)| *key == key_marker && &version.id == version_id_marker)
+            .map_or(0, |position| position + 1),
+        (Some(key_marker), None) => entries
+            .iter()
+            .position(|(key, _, _)| *key > key_marker)
+            .unwrap_or(entries.len()),
+        _ => 0,
+    };
+    let page = &entries[start..entries.len().min(start + state.page_size)];
+    let truncated = start + page.len() < entries.len();
+
+    let mut xml = format!(
+        "<ListVersionsResult><IsTruncated>{}</IsTruncated>",
+        truncated
+    );
+    for (key, version, is_latest) in page {
+        let element = match version.body {
+            Some(_) => "Version",
+            None => "DeleteMarker",
+        };
+        write!(
+            xml,
+            "<{element}><Key>{}</Key><VersionId>{}</VersionId><IsLatest>{}</IsLatest><LastModified>{}</LastModified>",
+            escape(key),
+            version.id,
+            is_latest,
+            version.modified.format("%Y-%m-%dT%H:%M:%S%.3fZ"),
+        )
+        .unwrap();
+        if let Some(body) = &version.body {
+            write!(
+                xml,
+                "<ETag>{}</ETag><Size>{}</Size><StorageClass>{}</StorageClass>",
+                escape(&version.e_tag()),
+                body.len(),
+                version.storage_class()
+            )
+            .unwrap();
+        }
+        write!(xml, "</{element}>").unwrap();
+    }
+    if let (true, Some((key, version, _)), false) =
+        (truncated, page.last(), state.omit_next_markers)
+    {
+        write!(
+            xml,
+            "<NextKeyMarker>{}</NextKeyMarker><NextVersionIdMarker>{}</NextVersionIdMarker>",
+            escape(key),
+            version.id
+        )
+        .unwrap();
+    }
+    xml + "</ListVersionsResult>"
+}
+
+fn object_response(
+    version: &Version,
+    headers: &HashMap<String, String>,
+    head: bool,
+) -> Response<Body> {
+    let body = version.body.clone().unwrap();
+    let (status, body) = match headers
+        .get("range")
+        .and_then(|range| parse_range(range, body.len()))
+    {
+        Some((start, end)) => (StatusCode::PARTIAL_CONTENT, body.slice(start..end)),
+        None => (StatusCode::OK, body),
+    };
+
+    let mut builder = Response::builder()
+        .status(status)
+        .header("content-length", body.len())
+        .header("etag", version.e_tag())
+        .header(
+            "last-modified",
+            version
+                .modified
+                .format("%a, %d %b %Y %H:%M:%S GMT")
+                .to_string(),
+        );
+    for (name, value) in &version.headers {
+        match name.as_str() {
+            "x-amz-tagging" => {
+                builder = builder.header("x-amz-tagging-count", version.tags().len())
+            }
+            _ => builder = builder.header(name, value),
+        }
+    }
+    let body = match head {
+        true => Body::empty(),
+        false => Body::from(body),
+    };
+    builder.body(body).unwrap()
+}
+
+/// `bytes=start-end`, inclusive, into a half-open range.
+fn parse_range(range: &str, len: usize) -> Option<(usize, usize)> {
+    let (start, end) = range.strip_prefix("bytes=")?.split_once('-')?;
+    let start = start.parse::<usize>().ok()?.min(len);
+    let end = match end {
+        "" => len,
+        end => (end.parse::<usize>().ok()? + 1).min(len),
+    };
+    Some((start, end.max(start)))
+}
+
+fn kept_headers(headers: &HashMap<String, String>) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .filter(|(name, _)| {
+            name.starts_with("x-amz-meta-") || KEPT_HEADERS.contains(&name.as_str())
+        })
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect()
+}
+
+fn response(status: StatusCode, body: impl Into<String>) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(body.into()))
+        .unwrap()
+}
+
+fn with_header(mut response: Response<Body>, name: &'static str, value: &str) -> Response<Body> {
+    response.headers_mut().insert(name, value.parse().unwrap());
+    response
+}
+
+fn with_version_id(response: Response<Body>, version_id: Option<String>) -> Response<Body> {
+    match version_id {
+        Some(version_id) => with_header(response, "x-amz-version-id", &version_id),
+        None => response,
+    }
+}
+
+fn error(status: StatusCode, code: &str) -> Response<Body> {
+    response(
+        status,
+        format!(
+            "<Error><Code>{}</Code><Message>{}</Message></Error>",
+            code, code
+        ),
+    )
+}
+
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((name, value)) => (percent_decode(name), percent_decode(value)),
+            None => (percent_decode(pair), String::new()),
+        })
+        .collect()
+}
+
+/// Tags of a `PutObjectTagging` body.
+fn parse_tag_set(xml: &str) -> BTreeMap<String, String> {
+    let element = |tag: &str, name: &str| {
+        let start = tag.find(&format!("<{}>", name))? + name.len() + 2;
+        let end = tag.find(&format!("</{}>", name))?;
+        Some(unescape(&tag[start..end]))
+    };
+    xml.split("<Tag>")
+        .skip(1)
+        .filter_map(|tag| Some((element(tag, "Key")?, element(tag, "Value")?)))
+        .collect()
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or_default();
+                match u8::from_str_radix(hex, 16) {
+                    Ok(byte) => {
+                        decoded.push(byte);
+                        i += 3;
+                        continue;
+                    }
+                    Err(_) => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Polls `condition` until it holds, for up to 10 seconds.
+pub fn eventually(condition: impl Fn() -> bool) -> bool {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while Instant::now() < deadline {
+        if condition() {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    condition()
+}
@@ -0,0 +1,44 @@
+mod common;
+
+use common::{local_files, TestEnv};
+
+#[test]
+fn keeps_the_local_file_when_a_download_fails() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("bucket");
+    let right = env.write_file("right.key", &[1; 32]);
+    let wrong = env.write_file("wrong.key", &[2; 32]);
+    env.write_file("upload/report.txt", b"quarterly report");
+    let upload = env.run(&[
+        "cp",
+        env.path("upload").to_str().unwrap(),
+        "mock:bucket",
+        "--cse-key-file",
+        right.to_str().unwrap(),
+    ]);
+    assert_eq!(upload.status.code(), Some(0));
+    let previous = env.write_file("download/report.txt", b"previous copy");
+
+    // A wrong key is rejected before writing, a corrupted body once read
+    let wrong_key = env.run(&[
+        "cp",
+        "mock:bucket",
+        env.path("download").to_str().unwrap(),
+        "--cse-key-file",
+        wrong.to_str().unwrap(),
+    ]);
+    assert_eq!(wrong_key.status.code(), Some(2));
+    env.s3.corrupt_object("bucket", "report.txt");
+    let output = env.run(&[
+        "cp",
+        "mock:bucket",
+        env.path("download").to_str().unwrap(),
+        "--cse-key-file",
+        right.to_str().unwrap(),
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(2), "{}", stdout);
+    assert_eq!(std::fs::read(previous).unwrap(), b"previous copy");
+    assert_eq!(local_files(&env.path("download")), ["report.txt"]);
+}
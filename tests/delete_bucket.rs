@@ -0,0 +1,82 @@
+mod common;
+
+use common::TestEnv;
+
+#[test]
+fn deletes_a_bucket_and_its_objects() {
+    let env = TestEnv::with_page_size(5);
+    env.s3.create_bucket("scratch");
+    for i in 0..12 {
+        env.s3
+            .put_object("scratch", &format!("a/{}.txt", i), "body");
+    }
+
+    let output = env.run(&["delete_bucket", "mock:scratch"]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(!env.s3.bucket_exists("scratch"));
+}
+
+#[test]
+fn deletes_every_version_of_a_versioned_bucket() {
+    let env = TestEnv::with_page_size(3);
+    env.s3.create_bucket("versioned");
+    env.s3.enable_versioning("versioned");
+    for i in 0..5 {
+        let key = format!("file-{}.txt", i);
+        env.s3.put_object("versioned", &key, "first");
+        env.s3.put_object("versioned", &key, "second");
+    }
+    env.s3.delete_object("versioned", "file-0.txt");
+    env.s3.delete_object("versioned", "file-3.txt");
+    assert_eq!(env.s3.version_count("versioned"), 12);
+
+    let output = env.run(&["delete_bucket", "mock:versioned"]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(!env.s3.bucket_exists("versioned"));
+}
+
+#[test]
+fn keeps_the_bucket_when_given_a_prefix() {
+    let env = TestEnv::with_page_size(2);
+    env.s3.create_bucket("versioned");
+    env.s3.enable_versioning("versioned");
+    for i in 0..4 {
+        env.s3
+            .put_object("versioned", &format!("tmp/{}.txt", i), "old");
+        env.s3
+            .put_object("versioned", &format!("tmp/{}.txt", i), "new");
+        env.s3
+            .put_object("versioned", &format!("keep/{}.txt", i), "keep");
+    }
+    env.s3.delete_object("versioned", "tmp/1.txt");
+
+    let output = env.run(&["delete_bucket", "mock:versioned/tmp"]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(env.s3.bucket_exists("versioned"));
+    assert_eq!(
+        env.s3.keys("versioned"),
+        ["keep/0.txt", "keep/1.txt", "keep/2.txt", "keep/3.txt"]
+    );
+    assert_eq!(env.s3.version_count("versioned"), 4);
+}
+
+#[test]
+fn reports_a_missing_bucket_in_exit_code() {
+    let env = TestEnv::new();
+
+    let output = env.run(&["delete_bucket", "mock:missing"]);
+
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn fails_for_an_unknown_remote() {
+    let env = TestEnv::new();
+
+    let output = env.run(&["delete_bucket", "unknown:bucket"]);
+
+    assert_eq!(output.status.code(), Some(1));
+}
@@ -0,0 +1,173 @@
+mod common;
+
+use common::TestEnv;
+
+#[test]
+fn reports_differences_between_buckets() {
+    let env = TestEnv::with_page_size(3);
+    env.s3.create_bucket("origin");
+    env.s3.create_bucket("target");
+    for i in 0..8 {
+        let key = format!("same/{}.txt", i);
+        env.s3.put_object("origin", &key, "same");
+        env.s3.put_object("target", &key, "same");
+    }
+    env.s3.put_object("origin", "origin-only.txt", "a");
+    env.s3.put_object("target", "target-only.txt", "b");
+    env.s3.put_object("origin", "size.txt", "short");
+    env.s3.put_object("target", "size.txt", "longer");
+    env.s3.put_object("origin", "content.txt", "abc");
+    env.s3.put_object("target", "content.txt", "xyz");
+
+    let output = env.run(&["diff", "mock:origin", "mock:target", "--format", "json"]);
+
+    assert_eq!(output.status.code(), Some(2));
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["matching"], 8);
+    assert_eq!(report["only_in_origin"], 1);
+    assert_eq!(report["only_in_target"], 1);
+    assert_eq!(report["different"], 2);
+    let statuses = report["differences"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| {
+            format!(
+                "{} {}",
+                entry["key"].as_str().unwrap(),
+                entry["status"].as_str().unwrap()
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        statuses,
+        [
+            "content.txt content_differs",
+            "origin-only.txt only_in_origin",
+            "size.txt size_differs",
+            "target-only.txt only_in_target",
+        ]
+    );
+}
+
+#[test]
+fn matches_a_bucket_with_a_local_directory() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("backups");
+    env.write_file("data/a/one.txt", b"one");
+    env.write_file("data/two.txt", b"two");
+    env.s3.put_object("backups", "site/a/one.txt", "one");
+    env.s3.put_object("backups", "site/two.txt", "two");
+
+    let output = env.run(&["diff", "data", "mock:backups/site", "--checksum"]);
+
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Done: 2 matching, 0 only in origin"));
+}
+
+#[test]
+fn compares_local_files_by_checksum() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("backups");
+    env.write_file("data/file.txt", b"local");
+    env.s3.put_object("backups", "file.txt", "bucket");
+    env.write_file("data/same-size.txt", b"aaaa");
+    env.s3.put_object("backups", "same-size.txt", "bbbb");
+
+    let output = env.run(&[
+        "diff",
+        "data",
+        "mock:backups",
+        "--checksum",
+        "--format",
+        "csv",
+    ]);
+
+    assert_eq!(output.status.code(), Some(2));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines = stdout.lines().collect::<Vec<_>>();
+    assert_eq!(
+        lines[0],
+        "key,status,origin_size,target_size,origin_etag,target_etag"
+    );
+    assert!(lines[1].starts_with("file.txt,size_differs,5,6,,"));
+    assert!(lines[2].starts_with("same-size.txt,content_differs,4,4,,"));
+    assert_eq!(lines.len(), 3);
+
+    let output = env.run(&["diff", "data", "mock:backups", "--format", "csv"]);
+
+    assert_eq!(output.status.code(), Some(2));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("\nsame-size.txt,unverified,4,4,,"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn does_not_take_the_etags_of_kms_objects_for_checksums() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("origin");
+    env.s3.create_bucket("target");
+    env.s3.put_kms_object("origin", "same.txt", "same");
+    env.s3.put_kms_object("target", "same.txt", "same");
+    env.s3.put_kms_object("origin", "content.txt", "abc");
+    env.s3.put_object("target", "content.txt", "xyz");
+
+    let output = env.run(&["diff", "mock:origin", "mock:target"]);
+
+    assert_eq!(output.status.code(), Some(2));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Unverified: content.txt"), "{}", stdout);
+    assert!(stdout.contains("Unverified: same.txt"), "{}", stdout);
+    assert!(
+        stdout.contains(
+            "Done: 0 matching, 0 only in origin, 0 only in target, 0 different, 2 unverified"
+        ),
+        "{}",
+        stdout
+    );
+
+    let output = env.run(&["diff", "mock:origin", "mock:target", "--checksum"]);
+
+    assert_eq!(output.status.code(), Some(2));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Content differs: content.txt"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains(
+            "Done: 1 matching, 0 only in origin, 0 only in target, 1 different, 0 unverified"
+        ),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn throttles_with_the_settings_of_the_remote() {
+    let env = TestEnv::new();
+    env.configure_remote("concurrency = 8");
+    env.s3.create_bucket("origin");
+    env.s3.create_bucket("target");
+    env.s3.put_object("origin", "a.txt", "a");
+    env.s3.put_object("target", "a.txt", "a");
+    env.s3.slow_down(1);
+
+    let output = env.run(&["diff", "mock:origin", "mock:target", "--format", "json"]);
+
+    assert_eq!(output.status.code(), Some(0));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Throttled by provider, concurrency reduced to 4"),
+        "{}",
+        stderr
+    );
+    // The report alone is on stdout
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["matching"], 1);
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use common::TestEnv;
+
+#[test]
+fn totals_prefixes_storage_classes_and_ages() {
+    let env = TestEnv::with_page_size(2);
+    env.s3.create_bucket("bucket");
+    env.s3
+        .put_object("bucket", "logs/2023/a.log", vec![0; 3000]);
+    env.s3
+        .put_object("bucket", "logs/2023/b.log", vec![0; 2000]);
+    env.s3.put_object("bucket", "logs/2024/c.log", vec![0; 100]);
+    env.s3.put_object("bucket", "images/x.png", vec![0; 10]);
+    env.s3.put_object("bucket", "readme.txt", vec![0; 1]);
+    env.s3
+        .set_storage_class("bucket", "logs/2023/a.log", "GLACIER");
+    env.s3.set_age("bucket", "logs/2023/a.log", 400);
+    env.s3.set_age("bucket", "logs/2023/b.log", 100);
+
+    let output = env.run(&["du", "mock:bucket", "--depth", "2"]);
+
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines = stdout.lines().map(str::trim).collect::<Vec<_>>();
+    assert_eq!(
+        lines,
+        [
+            "Prefixes:",
+            "5.0 KiB          5 objects  mock:bucket",
+            "5.0 KiB          3 objects    logs/",
+            "4.9 KiB          2 objects      2023/",
+            "100 B          1 objects      2024/",
+            "10 B          1 objects    images/",
+            "Storage classes:",
+            "2.9 KiB          1 objects  GLACIER",
+            "2.1 KiB          4 objects  STANDARD",
+            "Ages:",
+            "111 B          3 objects  0-30 days",
+            "2.0 KiB          1 objects  91-180 days",
+            "2.9 KiB          1 objects  over 1 year",
+            "Done: 5 objects (5111 bytes), 0 errors",
+        ]
+    );
+}
+
+#[test]
+fn stops_at_the_location_with_depth_zero() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("bucket");
+    env.s3.put_object("bucket", "data/a/1.txt", "1");
+    env.s3.put_object("bucket", "other/2.txt", "2");
+
+    let output = env.run(&["du", "mock:bucket/data", "--depth", "0"]);
+
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("1 objects  mock:bucket/data\nStorage classes:"));
+}
+
+#[test]
+fn counts_noncurrent_versions() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("bucket");
+    env.s3.enable_versioning("bucket");
+    env.s3.put_object("bucket", "file.txt", "12345");
+    env.s3.put_object("bucket", "file.txt", "123");
+    env.s3.delete_object("bucket", "file.txt");
+
+    let current = env.run(&["du", "mock:bucket"]);
+    let versions = env.run(&["du", "mock:bucket", "--versions"]);
+
+    assert!(String::from_utf8_lossy(&current.stdout).contains("Done: 0 objects (0 bytes)"));
+    assert!(String::from_utf8_lossy(&versions.stdout).contains("Done: 2 objects (8 bytes)"));
+}
@@ -0,0 +1,266 @@
+mod common;
+
+use common::{test_body, TestEnv};
+
+/// Just over one part, so the upload goes through multipart
+const MULTIPART_SIZE: usize = 20 * 1024 * 1024 + 4321;
+
+#[test]
+fn uploads_nested_directories() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("backups");
+    env.write_file("data/top.txt", b"top");
+    env.write_file("data/a/one.txt", b"one");
+    env.write_file("data/a/b/c/deep.txt", b"deep");
+    std::fs::create_dir_all(env.path("data/empty")).unwrap();
+
+    let output = env.run(&["f2b", "mock:backups/site", "--directory", "data"]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        env.s3.keys("backups"),
+        ["site/a/b/c/deep.txt", "site/a/one.txt", "site/top.txt"]
+    );
+    assert_eq!(
+        env.s3.object("backups", "site/a/b/c/deep.txt").unwrap(),
+        "deep"
+    );
+}
+
+#[test]
+fn flattens_nested_directories() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("backups");
+    env.write_file("data/a/one.txt", b"one");
+    env.write_file("data/a/b/two.txt", b"two");
+
+    let output = env.run(&[
+        "f2b",
+        "mock:backups",
+        "--directory",
+        "data",
+        "--flatten",
+        "true",
+    ]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(env.s3.keys("backups"), ["one.txt", "two.txt"]);
+}
+
+#[test]
+fn uploads_large_files_in_parts() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("backups");
+    let body = test_body(MULTIPART_SIZE);
+    env.write_file("data/large/archive.bin", &body);
+    env.write_file("data/small.txt", b"small");
+
+    let output = env.run(&["f2b", "mock:backups", "--directory", "data"]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(env.s3.completed_multipart_uploads(), 1);
+    assert_eq!(env.s3.keys("backups"), ["large/archive.bin", "small.txt"]);
+    assert!(env.s3.object("backups", "large/archive.bin").unwrap() == body);
+}
+
+#[test]
+fn fails_for_a_missing_directory() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("backups");
+
+    let output = env.run(&["f2b", "mock:backups", "--directory", "missing"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(env.s3.keys("backups").is_empty());
+}
+
+#[test]
+fn reports_failed_uploads_in_exit_code() {
+    let env = TestEnv::new();
+    env.write_file("data/file.txt", b"file");
+
+    let output = env.run(&["f2b", "mock:missing-bucket", "--directory", "data"]);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(!env.s3.bucket_exists("missing-bucket"));
+}
+
+#[cfg(unix)]
+fn write_non_utf8_file(env: &TestEnv) {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let name = OsStr::from_bytes(b"caf\xe9.txt");
+    std::fs::write(env.path("data").join(name), b"latin-1").unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn skips_file_names_that_are_not_utf8() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("backups");
+    env.write_file("data/ok.txt", b"ok");
+    write_non_utf8_file(&env);
+
+    let output = env.run(&["f2b", "mock:backups", "--directory", "data"]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(env.s3.keys("backups"), ["ok.txt"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("not valid UTF-8"));
+}
+
+#[cfg(unix)]
+#[test]
+fn percent_encodes_file_names_that_are_not_utf8() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("backups");
+    env.write_file("data/ok.txt", b"ok");
+    write_non_utf8_file(&env);
+
+    let output = env.run(&[
+        "f2b",
+        "mock:backups",
+        "--directory",
+        "data",
+        "--non-utf8-names",
+        "percent-encode",
+    ]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(env.s3.keys("backups"), ["caf%E9.txt", "ok.txt"]);
+    assert_eq!(env.s3.object("backups", "caf%E9.txt").unwrap(), "latin-1");
+}
+
+#[test]
+fn normalizes_keys_to_composed_characters() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("backups");
+    env.write_file("data/cafe\u{301}/menu.txt", b"menu");
+
+    let output = env.run(&[
+        "f2b",
+        "mock:backups",
+        "--directory",
+        "data",
+        "--normalize-keys",
+        "nfc",
+    ]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(env.s3.keys("backups"), ["caf\u{e9}/menu.txt"]);
+    assert_eq!(
+        env.s3.object("backups", "caf\u{e9}/menu.txt").unwrap(),
+        "menu"
+    );
+}
+
+#[test]
+fn skips_keys_longer_than_s3_allows() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("backups");
+    env.write_file("data/short.txt", b"short");
+    env.write_file(&format!("data/{}.txt", "x".repeat(200)), b"long");
+    let location = format!("mock:backups/{}", "p".repeat(900));
+
+    let output = env.run(&["f2b", &location, "--directory", "data"]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        env.s3.keys("backups"),
+        [format!("{}/short.txt", "p".repeat(900))]
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn skips_symbolic_links_unless_following_them() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("backups");
+    env.write_file("data/file.txt", b"file");
+    env.write_file("shared/linked.txt", b"linked");
+    std::os::unix::fs::symlink(env.path("shared"), env.path("data/shared")).unwrap();
+
+    let output = env.run(&["f2b", "mock:backups", "--directory", "data"]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(env.s3.keys("backups"), ["file.txt"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("symbolic link"));
+
+    let output = env.run(&[
+        "f2b",
+        "mock:backups",
+        "--directory",
+        "data",
+        "--follow-symlinks",
+    ]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(env.s3.keys("backups"), ["file.txt", "shared/linked.txt"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn reports_broken_symbolic_links_as_errors() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("backups");
+    env.write_file("data/file.txt", b"file");
+    std::os::unix::fs::symlink(env.path("missing"), env.path("data/broken")).unwrap();
+
+    let output = env.run(&[
+        "f2b",
+        "mock:backups",
+        "--directory",
+        "data",
+        "--follow-symlinks",
+    ]);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(env.s3.keys("backups"), ["file.txt"]);
+}
+
+#[test]
+fn leaves_out_hidden_and_ignored_files() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("backups");
+    env.write_file("data/keep.txt", b"keep");
+    env.write_file("data/.env", b"secret");
+    env.write_file("data/.gitignore", b"build/\n");
+    env.write_file("data/.s3ignore", b"*.log\n");
+    env.write_file("data/app.log", b"log");
+    env.write_file("data/build/out.bin", b"out");
+    env.write_file("data/src/.cache/entry", b"cache");
+    env.write_file("data/src/main.rs", b"main");
+
+    let output = env.run(&[
+        "f2b",
+        "mock:backups",
+        "--directory",
+        "data",
+        "--skip-hidden",
+        "--ignore-files",
+    ]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(env.s3.keys("backups"), ["keep.txt", "src/main.rs"]);
+}
+
+#[test]
+fn lists_files_down_to_the_maximum_depth() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("backups");
+    env.write_file("data/top.txt", b"top");
+    env.write_file("data/a/one.txt", b"one");
+    env.write_file("data/a/b/two.txt", b"two");
+
+    let output = env.run(&[
+        "f2b",
+        "mock:backups",
+        "--directory",
+        "data",
+        "--max-depth",
+        "2",
+    ]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(env.s3.keys("backups"), ["a/one.txt", "top.txt"]);
+}
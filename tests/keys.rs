@@ -0,0 +1,93 @@
+mod common;
+
+use common::TestEnv;
+
+#[test]
+fn renames_keys_copied_between_buckets() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("origin");
+    env.s3.create_bucket("target");
+    env.s3.put_object("origin", "old/Report.PDF", "report");
+    env.s3.put_object("origin", "other/Notes.txt", "notes");
+
+    let output = env.run(&[
+        "b2b",
+        "mock:origin",
+        "mock:target",
+        "--rename",
+        r"s/^old\//new\//",
+        "--key-case",
+        "lower",
+        "--target-prefix",
+        "/archive/",
+    ]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        env.s3.keys("target"),
+        ["archive/new/report.pdf", "archive/other/notes.txt"]
+    );
+}
+
+#[test]
+fn strips_a_prefix_and_renames_with_groups() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("backups");
+    env.write_file("data/2024/01/log-1.txt", b"one");
+    env.write_file("data/2024/02/log-2.txt", b"two");
+    env.write_file("data/other.txt", b"other");
+
+    let output = env.run(&[
+        "f2b",
+        "mock:backups/logs",
+        "--directory",
+        "data",
+        "--strip-prefix",
+        "2024",
+        "--rename",
+        r"s|^(\d+)/log-(\d+)|month-\1/\2|",
+    ]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        env.s3.keys("backups"),
+        [
+            "logs/month-01/1.txt",
+            "logs/month-02/2.txt",
+            "logs/other.txt"
+        ]
+    );
+}
+
+#[test]
+fn reports_files_flattened_to_the_same_key() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("backups");
+    env.write_file("data/a/same.txt", b"a");
+    env.write_file("data/b/same.txt", b"b");
+    env.write_file("data/b/other.txt", b"other");
+
+    let output = env.run(&[
+        "f2b",
+        "mock:backups",
+        "--directory",
+        "data",
+        "--flatten",
+        "true",
+    ]);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(env.s3.keys("backups"), ["other.txt", "same.txt"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("is also the target of"));
+}
+
+#[test]
+fn rejects_an_invalid_rename_rule() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("origin");
+    env.s3.create_bucket("target");
+
+    let output = env.run(&["b2b", "mock:origin", "mock:target", "--rename", "s/(/x/"]);
+
+    assert_eq!(output.status.code(), Some(1));
+}
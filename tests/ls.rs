@@ -0,0 +1,163 @@
+mod common;
+
+use common::TestEnv;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::record::Field;
+
+fn stdout(output: &std::process::Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn lists_objects_as_csv_across_pages() {
+    let env = TestEnv::with_page_size(3);
+    env.s3.create_bucket("bucket");
+    for i in 0..7 {
+        env.s3
+            .put_object("bucket", &format!("logs/{}.log", i), "log");
+    }
+    env.s3.put_object("bucket", "other.txt", "other");
+
+    let output = env.run(&["ls", "mock:bucket/logs", "--format", "csv"]);
+
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = stdout(&output);
+    let lines = stdout.lines().collect::<Vec<_>>();
+    assert_eq!(lines[0], "key,size,etag,last_modified,storage_class,owner");
+    assert_eq!(lines.len(), 8);
+    assert!(lines[1].starts_with("logs/0.log,3,\"\"\"")); // quoted ETag
+    assert!(lines.iter().all(|line| !line.contains("other.txt")));
+}
+
+#[test]
+fn lists_versions_as_json_lines() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("bucket");
+    env.s3.enable_versioning("bucket");
+    env.s3.put_object("bucket", "file.txt", "first");
+    env.s3.put_object("bucket", "file.txt", "second!");
+    env.s3.delete_object("bucket", "file.txt");
+
+    let output = env.run(&[
+        "inventory",
+        "mock:bucket",
+        "--versions",
+        "--format",
+        "jsonl",
+    ]);
+
+    assert_eq!(output.status.code(), Some(0));
+    let lines = stdout(&output)
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(lines.len(), 3);
+    let versions = lines
+        .iter()
+        .filter(|line| line["delete_marker"] == false)
+        .map(|line| line["size"].as_u64().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(versions.len(), 2);
+    assert!(versions.contains(&5) && versions.contains(&7));
+    let markers = lines
+        .iter()
+        .filter(|line| line["delete_marker"] == true)
+        .collect::<Vec<_>>();
+    assert_eq!(markers.len(), 1);
+    assert_eq!(markers[0]["is_latest"], true);
+}
+
+#[test]
+fn totals_common_prefixes_with_a_delimiter() {
+    let env = TestEnv::with_page_size(2);
+    env.s3.create_bucket("bucket");
+    env.s3.put_object("bucket", "data/top.txt", "12345");
+    env.s3.put_object("bucket", "data/a/1.txt", "1");
+    env.s3.put_object("bucket", "data/a/b/2.txt", "22");
+    env.s3.put_object("bucket", "data/c/3.txt", "333");
+
+    let output = env.run(&[
+        "ls",
+        "mock:bucket/data",
+        "--delimiter",
+        "/",
+        "--format",
+        "csv",
+    ]);
+
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = stdout(&output);
+    let lines = stdout.lines().collect::<Vec<_>>();
+    assert_eq!(
+        lines[0],
+        "key,size,etag,last_modified,storage_class,owner,objects"
+    );
+    assert!(lines[1].starts_with("data/top.txt,5,"));
+    assert!(lines[1].ends_with(",1"));
+    assert_eq!(lines[2], "data/a/,3,,,,,2");
+    assert_eq!(lines[3], "data/c/,3,,,,,1");
+    assert_eq!(lines.len(), 4);
+}
+
+#[test]
+fn writes_parquet_files() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("bucket");
+    for i in 0..5 {
+        env.s3
+            .put_object("bucket", &format!("{}.bin", i), vec![0; i]);
+    }
+    let path = env.path("inventory.parquet");
+
+    let output = env.run(&[
+        "ls",
+        "mock:bucket",
+        "--format",
+        "parquet",
+        "--output",
+        path.to_str().unwrap(),
+    ]);
+
+    assert_eq!(output.status.code(), Some(0));
+    let reader = SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+    assert_eq!(reader.metadata().file_metadata().num_rows(), 5);
+    let rows = reader
+        .get_row_iter(None)
+        .unwrap()
+        .map(|row| {
+            let row = row.unwrap();
+            let columns = row.get_column_iter().collect::<Vec<_>>();
+            assert_eq!(columns[0].0, "key");
+            assert_eq!(columns[1].0, "size");
+            match (columns[0].1, columns[1].1) {
+                (Field::Str(key), Field::Long(size)) => (key.clone(), *size),
+                other => panic!("unexpected fields {:?}", other),
+            }
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(rows[3], ("3.bin".to_owned(), 3));
+}
+
+#[test]
+fn stops_at_a_truncated_page_without_a_continuation_token() {
+    let env = TestEnv::with_page_size(2);
+    env.s3.create_bucket("bucket");
+    env.s3.enable_versioning("bucket");
+    for i in 0..5 {
+        env.s3.put_object("bucket", &format!("{}.log", i), "log");
+    }
+    env.s3.omit_next_markers();
+
+    let objects = env.run(&["ls", "mock:bucket", "--format", "csv"]);
+    let versions = env.run(&["ls", "mock:bucket", "--format", "csv", "--versions"]);
+
+    for (output, error) in [
+        (&objects, "Truncated listing without a continuation token"),
+        (&versions, "Truncated listing without a key marker"),
+    ] {
+        assert_eq!(output.status.code(), Some(2));
+        assert_eq!(stdout(output).lines().count(), 3);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains(error), "{}", stderr);
+    }
+}
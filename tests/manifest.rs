@@ -0,0 +1,141 @@
+mod common;
+
+use common::{local_files, TestEnv};
+
+#[test]
+fn copies_only_the_keys_of_a_manifest() {
+    let env = TestEnv::with_page_size(2);
+    env.s3.create_bucket("origin");
+    env.s3.create_bucket("target");
+    for i in 0..5 {
+        env.s3
+            .put_object("origin", &format!("{}.txt", i), format!("body {}", i));
+    }
+    let manifest = env.write_file(
+        "keys.csv",
+        b"key,size,target_key\n1.txt,6,\n\"3.txt\",6,renamed/three.txt\n",
+    );
+
+    let output = env.run(&[
+        "b2b",
+        "mock:origin",
+        "mock:target",
+        "--from-manifest",
+        manifest.to_str().unwrap(),
+    ]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        env.s3.keys("target"),
+        vec!["1.txt".to_owned(), "renamed/three.txt".to_owned()]
+    );
+    assert_eq!(
+        env.s3.object("target", "renamed/three.txt").unwrap(),
+        "body 3"
+    );
+}
+
+#[test]
+fn copies_the_versions_of_a_manifest() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("origin");
+    env.s3.enable_versioning("origin");
+    env.s3.create_bucket("target");
+    env.s3.put_object("origin", "file.txt", "first");
+    env.s3.put_object("origin", "file.txt", "second");
+    let first = env.s3.version_ids("origin", "file.txt")[0].clone();
+    let manifest = env.write_file(
+        "versions.jsonl",
+        format!(
+            "{{\"key\": \"file.txt\", \"version_id\": \"{}\", \"target_key\": \"file.v1.txt\"}}\n",
+            first
+        )
+        .as_bytes(),
+    );
+
+    let output = env.run(&[
+        "b2b",
+        "mock:origin",
+        "mock:target",
+        "--from-manifest",
+        manifest.to_str().unwrap(),
+    ]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(env.s3.object("target", "file.v1.txt").unwrap(), "first");
+}
+
+#[test]
+fn downloads_the_keys_of_a_manifest_and_reports_missing_ones() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("origin");
+    env.s3.put_object("origin", "data/a.txt", "a");
+    env.s3.put_object("origin", "data/b.txt", "b");
+    let manifest = env.write_file("keys.txt", b"data/b.txt\r\n\ndata/missing.txt\n");
+    let destination = env.path("download");
+
+    let output = env.run(&[
+        "cp",
+        "mock:origin/data",
+        destination.to_str().unwrap(),
+        "--from-manifest",
+        manifest.to_str().unwrap(),
+    ]);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(local_files(&destination), vec!["b.txt".to_owned()]);
+}
+
+#[test]
+fn refuses_to_sync_a_manifest() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("origin");
+    env.s3.put_object("origin", "a.txt", "a");
+    let manifest = env.write_file("keys.txt", b"a.txt\n");
+
+    let output = env.run(&[
+        "sync",
+        "mock:origin",
+        env.path("download").to_str().unwrap(),
+        "--from-manifest",
+        manifest.to_str().unwrap(),
+    ]);
+
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn deletes_the_versions_of_a_manifest_under_the_prefix() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("versioned");
+    env.s3.enable_versioning("versioned");
+    env.s3.put_object("versioned", "tmp/a.txt", "old");
+    env.s3.put_object("versioned", "tmp/a.txt", "new");
+    env.s3.put_object("versioned", "tmp/b.txt", "b");
+    env.s3.put_object("versioned", "keep/c.txt", "c");
+    let old = env.s3.version_ids("versioned", "tmp/a.txt")[0].clone();
+    let manifest = env.write_file(
+        "delete.csv",
+        format!(
+            "key,version_id\ntmp/a.txt,{}\ntmp/b.txt,\nkeep/c.txt,\n",
+            old
+        )
+        .as_bytes(),
+    );
+
+    let output = env.run(&[
+        "delete_bucket",
+        "mock:versioned/tmp",
+        "--from-manifest",
+        manifest.to_str().unwrap(),
+    ]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(env.s3.bucket_exists("versioned"));
+    assert_eq!(env.s3.object("versioned", "tmp/a.txt").unwrap(), "new");
+    assert_eq!(env.s3.version_ids("versioned", "tmp/a.txt").len(), 1);
+    assert_eq!(
+        env.s3.keys("versioned"),
+        vec!["keep/c.txt".to_owned(), "tmp/a.txt".to_owned()]
+    );
+}
@@ -0,0 +1,168 @@
+use bytes::Bytes;
+use chrono::Utc;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use s3_utils::store::PutOptions;
+use s3_utils::{
+    copy_versions_between, delete_from, restore_point_in_time, transfer_between, MemoryStore,
+    ObjectStore, PointInTimeOptions, StorePath, TransferOptions,
+};
+
+async fn store_with(objects: &[(&str, &str)]) -> MemoryStore {
+    let store = MemoryStore::new();
+    for (key, body) in objects {
+        store
+            .put(key, Bytes::from(body.to_string()), &PutOptions::default())
+            .await
+            .unwrap();
+    }
+    store
+}
+
+#[tokio::test]
+async fn transfers_between_memory_stores() {
+    let source = store_with(&[("docs/a.txt", "a"), ("docs/b.txt", "b")]).await;
+    source
+        .put(
+            "docs/meta.txt",
+            Bytes::from("meta"),
+            &PutOptions {
+                metadata: Some(HashMap::from([("owner".to_owned(), "ops".to_owned())])),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    let target = store_with(&[("backup/gone.txt", "gone")]).await;
+    let source_path = StorePath::new(source.clone(), "docs/");
+    let target_path = StorePath::new(target.clone(), "backup/");
+
+    let summary = transfer_between(&source_path, &target_path, &TransferOptions::default())
+        .await
+        .unwrap();
+
+    assert_eq!(summary.transferred, 3);
+    assert_eq!(summary.bytes, 6);
+    assert_eq!(
+        target.keys(),
+        [
+            "backup/a.txt",
+            "backup/b.txt",
+            "backup/gone.txt",
+            "backup/meta.txt"
+        ]
+    );
+    let head = target.head("backup/meta.txt").await.unwrap().unwrap();
+    assert_eq!(head.metadata["owner"], "ops");
+
+    // Synced with deletes, nothing is left to copy
+    let sync = TransferOptions {
+        sync: true,
+        delete: true,
+        ..Default::default()
+    };
+    let summary = transfer_between(&source_path, &target_path, &sync)
+        .await
+        .unwrap();
+
+    assert_eq!(summary.transferred, 1);
+    assert_eq!(summary.skipped, 3);
+    assert_eq!(
+        target.keys(),
+        ["backup/a.txt", "backup/b.txt", "backup/meta.txt"]
+    );
+}
+
+#[tokio::test]
+async fn deletes_from_a_memory_store() {
+    let store = store_with(&[("logs/a.log", "a"), ("logs/b.log", "b"), ("keep.txt", "k")]).await;
+
+    let summary = delete_from(
+        &StorePath::new(store.clone(), "logs/"),
+        false,
+        &TransferOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(summary.transferred, 2);
+    assert_eq!(store.keys(), ["keep.txt"]);
+
+    let summary = delete_from(
+        &StorePath::new(store.clone(), ""),
+        true,
+        &TransferOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(summary.failed, 0);
+    assert!(store.keys().is_empty());
+}
+
+#[tokio::test]
+async fn copies_the_only_version_of_memory_objects() {
+    let source = store_with(&[("a.txt", "a"), ("b.txt", "b")]).await;
+    let target = MemoryStore::new();
+    let directory = tempfile::tempdir().unwrap();
+    let mapping = directory.path().join("mapping.jsonl");
+
+    let summary = copy_versions_between(
+        &StorePath::new(source, ""),
+        &StorePath::new(target.clone(), "copy/"),
+        &mapping,
+        &TransferOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(summary.transferred, 2);
+    assert_eq!(target.keys(), ["copy/a.txt", "copy/b.txt"]);
+    assert_eq!(target.body("copy/b.txt"), Some(Bytes::from("b")));
+    let mapping = std::fs::read_to_string(mapping).unwrap();
+    let entries = mapping
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(entries.len(), 2);
+    assert!(entries
+        .iter()
+        .all(|entry| entry["version_id"] == "null" && entry["target_version_id"].is_null()));
+}
+
+#[tokio::test]
+async fn restores_memory_objects_as_they_were() {
+    let source = store_with(&[("a.txt", "a")]).await;
+    let at = Utc::now();
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    source
+        .put("b.txt", Bytes::from("b"), &PutOptions::default())
+        .await
+        .unwrap();
+    let target = store_with(&[("b.txt", "b, copied earlier")]).await;
+    let source_path = StorePath::new(source.clone(), "");
+    let restore = PointInTimeOptions { at, delete: true };
+
+    let summary = restore_point_in_time(
+        &source_path,
+        Some(&StorePath::new(target.clone(), "")),
+        &TransferOptions::default(),
+        &restore,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(summary.transferred, 2);
+    assert_eq!(target.keys(), ["a.txt"]);
+    assert_eq!(target.body("a.txt"), Some(Bytes::from("a")));
+
+    // In place, the object created later goes and the other one stays
+    let summary = restore_point_in_time(&source_path, None, &TransferOptions::default(), &restore)
+        .await
+        .unwrap();
+
+    assert_eq!(summary.transferred, 1);
+    assert_eq!(summary.skipped, 1);
+    assert_eq!(source.keys(), ["a.txt"]);
+}
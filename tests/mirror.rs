@@ -0,0 +1,161 @@
+mod common;
+
+use common::{local_files, TestEnv};
+
+#[test]
+fn mirrors_changes_since_the_last_poll() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("origin");
+    env.s3.create_bucket("replica");
+    env.s3.put_object("origin", "logs/a.txt", "a");
+    env.s3.put_object("origin", "logs/b.txt", "b");
+    let state = env.path("mirror.json");
+    let args = [
+        "mirror",
+        "mock:origin/logs",
+        "mock:replica/backup",
+        "--state",
+        state.to_str().unwrap(),
+        "--once",
+    ];
+
+    let output = env.run(&args);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(env.s3.keys("replica"), ["backup/a.txt", "backup/b.txt"]);
+    assert!(state.is_file());
+
+    env.s3.put_object("origin", "logs/b.txt", "b, modified");
+    env.s3.put_object("origin", "logs/c.txt", "c");
+    // Unchanged in the source, so left as it is
+    env.s3
+        .put_object("replica", "backup/a.txt", "a, edited in the replica");
+
+    let output = env.run(&args);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout.contains("Done: 2 transferred"), "{}", stdout);
+    assert_eq!(
+        env.s3.keys("replica"),
+        ["backup/a.txt", "backup/b.txt", "backup/c.txt"]
+    );
+    assert_eq!(
+        env.s3.object("replica", "backup/b.txt").as_deref(),
+        Some(&b"b, modified"[..])
+    );
+    assert_eq!(
+        env.s3.object("replica", "backup/a.txt").as_deref(),
+        Some(&b"a, edited in the replica"[..])
+    );
+}
+
+#[test]
+fn deletes_removed_objects_only_when_asked() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("origin");
+    env.s3.create_bucket("replica");
+    env.s3.put_object("origin", "a.txt", "a");
+    env.s3.put_object("origin", "b.txt", "b");
+    let state = env.path("mirror.json");
+    let state = state.to_str().unwrap();
+
+    let args = [
+        "mirror",
+        "mock:origin",
+        "mock:replica",
+        "--state",
+        state,
+        "--once",
+    ];
+    assert_eq!(env.run(&args).status.code(), Some(0));
+
+    env.s3.delete_object("origin", "a.txt");
+    assert_eq!(env.run(&args).status.code(), Some(0));
+    assert_eq!(env.s3.keys("replica"), ["a.txt", "b.txt"]);
+
+    // The state forgot a.txt, so it is no longer known to be removed
+    env.s3.delete_object("origin", "b.txt");
+    let output = env.run(&[
+        "mirror",
+        "mock:origin",
+        "mock:replica",
+        "--state",
+        state,
+        "--once",
+        "--delete",
+    ]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(env.s3.keys("replica"), ["a.txt"]);
+}
+
+#[test]
+fn mirrors_a_bucket_into_a_directory() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("origin");
+    env.s3.put_object("origin", "site/index.html", "index");
+    env.s3.put_object("origin", "site/css/main.css", "main");
+    env.write_file("replica/stale.txt", b"stale");
+
+    let output = env.run(&[
+        "mirror",
+        "mock:origin/site",
+        "replica",
+        "--once",
+        "--delete",
+    ]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        local_files(&env.path("replica")),
+        ["css/main.css", "index.html"]
+    );
+
+    let output = env.run(&["mirror", "mock:origin/site", "new/replica", "--once"]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        local_files(&env.path("new/replica")),
+        ["css/main.css", "index.html"]
+    );
+}
+
+#[test]
+fn fails_on_an_unreadable_state() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("origin");
+    env.s3.create_bucket("replica");
+    env.s3.put_object("origin", "a.txt", "a");
+    let state = env.write_file("mirror.json", b"not json");
+
+    let output = env.run(&[
+        "mirror",
+        "mock:origin",
+        "mock:replica",
+        "--state",
+        state.to_str().unwrap(),
+        "--once",
+    ]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(env.s3.keys("replica").is_empty());
+}
+
+#[test]
+fn throttles_with_the_settings_of_the_remote() {
+    let env = TestEnv::new();
+    env.configure_remote("concurrency = 8");
+    env.s3.create_bucket("origin");
+    env.s3.create_bucket("replica");
+    env.s3.put_object("origin", "a.txt", "a");
+    env.s3.slow_down(1);
+
+    let output = env.run(&["mirror", "mock:origin", "mock:replica", "--once"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(
+        stdout.contains("Throttled by provider, concurrency reduced to 4"),
+        "{}",
+        stdout
+    );
+    assert_eq!(env.s3.keys("replica"), ["a.txt"]);
+}
@@ -0,0 +1,198 @@
+mod common;
+
+use bytes::Bytes;
+use chrono::Utc;
+use common::{local_files, TestEnv};
+
+/// A versioned bucket where, five days ago, `a.txt` and `b.txt` were at
+/// their first version and `c.txt` did not exist.
+fn history(env: &TestEnv) -> String {
+    env.s3.create_bucket("bucket");
+    env.s3.enable_versioning("bucket");
+    env.s3.put_object("bucket", "docs/a.txt", "a, first");
+    env.s3.put_object("bucket", "docs/a.txt", "a, second");
+    env.s3.set_version_ages("bucket", "docs/a.txt", &[10, 2]);
+    env.s3.put_object("bucket", "docs/b.txt", "b");
+    env.s3.delete_object("bucket", "docs/b.txt");
+    env.s3.set_version_ages("bucket", "docs/b.txt", &[10, 2]);
+    env.s3.put_object("bucket", "docs/c.txt", "c");
+    env.s3.set_version_ages("bucket", "docs/c.txt", &[1]);
+    (Utc::now() - chrono::Duration::days(5)).to_rfc3339()
+}
+
+#[test]
+fn restores_a_prefix_in_place() {
+    let env = TestEnv::new();
+    let at = history(&env);
+
+    let output = env.run(&["restore", "mock:bucket/docs", "--at", &at, "--delete"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout.contains("Done: 3 transferred"), "{}", stdout);
+    assert_eq!(env.s3.keys("bucket"), ["docs/a.txt", "docs/b.txt"]);
+    assert_eq!(
+        env.s3.object("bucket", "docs/a.txt"),
+        Some(Bytes::from("a, first"))
+    );
+    assert_eq!(
+        env.s3.object("bucket", "docs/b.txt"),
+        Some(Bytes::from("b"))
+    );
+    // Later versions are kept
+    assert_eq!(env.s3.version_ids("bucket", "docs/a.txt").len(), 3);
+    assert_eq!(env.s3.version_ids("bucket", "docs/c.txt").len(), 2);
+    // Without being downloaded
+    assert_eq!(env.s3.copied_objects(), 2);
+
+    // Already as it was then
+    let output = env.run(&["restore", "mock:bucket/docs", "--at", &at, "--delete"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Done: 0 transferred"), "{}", stdout);
+}
+
+#[test]
+fn restores_into_a_directory() {
+    let env = TestEnv::new();
+    let at = history(&env);
+
+    let output = env.run(&[
+        "restore",
+        "mock:bucket/docs",
+        "--at",
+        &at,
+        "--to",
+        "recovered",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout.contains("Skipping object docs/c.txt"), "{}", stdout);
+    assert_eq!(local_files(&env.path("recovered")), ["a.txt", "b.txt"]);
+    assert_eq!(
+        std::fs::read(env.path("recovered/a.txt")).unwrap(),
+        b"a, first"
+    );
+    // The bucket is left as it is
+    assert_eq!(env.s3.keys("bucket"), ["docs/a.txt", "docs/c.txt"]);
+}
+
+#[test]
+fn restores_into_another_bucket() {
+    let env = TestEnv::new();
+    let at = history(&env);
+    env.s3.create_bucket("recovered");
+    env.s3.put_object("recovered", "c.txt", "c, copied earlier");
+
+    let output = env.run(&[
+        "restore",
+        "mock:bucket/docs",
+        "--at",
+        &at,
+        "--to",
+        "mock:recovered",
+        "--delete",
+    ]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(env.s3.keys("recovered"), ["a.txt", "b.txt"]);
+}
+
+#[test]
+fn restores_into_another_prefix_of_the_bucket() {
+    let env = TestEnv::new();
+    let at = history(&env);
+
+    let output = env.run(&[
+        "restore",
+        "mock:bucket/docs",
+        "--at",
+        &at,
+        "--to",
+        "mock:bucket/recovered",
+    ]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        env.s3.object("bucket", "recovered/a.txt"),
+        Some(Bytes::from("a, first"))
+    );
+    assert_eq!(
+        env.s3.object("bucket", "recovered/b.txt"),
+        Some(Bytes::from("b"))
+    );
+    assert_eq!(env.s3.copied_objects(), 2);
+}
+
+#[test]
+fn refuses_to_restore_in_place_with_versioning_suspended() {
+    let env = TestEnv::new();
+    let at = history(&env);
+    env.s3.suspend_versioning("bucket");
+
+    let output = env.run(&["restore", "mock:bucket/docs", "--at", &at, "--delete"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout.contains("Versioning is suspended"), "{}", stdout);
+    assert_eq!(env.s3.version_ids("bucket", "docs/a.txt").len(), 2);
+    assert_eq!(env.s3.version_ids("bucket", "docs/c.txt").len(), 1);
+}
+
+#[test]
+fn restores_an_s3_url_in_place() {
+    let env = TestEnv::new();
+    let at = history(&env);
+    let endpoint = env.s3.endpoint();
+
+    let output = env.run(&[
+        "restore",
+        "s3://bucket/docs",
+        "--at",
+        &at,
+        "--endpoint",
+        &endpoint,
+        "--provider",
+        "minio",
+        "--region",
+        "us-east-1",
+        "--aws-access-key-id",
+        "test",
+        "--aws-secret-access-key",
+        "test",
+    ]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        env.s3.object("bucket", "docs/a.txt"),
+        Some(Bytes::from("a, first"))
+    );
+}
+
+#[test]
+fn refuses_a_local_directory() {
+    let env = TestEnv::new();
+    env.write_file("docs/a.txt", b"a");
+
+    let output = env.run(&["restore", "docs", "--at", "2024-05-01"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        stdout.contains("restore needs a versioned bucket, not the local directory docs"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn refuses_a_bucket_without_versioning() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("bucket");
+    env.s3.put_object("bucket", "a.txt", "a");
+
+    let output = env.run(&["restore", "mock:bucket", "--at", "2024-05-01"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(env.s3.object("bucket", "a.txt"), Some(Bytes::from("a")));
+}
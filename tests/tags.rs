@@ -0,0 +1,159 @@
+mod common;
+
+use common::{test_body, TestEnv};
+use std::collections::BTreeMap;
+
+fn tags(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+    pairs
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+#[test]
+fn tags_uploads() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("backups");
+    env.write_file("data/small.txt", b"small");
+    env.write_file("data/large.bin", &test_body(20 * 1024 * 1024 + 1));
+
+    let output = env.run(&[
+        "f2b",
+        "mock:backups",
+        "--directory",
+        "data",
+        "--tag",
+        "team=web & mobile",
+        "--tag",
+        "env=prod",
+    ]);
+
+    assert_eq!(output.status.code(), Some(0));
+    let expected = tags(&[("env", "prod"), ("team", "web & mobile")]);
+    assert_eq!(env.s3.tags("backups", "small.txt"), expected);
+    assert_eq!(env.s3.tags("backups", "large.bin"), expected);
+
+    let output = env.run(&["f2b", "mock:backups", "--directory", "data", "--tag", "env"]);
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn copies_tags_and_filters_by_them() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("origin");
+    env.s3.create_bucket("target");
+    env.s3.put_object("origin", "a.txt", "a");
+    env.s3
+        .set_tags("origin", "a.txt", &[("keep", "yes"), ("owner", "ops")]);
+    env.s3.put_object("origin", "b.txt", "b");
+    env.s3.put_object("origin", "c.txt", "c");
+    env.s3.set_tags("origin", "c.txt", &[("keep", "no")]);
+
+    let output = env.run(&[
+        "b2b",
+        "mock:origin",
+        "mock:target",
+        "--where-tag",
+        "keep=yes",
+    ]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(env.s3.keys("target"), ["a.txt"]);
+    assert_eq!(
+        env.s3.tags("target", "a.txt"),
+        tags(&[("keep", "yes"), ("owner", "ops")])
+    );
+
+    let output = env.run(&["b2b", "mock:origin", "mock:target"]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(env.s3.keys("target"), ["a.txt", "b.txt", "c.txt"]);
+    assert!(env.s3.tags("target", "b.txt").is_empty());
+    assert_eq!(env.s3.tags("target", "c.txt"), tags(&[("keep", "no")]));
+}
+
+#[test]
+fn changes_tags_of_matching_objects() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("assets");
+    for key in ["logs/a.log", "logs/b.log", "logs/c.log", "other.txt"] {
+        env.s3.put_object("assets", key, "body");
+    }
+    env.s3
+        .set_tags("assets", "logs/a.log", &[("stage", "raw"), ("temp", "1")]);
+    env.s3
+        .set_tags("assets", "logs/b.log", &[("stage", "done")]);
+
+    let output = env.run(&[
+        "tag",
+        "mock:assets/logs",
+        "--tag",
+        "stage=archived",
+        "--remove-tag",
+        "temp",
+        "--where-tag",
+        "stage=raw",
+    ]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        env.s3.tags("assets", "logs/a.log"),
+        tags(&[("stage", "archived")])
+    );
+    assert_eq!(
+        env.s3.tags("assets", "logs/b.log"),
+        tags(&[("stage", "done")])
+    );
+    assert!(env.s3.tags("assets", "logs/c.log").is_empty());
+
+    let output = env.run(&["tag", "mock:assets/logs", "--tag", "project=site"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout.contains("Done: 3 transferred"), "{}", stdout);
+    assert_eq!(
+        env.s3.tags("assets", "logs/b.log"),
+        tags(&[("project", "site"), ("stage", "done")])
+    );
+
+    // Already tagged
+    let output = env.run(&["tag", "mock:assets/logs", "--tag", "project=site"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Done: 0 transferred (0 bytes), 3 skipped"),
+        "{}",
+        stdout
+    );
+
+    let output = env.run(&["tag", "mock:assets", "--replace", "--tag", "only=this"]);
+    assert_eq!(output.status.code(), Some(0));
+    for key in ["logs/a.log", "logs/b.log", "logs/c.log", "other.txt"] {
+        assert_eq!(env.s3.tags("assets", key), tags(&[("only", "this")]));
+    }
+
+    let output = env.run(&["tag", "mock:assets"]);
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn deletes_only_tagged_objects() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("scratch");
+    env.s3.put_object("scratch", "expired.txt", "old");
+    env.s3
+        .set_tags("scratch", "expired.txt", &[("expired", "true")]);
+    env.s3.put_object("scratch", "kept.txt", "new");
+    env.s3
+        .set_tags("scratch", "kept.txt", &[("expired", "false")]);
+    env.s3.put_object("scratch", "untagged.txt", "new");
+
+    let output = env.run(&[
+        "delete_bucket",
+        "mock:scratch",
+        "--where-tag",
+        "expired=true",
+    ]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(env.s3.bucket_exists("scratch"));
+    assert_eq!(env.s3.keys("scratch"), ["kept.txt", "untagged.txt"]);
+}
@@ -0,0 +1,159 @@
+use bytes::Bytes;
+use hyper::{HeaderMap, StatusCode};
+use rusoto_core::request::BufferedHttpResponse;
+use rusoto_core::RusotoError;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+
+use s3_utils::throttle::{is_throttling_error, Throttle};
+use s3_utils::{EventHandler, TransferEvent};
+
+#[derive(Debug)]
+struct ServiceError(&'static str);
+
+impl fmt::Display for ServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+fn response(status: u16, body: &'static str) -> RusotoError<ServiceError> {
+    RusotoError::Unknown(BufferedHttpResponse {
+        status: StatusCode::from_u16(status).unwrap(),
+        body: Bytes::from(body),
+        headers: HeaderMap::default(),
+    })
+}
+
+fn slow_down() -> RusotoError<ServiceError> {
+    response(503, "<Error><Code>SlowDown</Code></Error>")
+}
+
+/// Throttle reporting the concurrency it falls back to.
+fn throttle(concurrency: usize) -> (Throttle, Arc<Mutex<Vec<usize>>>) {
+    let reductions = Arc::new(Mutex::new(Vec::new()));
+    let events = {
+        let reductions = reductions.clone();
+        EventHandler::new(move |event| {
+            if let TransferEvent::Throttled { concurrency } = event {
+                reductions.lock().unwrap().push(*concurrency);
+            }
+        })
+    };
+    (
+        Throttle::new(concurrency, None).with_events(events),
+        reductions,
+    )
+}
+
+/// Runs a request that is throttled `failures` times before it succeeds.
+async fn run_throttled(throttle: &Throttle, failures: usize) {
+    let attempts = AtomicUsize::new(0);
+    throttle
+        .run(|| async {
+            match attempts.fetch_add(1, Ordering::SeqCst) < failures {
+                true => Err(slow_down()),
+                false => Ok(()),
+            }
+        })
+        .await
+        .unwrap();
+}
+
+async fn run_successes(throttle: &Throttle, count: usize) {
+    for _ in 0..count {
+        throttle
+            .run(|| async { Ok::<_, RusotoError<ServiceError>>(()) })
+            .await
+            .unwrap();
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn halves_the_concurrency_when_throttled() {
+    let (throttle, reductions) = throttle(8);
+
+    run_throttled(&throttle, 1).await;
+
+    assert_eq!(throttle.concurrency(), 4);
+    assert_eq!(*reductions.lock().unwrap(), [4]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn halves_once_per_second_of_throttling() {
+    let (throttle, reductions) = throttle(8);
+
+    // Retried after 200ms, 400ms and 800ms: only the first and last
+    // failures are a second apart
+    run_throttled(&throttle, 4).await;
+
+    assert_eq!(throttle.concurrency(), 2);
+    assert_eq!(*reductions.lock().unwrap(), [4, 2]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn grows_back_by_one_per_window_of_successes() {
+    let (throttle, _) = throttle(8);
+    run_throttled(&throttle, 1).await;
+
+    // The retry that went through counts towards the first window
+    run_successes(&throttle, 2).await;
+    assert_eq!(throttle.concurrency(), 4);
+    run_successes(&throttle, 1).await;
+    assert_eq!(throttle.concurrency(), 5);
+    run_successes(&throttle, 5).await;
+    assert_eq!(throttle.concurrency(), 6);
+    run_successes(&throttle, 100).await;
+    assert_eq!(throttle.concurrency(), 8);
+}
+
+#[tokio::test(start_paused = true)]
+async fn gives_up_after_repeated_throttling() {
+    let (throttle, _) = throttle(8);
+
+    let result = throttle.run(|| async { Err::<(), _>(slow_down()) }).await;
+
+    assert!(result.is_err());
+    assert_eq!(throttle.concurrency(), 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn caps_the_requests_per_second() {
+    let throttle = Throttle::new(4, Some(10.0));
+    let start = Instant::now();
+
+    run_successes(&throttle, 5).await;
+
+    assert!(start.elapsed() >= Duration::from_millis(400));
+    assert!(start.elapsed() < Duration::from_millis(500));
+}
+
+#[test]
+fn recognizes_throttling_errors() {
+    assert!(is_throttling_error(&response(503, "")));
+    assert!(is_throttling_error(&response(429, "")));
+    assert!(is_throttling_error(&response(
+        400,
+        "<Error><Code>RequestLimitExceeded</Code></Error>"
+    )));
+    assert!(is_throttling_error(&RusotoError::Service(ServiceError(
+        "SlowDown: Please reduce your request rate."
+    ))));
+    assert!(is_throttling_error(&RusotoError::Service(ServiceError(
+        "Throttling: Rate exceeded"
+    ))));
+
+    assert!(!is_throttling_error(&response(
+        404,
+        "<Error><Code>NoSuchKey</Code></Error>"
+    )));
+    assert!(!is_throttling_error(&RusotoError::Service(ServiceError(
+        "NoSuchKey: The specified key does not exist."
+    ))));
+    assert!(!is_throttling_error::<ServiceError>(
+        &RusotoError::Validation("invalid bucket name".to_owned())
+    ));
+}
@@ -0,0 +1,159 @@
+mod common;
+
+use chrono::Utc;
+use common::TestEnv;
+use std::fs::File;
+use std::time::{Duration, SystemTime};
+
+#[test]
+fn purges_the_objects_older_than_an_age() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("logs");
+    env.s3.enable_versioning("logs");
+    env.s3.put_object("logs", "old.log", "old");
+    env.s3.set_age("logs", "old.log", 40);
+    env.s3.put_object("logs", "rewritten.log", "first");
+    env.s3.put_object("logs", "rewritten.log", "second");
+    env.s3.set_version_ages("logs", "rewritten.log", &[60, 3]);
+    env.s3.put_object("logs", "new.log", "new");
+
+    let output = env.run(&[
+        "delete_bucket",
+        "mock:logs",
+        "--older-than",
+        "30d",
+        "--keep-bucket",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(
+        stdout.contains("Done: 2 transferred (0 bytes), 0 skipped, 0 failed"),
+        "{}",
+        stdout
+    );
+    assert!(env.s3.bucket_exists("logs"));
+    assert_eq!(env.s3.keys("logs"), ["new.log", "rewritten.log"]);
+    assert_eq!(env.s3.version_ids("logs", "rewritten.log").len(), 1);
+}
+
+#[test]
+fn keeps_the_delete_markers_in_the_window() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("logs");
+    env.s3.enable_versioning("logs");
+    env.s3.put_object("logs", "gone.log", "gone");
+    env.s3.delete_object("logs", "gone.log");
+    env.s3.set_version_ages("logs", "gone.log", &[10, 2]);
+
+    let output = env.run(&[
+        "delete_bucket",
+        "mock:logs",
+        "--newer-than",
+        "7d",
+        "--keep-bucket",
+    ]);
+
+    assert_eq!(output.status.code(), Some(0));
+    // Still deleted, rather than back to its older version
+    assert!(env.s3.keys("logs").is_empty());
+    assert_eq!(env.s3.version_ids("logs", "gone.log").len(), 2);
+}
+
+#[test]
+fn keeps_an_emptied_bucket() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("scratch");
+    env.s3.put_object("scratch", "a.txt", "a");
+
+    let output = env.run(&["delete_bucket", "mock:scratch", "--keep-bucket"]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(env.s3.bucket_exists("scratch"));
+    assert!(env.s3.keys("scratch").is_empty());
+}
+
+#[test]
+fn copies_the_objects_modified_in_a_window() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("origin");
+    env.s3.create_bucket("replica");
+    for (key, days) in [("recent.txt", 2), ("older.txt", 10), ("oldest.txt", 90)] {
+        env.s3.put_object("origin", key, key);
+        env.s3.set_age("origin", key, days);
+    }
+
+    let output = env.run(&["b2b", "mock:origin", "mock:replica", "--newer-than", "7d"]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(env.s3.keys("replica"), ["recent.txt"]);
+
+    let after = (Utc::now() - chrono::Duration::days(30)).to_rfc3339();
+    let output = env.run(&[
+        "b2b",
+        "mock:origin",
+        "mock:replica",
+        "--modified-after",
+        &after,
+        "--older-than",
+        "1w",
+    ]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(env.s3.keys("replica"), ["older.txt", "recent.txt"]);
+}
+
+#[test]
+fn uploads_the_files_modified_before_a_date() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("backups");
+    let archived = env.write_file("data/archived.txt", b"archived");
+    env.write_file("data/current.txt", b"current");
+    let three_days_ago = SystemTime::now() - Duration::from_secs(3 * 24 * 3600);
+    File::options()
+        .write(true)
+        .open(archived)
+        .unwrap()
+        .set_modified(three_days_ago)
+        .unwrap();
+
+    let before = (Utc::now() - chrono::Duration::days(1))
+        .format("%Y-%m-%d")
+        .to_string();
+    let output = env.run(&[
+        "f2b",
+        "mock:backups",
+        "--directory",
+        "data",
+        "--modified-before",
+        &before,
+    ]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(env.s3.keys("backups"), ["archived.txt"]);
+}
+
+#[test]
+fn refuses_an_empty_window() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("origin");
+    env.s3.put_object("origin", "a.txt", "a");
+
+    let output = env.run(&[
+        "b2b",
+        "mock:origin",
+        "mock:replica",
+        "--newer-than",
+        "7d",
+        "--older-than",
+        "30d",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        stdout.contains("No object can be modified both after"),
+        "{}",
+        stdout
+    );
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use common::{eventually, TestEnv};
+
+#[test]
+fn uploads_changes_until_interrupted() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("drop");
+    env.write_file("data/first.txt", b"first");
+
+    let mut child = env.spawn(&[
+        "f2b",
+        "mock:drop/inbox",
+        "--directory",
+        "data",
+        "--watch",
+        "--delete",
+        "--debounce-ms",
+        "100",
+    ]);
+    let uploaded = eventually(|| env.s3.keys("drop") == ["inbox/first.txt"]);
+
+    env.write_file("data/nested/second.txt", b"second");
+    let created = eventually(|| env.s3.object("drop", "inbox/nested/second.txt").is_some());
+
+    env.write_file("data/first.txt", b"first, modified");
+    let modified = eventually(|| {
+        env.s3.object("drop", "inbox/first.txt").as_deref() == Some(&b"first, modified"[..])
+    });
+
+    std::fs::remove_file(env.path("data/nested/second.txt")).unwrap();
+    let deleted = eventually(|| env.s3.keys("drop") == ["inbox/first.txt"]);
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+    assert!(uploaded && created && modified && deleted);
+}
+
+#[test]
+fn syncs_deletions_of_a_directory() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("mirror");
+    env.s3.put_object("mirror", "site/stale.txt", "stale");
+    env.s3.put_object("mirror", "other/kept.txt", "kept");
+    env.write_file("data/index.html", b"index");
+
+    let output = env.run(&["sync", "data", "mock:mirror/site", "--delete"]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(env.s3.keys("mirror"), ["other/kept.txt", "site/index.html"]);
+}
+
+#[test]
+fn refuses_to_delete_without_syncing() {
+    let env = TestEnv::new();
+    env.s3.create_bucket("mirror");
+    env.s3.put_object("mirror", "stale.txt", "stale");
+    env.write_file("data/index.html", b"index");
+
+    let output = env.run(&["cp", "data", "mock:mirror", "--delete"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(env.s3.keys("mirror"), ["stale.txt"]);
+}